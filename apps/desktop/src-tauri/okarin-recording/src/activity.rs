@@ -0,0 +1,232 @@
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::dsp::linear_to_db;
+use super::types::*;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// VAD analysis frame (20ms at 48kHz)
+const VAD_FRAME_FRAMES: usize = 960;
+const VAD_FRAME_SECONDS: f64 = VAD_FRAME_FRAMES as f64 / OPUS_SAMPLE_RATE as f64;
+/// Frames below this are digital silence (gap fill, DTX) and say nothing about the room
+const DIGITAL_SILENCE_DB: f64 = -90.0;
+/// Percentile of the frame levels taken as the track's noise floor
+const NOISE_FLOOR_PERCENTILE: f64 = 0.1;
+/// Speech must stand this far above the noise floor
+const SPEECH_MARGIN_DB: f64 = 12.0;
+/// Bounds of the speech threshold, whatever the noise floor says
+const MIN_SPEECH_THRESHOLD_DB: f64 = -50.0;
+const MAX_SPEECH_THRESHOLD_DB: f64 = -35.0;
+/// Pauses shorter than this are part of the same utterance
+const HANGOVER_SECONDS: f64 = 0.3;
+/// Bursts shorter than this are clicks and bumps, not speech
+const MIN_SPEECH_SECONDS: f64 = 0.15;
+
+/// Speech intervals of one track before they are placed on the session timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackActivity {
+    pub speech: Vec<TimeRange>,
+    pub threshold_db: f64,
+    pub duration_seconds: f64,
+}
+
+/// Derive the speech threshold from the per-frame levels of a track
+fn speech_threshold(levels_db: &[f64]) -> f64 {
+    let mut sorted: Vec<f64> = levels_db
+        .iter()
+        .copied()
+        .filter(|&db| db > DIGITAL_SILENCE_DB)
+        .collect();
+    if sorted.is_empty() {
+        return MAX_SPEECH_THRESHOLD_DB;
+    }
+    sorted.sort_by(f64::total_cmp);
+
+    let noise_floor = sorted[((sorted.len() - 1) as f64 * NOISE_FLOOR_PERCENTILE) as usize];
+    (noise_floor + SPEECH_MARGIN_DB).clamp(MIN_SPEECH_THRESHOLD_DB, MAX_SPEECH_THRESHOLD_DB)
+}
+
+/// Turn per-frame levels into speech intervals, with hangover and minimum length
+fn speech_ranges(levels_db: &[f64], threshold_db: f64) -> Vec<TimeRange> {
+    let mut ranges: Vec<TimeRange> = Vec::new();
+    let mut start = None;
+
+    for (index, &db) in levels_db
+        .iter()
+        .chain(std::iter::once(&f64::MIN))
+        .enumerate()
+    {
+        match (db > threshold_db, start) {
+            (true, None) => start = Some(index),
+            (false, Some(first)) => {
+                let range = TimeRange {
+                    start_seconds: first as f64 * VAD_FRAME_SECONDS,
+                    end_seconds: index as f64 * VAD_FRAME_SECONDS,
+                };
+                match ranges.last_mut() {
+                    Some(last) if range.start_seconds - last.end_seconds < HANGOVER_SECONDS => {
+                        last.end_seconds = range.end_seconds;
+                    }
+                    _ => ranges.push(range),
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    ranges.retain(|r| r.duration_seconds() >= MIN_SPEECH_SECONDS);
+    ranges
+}
+
+/// Run the VAD over a recorded audio file, timestamps relative to the file
+pub fn analyze_file(path: &Path) -> RecordingResult<TrackActivity> {
+    let decoder = OpusTrackDecoder::open(path)?;
+    let channels = decoder.channels().max(1) as usize;
+    let mut reader = TimelineReader::new(decoder, 0.0);
+
+    let mut levels_db = Vec::new();
+    let mut frames = 0usize;
+    while let Some(block) = reader.read(VAD_FRAME_FRAMES)? {
+        let mean_square =
+            block.iter().map(|&s| (s as f64) * (s as f64)).sum::<f64>() / block.len() as f64;
+        levels_db.push(if mean_square > 0.0 {
+            linear_to_db(mean_square.sqrt())
+        } else {
+            f64::MIN
+        });
+        frames += block.len() / channels;
+    }
+
+    let threshold_db = speech_threshold(&levels_db);
+    Ok(TrackActivity {
+        speech: speech_ranges(&levels_db, threshold_db),
+        threshold_db,
+        duration_seconds: frames as f64 / OPUS_SAMPLE_RATE as f64,
+    })
+}
+
+/// Build the speech activity timeline of every participant of a recording
+pub fn analyze_recording(metadata: &RecordingMetadata) -> RecordingResult<ActivityTimeline> {
+    let mut tracks = Vec::new();
+    let mut duration_seconds = metadata.duration_seconds as f64;
+
+    for (participant_id, participant) in &metadata.participants {
+        if let Some(path) = &participant.audio_file {
+            log::info!(
+                "Detecting speech activity for participant: {}",
+                participant_id
+            );
+            let offset = metadata.participant_offset_seconds(participant);
+            let activity = analyze_file(path)?;
+            duration_seconds = duration_seconds.max(offset + activity.duration_seconds);
+            tracks.push((participant_id.clone(), offset, activity));
+        }
+    }
+
+    let participants: HashMap<String, ParticipantActivity> = tracks
+        .into_iter()
+        .map(|(participant_id, offset, activity)| {
+            let speech: Vec<TimeRange> = activity
+                .speech
+                .iter()
+                .map(|r| TimeRange {
+                    start_seconds: r.start_seconds + offset,
+                    end_seconds: r.end_seconds + offset,
+                })
+                .collect();
+            let talk_seconds: f64 = speech.iter().map(TimeRange::duration_seconds).sum();
+            let talk_time_percent = if duration_seconds > 0.0 {
+                100.0 * talk_seconds / duration_seconds
+            } else {
+                0.0
+            };
+
+            let activity = ParticipantActivity {
+                speech,
+                talk_seconds,
+                talk_time_percent,
+                threshold_db: activity.threshold_db,
+            };
+            (participant_id, activity)
+        })
+        .collect();
+
+    Ok(ActivityTimeline {
+        analyzed_at: Utc::now(),
+        duration_seconds,
+        participants,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    fn levels(pattern: &[(f64, usize)]) -> Vec<f64> {
+        pattern
+            .iter()
+            .flat_map(|&(db, frames)| std::iter::repeat_n(db, frames))
+            .collect()
+    }
+
+    #[test]
+    fn test_speech_ranges_hangover_and_minimum_length() {
+        // 1s speech, 200ms pause, 1s speech, 1s silence, 100ms click, 1s silence
+        let levels = levels(&[
+            (-20.0, 50),
+            (-60.0, 10),
+            (-20.0, 50),
+            (-60.0, 50),
+            (-20.0, 5),
+            (-60.0, 50),
+        ]);
+        let threshold = speech_threshold(&levels);
+        assert_eq!(threshold, -48.0);
+
+        let ranges = speech_ranges(&levels, threshold);
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0].start_seconds.abs() < 1e-9);
+        assert!((ranges[0].end_seconds - 2.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_silences_between_speakers() {
+        let range = |start_seconds, end_seconds| TimeRange {
+            start_seconds,
+            end_seconds,
+        };
+        let participant = |speech: Vec<TimeRange>| ParticipantActivity {
+            talk_seconds: speech.iter().map(TimeRange::duration_seconds).sum(),
+            speech,
+            talk_time_percent: 0.0,
+            threshold_db: -40.0,
+        };
+        let timeline = ActivityTimeline {
+            analyzed_at: Utc::now(),
+            duration_seconds: 30.0,
+            participants: HashMap::from([
+                ("p1".to_string(), participant(vec![range(1.0, 5.0)])),
+                (
+                    "p2".to_string(),
+                    participant(vec![range(4.0, 8.0), range(20.0, 22.0)]),
+                ),
+            ]),
+        };
+
+        assert_eq!(
+            timeline.silences(2.0),
+            vec![range(8.0, 20.0), range(22.0, 30.0)]
+        );
+    }
+
+    #[test]
+    fn test_analyze_fixture() {
+        let activity = analyze_file(&fixture()).unwrap();
+
+        assert_eq!(activity.speech.len(), 1);
+        assert!(activity.speech[0].start_seconds < 0.05);
+        assert!((activity.speech[0].end_seconds - activity.duration_seconds).abs() < 0.05);
+    }
+}
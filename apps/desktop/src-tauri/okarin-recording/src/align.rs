@@ -0,0 +1,654 @@
+use super::correlation::{cross_correlation, downmix_decimate, estimate_lag};
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::export::FormatWavWriter;
+use super::storage::derived_file_path;
+use super::types::*;
+use super::wav::WavFileReader;
+use chrono::Utc;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Level envelopes used for the coarse search, 10ms resolution
+const ENVELOPE_RATE: u32 = 100;
+/// Rate of the audio the fine search runs on
+const ALIGN_RATE: u32 = 8000;
+/// Length of every window compared for the fine search, short enough for
+/// drift not to smear the correlation peak within a window
+const ALIGN_WINDOW_SECONDS: f64 = 2.0;
+/// About two windows a minute, within these bounds
+const ALIGN_WINDOW_SPACING_SECONDS: f64 = 30.0;
+const MIN_ALIGN_WINDOWS: usize = 4;
+const MAX_ALIGN_WINDOWS: usize = 120;
+/// Drift tolerated between a window and the whole-file estimate
+const LOCAL_SEARCH_SECONDS: f64 = 2.0;
+/// Fine search margin around the envelope estimate
+const FINE_SEARCH_SECONDS: f64 = 0.05;
+/// Windows matching worse than this (silence, crosstalk) are ignored
+const MIN_ALIGN_CORRELATION: f64 = 0.3;
+/// Windows this far off the fitted line are dropped as outliers
+const MAX_ALIGN_RESIDUAL_SECONDS: f64 = 0.002;
+const ALIGN_BLOCK_FRAMES: usize = 4800;
+
+/// Mono audio read block by block, at any sample rate
+trait MonoSource {
+    fn sample_rate(&self) -> u32;
+    fn read(&mut self, frames: usize) -> RecordingResult<Option<Vec<f32>>>;
+}
+
+/// A participant's track placed on the session timeline
+struct TrackSource {
+    reader: TimelineReader<BufReader<File>>,
+}
+
+impl MonoSource for TrackSource {
+    fn sample_rate(&self) -> u32 {
+        OPUS_SAMPLE_RATE
+    }
+
+    fn read(&mut self, frames: usize) -> RecordingResult<Option<Vec<f32>>> {
+        let channels = self.reader.channels();
+        Ok(self
+            .reader
+            .read(frames)?
+            .map(|block| downmix_decimate(&block, channels, 1)))
+    }
+}
+
+struct WavSource {
+    reader: WavFileReader,
+}
+
+impl MonoSource for WavSource {
+    fn sample_rate(&self) -> u32 {
+        self.reader.sample_rate()
+    }
+
+    fn read(&mut self, frames: usize) -> RecordingResult<Option<Vec<f32>>> {
+        let channels = self.reader.channels();
+        Ok(self
+            .reader
+            .read(frames)?
+            .map(|block| downmix_decimate(&block, channels, 1)))
+    }
+}
+
+/// Averages a stream over consecutive bins of (possibly fractional) length
+///
+/// Used to bring both files to a common low rate: the box average is a
+/// crude anti-aliasing filter, and its half-bin delay is the same on both
+/// sides so it cancels out of the measured offset.
+struct BoxResampler {
+    ratio: f64,
+    position: u64,
+    bins: u64,
+    sum: f64,
+}
+
+impl BoxResampler {
+    fn new(input_rate: u32, output_rate: u32) -> Self {
+        Self {
+            ratio: input_rate as f64 / output_rate as f64,
+            position: 0,
+            bins: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn push(&mut self, samples: &[f32], output: &mut Vec<f32>) {
+        for &sample in samples {
+            let sample = sample as f64;
+            let mut cursor = self.position as f64;
+            let end = cursor + 1.0;
+            let mut edge = (self.bins + 1) as f64 * self.ratio;
+
+            while edge <= end {
+                self.sum += sample * (edge - cursor);
+                output.push((self.sum / self.ratio) as f32);
+                self.sum = 0.0;
+                self.bins += 1;
+                cursor = edge;
+                edge = (self.bins + 1) as f64 * self.ratio;
+            }
+            self.sum += sample * (end - cursor);
+            self.position += 1;
+        }
+    }
+}
+
+/// RMS envelope at `ENVELOPE_RATE`, mean removed
+fn envelope(mut source: impl MonoSource) -> RecordingResult<Vec<f32>> {
+    let mut resampler = BoxResampler::new(source.sample_rate(), ENVELOPE_RATE);
+    let mut envelope = Vec::new();
+    while let Some(block) = source.read(ALIGN_BLOCK_FRAMES)? {
+        let squared: Vec<f32> = block.iter().map(|s| s * s).collect();
+        resampler.push(&squared, &mut envelope);
+    }
+
+    envelope.iter_mut().for_each(|v| *v = v.sqrt());
+    let mean = envelope.iter().sum::<f32>() / envelope.len().max(1) as f32;
+    envelope.iter_mut().for_each(|v| *v -= mean);
+    Ok(envelope)
+}
+
+/// Copy `data[start..start + len]`, zeros outside of `data`
+fn slice_padded(data: &[f32], start: i64, len: usize) -> Vec<f32> {
+    (start..start + len as i64)
+        .map(|i| {
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| data.get(i))
+                .copied()
+                .unwrap_or(0.0)
+        })
+        .collect()
+}
+
+/// Gather `(start, len)` ranges of a source resampled to `ALIGN_RATE`
+fn collect_windows(
+    mut source: impl MonoSource,
+    ranges: &[(i64, usize)],
+) -> RecordingResult<Vec<Vec<f32>>> {
+    let mut resampler = BoxResampler::new(source.sample_rate(), ALIGN_RATE);
+    let mut windows: Vec<Vec<f32>> = ranges.iter().map(|&(_, len)| vec![0.0; len]).collect();
+    let mut index = 0i64;
+    let mut block = Vec::new();
+
+    while let Some(samples) = source.read(ALIGN_BLOCK_FRAMES)? {
+        block.clear();
+        resampler.push(&samples, &mut block);
+        let block_end = index + block.len() as i64;
+
+        for (window, &(start, len)) in windows.iter_mut().zip(ranges) {
+            let from = start.max(index);
+            let to = (start + len as i64).min(block_end);
+            if from < to {
+                window[(from - start) as usize..(to - start) as usize]
+                    .copy_from_slice(&block[(from - index) as usize..(to - index) as usize]);
+            }
+        }
+        index = block_end;
+    }
+
+    Ok(windows)
+}
+
+/// Linear mapping from session time to external file time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockFit {
+    /// External time at the start of the session
+    pub intercept: f64,
+    /// External seconds per session second
+    pub rate: f64,
+}
+
+impl ClockFit {
+    /// Least-squares fit of `(session, external)` time pairs
+    fn fit(points: &[(f64, f64)]) -> Option<Self> {
+        let n = points.len() as f64;
+        let (first_session, first_external) = *points.first()?;
+        if points.len() == 1 {
+            return Some(Self {
+                intercept: first_external - first_session,
+                rate: 1.0,
+            });
+        }
+
+        let mean_s = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_e = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|p| (p.0 - mean_s) * (p.1 - mean_e)).sum();
+        let variance: f64 = points.iter().map(|p| (p.0 - mean_s).powi(2)).sum();
+        let rate = covariance / variance;
+        Some(Self {
+            intercept: mean_e - rate * mean_s,
+            rate,
+        })
+    }
+
+    pub fn external_time(&self, session_seconds: f64) -> f64 {
+        self.intercept + self.rate * session_seconds
+    }
+
+    /// Session time of the external file's first sample
+    pub fn offset_seconds(&self) -> f64 {
+        -self.intercept / self.rate
+    }
+
+    pub fn drift_ppm(&self) -> f64 {
+        (self.rate - 1.0) * 1e6
+    }
+}
+
+/// Peak position refined between samples with a parabola
+fn refine_peak(values: &[f64], index: usize) -> f64 {
+    if index == 0 || index + 1 >= values.len() {
+        return index as f64;
+    }
+    let (left, center, right) = (values[index - 1], values[index], values[index + 1]);
+    let curvature = left - 2.0 * center + right;
+    if curvature >= 0.0 {
+        return index as f64;
+    }
+    index as f64 + 0.5 * (left - right) / curvature
+}
+
+/// Estimate how the external clock maps onto the session
+///
+/// A whole-file envelope correlation gives a rough offset, refined per
+/// window on the envelopes to follow drift, then to a fraction of a sample
+/// on 8kHz audio. A line through the window positions gives offset and
+/// drift. Returns the fit and the session times of the windows used.
+fn estimate_clock<R, E>(
+    open_reference: impl Fn() -> RecordingResult<R>,
+    open_external: impl Fn() -> RecordingResult<E>,
+) -> RecordingResult<Option<(ClockFit, Vec<f64>)>>
+where
+    R: MonoSource,
+    E: MonoSource,
+{
+    let reference_envelope = envelope(open_reference()?)?;
+    let external_envelope = envelope(open_external()?)?;
+    let global = match estimate_lag(
+        &reference_envelope,
+        &external_envelope,
+        -(reference_envelope.len() as isize),
+        external_envelope.len() as isize,
+    ) {
+        Some(global) => global.lag as i64,
+        None => return Ok(None),
+    };
+
+    let duration = reference_envelope.len() as f64 / ENVELOPE_RATE as f64;
+    let window_seconds = ALIGN_WINDOW_SECONDS.min(duration);
+    let count = if duration <= window_seconds {
+        1
+    } else {
+        ((duration / ALIGN_WINDOW_SPACING_SECONDS).ceil() as usize)
+            .clamp(MIN_ALIGN_WINDOWS, MAX_ALIGN_WINDOWS)
+    };
+    let spacing = (duration - window_seconds) / (count.max(2) - 1) as f64;
+
+    let envelope_window = (window_seconds * ENVELOPE_RATE as f64) as usize;
+    let envelope_search = (LOCAL_SEARCH_SECONDS * ENVELOPE_RATE as f64) as i64;
+    let fine_window = (window_seconds * ALIGN_RATE as f64) as usize;
+    let fine_search = (FINE_SEARCH_SECONDS * ALIGN_RATE as f64) as i64;
+
+    let mut reference_ranges = Vec::new();
+    let mut external_ranges = Vec::new();
+    for index in 0..count {
+        let session_seconds = index as f64 * spacing;
+        let envelope_start = (session_seconds * ENVELOPE_RATE as f64).round() as i64;
+        let reference = slice_padded(&reference_envelope, envelope_start, envelope_window);
+        let external = slice_padded(
+            &external_envelope,
+            envelope_start + global - envelope_search,
+            envelope_window + 2 * envelope_search as usize,
+        );
+
+        let local = match estimate_lag(&reference, &external, 0, 2 * envelope_search as isize) {
+            Some(local) if local.correlation >= MIN_ALIGN_CORRELATION => local,
+            _ => continue,
+        };
+        let envelope_lag = global - envelope_search + local.lag as i64;
+        let predicted = session_seconds + envelope_lag as f64 / ENVELOPE_RATE as f64;
+
+        let reference_start = (session_seconds * ALIGN_RATE as f64).round() as i64;
+        let external_start = (predicted * ALIGN_RATE as f64).round() as i64 - fine_search;
+        reference_ranges.push((reference_start, fine_window));
+        external_ranges.push((external_start, fine_window + 2 * fine_search as usize));
+    }
+
+    let reference_windows = collect_windows(open_reference()?, &reference_ranges)?;
+    let external_windows = collect_windows(open_external()?, &external_ranges)?;
+
+    let energy = |s: &[f32]| s.iter().map(|&x| (x as f64) * (x as f64)).sum::<f64>();
+    let mut points = Vec::new();
+    for index in 0..reference_windows.len() {
+        let (reference, external) = (&reference_windows[index], &external_windows[index]);
+        let norm = (energy(reference) * energy(external)).sqrt();
+        if norm <= 0.0 {
+            continue;
+        }
+
+        let correlation = cross_correlation(reference, external, 0, 2 * fine_search as isize);
+        let (peak_index, peak) = correlation
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0));
+        if peak / norm < MIN_ALIGN_CORRELATION {
+            continue;
+        }
+
+        let lag = refine_peak(&correlation, peak_index);
+        let session_seconds = reference_ranges[index].0 as f64 / ALIGN_RATE as f64;
+        let external_seconds = (external_ranges[index].0 as f64 + lag) / ALIGN_RATE as f64;
+        points.push((session_seconds, external_seconds));
+    }
+
+    let mut fit = match ClockFit::fit(&points) {
+        Some(fit) => fit,
+        None => return Ok(None),
+    };
+    let inliers: Vec<(f64, f64)> = points
+        .iter()
+        .copied()
+        .filter(|&(s, e)| (fit.external_time(s) - e).abs() <= MAX_ALIGN_RESIDUAL_SECONDS)
+        .collect();
+    if !inliers.is_empty() && inliers.len() < points.len() {
+        fit = ClockFit::fit(&inliers).unwrap_or(fit);
+        points = inliers;
+    }
+
+    Ok(Some((fit, points.into_iter().map(|(s, _)| s).collect())))
+}
+
+/// Reads frames of a WAV file at fractional positions
+struct Interpolator {
+    reader: WavFileReader,
+    channels: usize,
+    buffer: VecDeque<f32>,
+    /// Frame index of the first buffered frame
+    start: i64,
+    exhausted: bool,
+}
+
+impl Interpolator {
+    fn new(reader: WavFileReader) -> Self {
+        Self {
+            channels: reader.channels().max(1) as usize,
+            reader,
+            buffer: VecDeque::new(),
+            start: 0,
+            exhausted: false,
+        }
+    }
+
+    fn frame(&self, index: i64, channel: usize) -> f32 {
+        if index < self.start {
+            return 0.0;
+        }
+        let offset = (index - self.start) as usize * self.channels + channel;
+        self.buffer.get(offset).copied().unwrap_or(0.0)
+    }
+
+    /// Catmull-Rom interpolation of every channel at `position` (in frames)
+    fn sample_at(&mut self, position: f64, output: &mut Vec<f32>) -> RecordingResult<()> {
+        let base = position.floor() as i64;
+        let t = (position - base as f64) as f32;
+
+        while base + 2 >= self.start + (self.buffer.len() / self.channels) as i64 && !self.exhausted
+        {
+            match self.reader.read(ALIGN_BLOCK_FRAMES)? {
+                Some(block) => self.buffer.extend(block),
+                None => self.exhausted = true,
+            }
+            // Only the 4 frames around the position are ever needed
+            let stale =
+                (base - 1 - self.start).clamp(0, (self.buffer.len() / self.channels) as i64);
+            self.buffer.drain(..stale as usize * self.channels);
+            self.start += stale;
+        }
+
+        for channel in 0..self.channels {
+            let p0 = self.frame(base - 1, channel);
+            let p1 = self.frame(base, channel);
+            let p2 = self.frame(base + 1, channel);
+            let p3 = self.frame(base + 2, channel);
+            output.push(
+                p1 + 0.5
+                    * t
+                    * (p2 - p0
+                        + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3
+                            + t * (3.0 * (p1 - p2) + p3 - p0))),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Resample an external file onto the session timeline, in the `target`
+/// format
+///
+/// Sample 0 of the output is the start of the session, and the drift is
+/// corrected, so the copy lines up with the recorded tracks over its whole
+/// length.
+pub fn write_aligned_copy(
+    external_file: &Path,
+    fit: &ClockFit,
+    target: &MediaFormat,
+    output: &Path,
+) -> RecordingResult<()> {
+    let reader = WavFileReader::open(external_file)?;
+    let rate = reader.sample_rate() as f64;
+    let channels = reader.channels();
+    let external_seconds = reader.frame_count() as f64 / rate;
+
+    let session_end = (external_seconds - fit.intercept) / fit.rate;
+    if session_end <= 0.0 {
+        return Err(RecordingError::InvalidMedia(format!(
+            "{:?} ends before the session starts",
+            external_file
+        )));
+    }
+    let frames = (session_end * OPUS_SAMPLE_RATE as f64).floor() as u64;
+
+    let mut interpolator = Interpolator::new(reader);
+    let mut writer = FormatWavWriter::create(output, channels, target)?;
+    let mut block = Vec::with_capacity(ALIGN_BLOCK_FRAMES * channels as usize);
+
+    for frame in 0..frames {
+        let position = fit.external_time(frame as f64 / OPUS_SAMPLE_RATE as f64) * rate;
+        if position < -1.0 {
+            block.extend(std::iter::repeat_n(0.0, channels as usize));
+        } else {
+            interpolator.sample_at(position, &mut block)?;
+        }
+        if block.len() >= ALIGN_BLOCK_FRAMES * channels as usize {
+            writer.write(&block)?;
+            block.clear();
+        }
+    }
+    writer.write(&block)?;
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Line an external backup up with a participant's track and write an
+/// aligned copy of it next to the track
+pub fn align_external_file(
+    metadata: &RecordingMetadata,
+    participant_id: &str,
+    external_file: &Path,
+) -> RecordingResult<ExternalAlignment> {
+    let participant = metadata
+        .participants
+        .get(participant_id)
+        .ok_or_else(|| RecordingError::ParticipantNotFound(participant_id.to_string()))?;
+    let track = participant.audio_file.as_ref().ok_or_else(|| {
+        RecordingError::InvalidConfig(format!("participant {} has no audio track", participant_id))
+    })?;
+    let track_offset = metadata.participant_offset_seconds(participant);
+
+    log::info!(
+        "Aligning {:?} against the track of participant {}",
+        external_file,
+        participant_id
+    );
+    let estimate = estimate_clock(
+        || {
+            let decoder = OpusTrackDecoder::open(track)?;
+            Ok(TrackSource {
+                reader: TimelineReader::new(decoder, track_offset),
+            })
+        },
+        || {
+            Ok(WavSource {
+                reader: WavFileReader::open(external_file)?,
+            })
+        },
+    )?;
+    let (fit, sync_points) = estimate.ok_or_else(|| {
+        RecordingError::InvalidMedia(format!(
+            "could not line {:?} up with the track of participant {}",
+            external_file, participant_id
+        ))
+    })?;
+
+    let stem = external_file
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let output = derived_file_path(track, &format!("{}-aligned", stem), "wav");
+    write_aligned_copy(external_file, &fit, &metadata.export_format(), &output)?;
+
+    Ok(ExternalAlignment {
+        participant_id: participant_id.to_string(),
+        source_file: external_file.to_path_buf(),
+        method: SyncMethod::CrossCorrelation,
+        offset_seconds: fit.offset_seconds(),
+        drift_ppm: fit.drift_ppm(),
+        sync_points,
+        aligned_file: Some(output),
+        aligned_at: Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use crate::wav::WavFileWriter;
+
+    struct VecSource {
+        samples: Vec<f32>,
+        sample_rate: u32,
+        position: usize,
+    }
+
+    impl MonoSource for VecSource {
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn read(&mut self, frames: usize) -> RecordingResult<Option<Vec<f32>>> {
+            let end = (self.position + frames).min(self.samples.len());
+            let block = self.samples[self.position..end].to_vec();
+            self.position = end;
+            Ok((!block.is_empty()).then_some(block))
+        }
+    }
+
+    /// Noise shaped into 200ms "syllables" of random loudness
+    fn speech_like(frames: usize) -> Vec<f32> {
+        let mut state = 12345u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32
+        };
+        let mut level = 0.0;
+        (0..frames)
+            .map(|n| {
+                if n % 9600 == 0 {
+                    level = if next() < 0.3 { 0.0 } else { next() };
+                }
+                level * (next() - 0.5)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_box_resampler_fractional_ratio() {
+        let mut resampler = BoxResampler::new(3, 2);
+        let mut output = Vec::new();
+        resampler.push(&[1.0, 1.0, 1.0, 4.0, 4.0, 4.0, 2.0], &mut output);
+        assert_eq!(output, vec![1.0, 1.0, 4.0, 4.0]);
+    }
+
+    #[test]
+    fn test_aligned_copy_starts_at_session_start() {
+        let dir = temp_dir("align-copy");
+        let external = dir.join("backup.wav");
+        let output = dir.join("aligned.wav");
+
+        let mut writer = WavFileWriter::create(&external, 1, 44100).unwrap();
+        let sine: Vec<f32> = (0..2 * 44100)
+            .map(|n| (0.5 * (2.0 * std::f64::consts::PI * 440.0 * n as f64 / 44100.0).sin()) as f32)
+            .collect();
+        writer.write(&sine).unwrap();
+        writer.finalize().unwrap();
+
+        // Backup started half a second into the session
+        let fit = ClockFit {
+            intercept: -0.5,
+            rate: 1.0,
+        };
+        write_aligned_copy(&external, &fit, &MediaFormat::default(), &output).unwrap();
+        let mut reader = WavFileReader::open(&output).unwrap();
+        let frames = reader.frame_count() as usize;
+        let samples = reader.read(frames).unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(samples.len(), 120000);
+        assert!(samples[..23990].iter().all(|&s| s == 0.0));
+        let expected = 0.5 * (2.0 * std::f64::consts::PI * 440.0 * 0.75).sin();
+        assert!((samples[60000] as f64 - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_estimates_offset_and_drift() {
+        let reference = speech_like(40 * 48000);
+
+        // Backup started 2.5s before the session and its clock runs 100ppm fast
+        let truth = ClockFit {
+            intercept: 2.5,
+            rate: 1.0001,
+        };
+        let external: Vec<f32> = (0..44 * 44100)
+            .map(|j| {
+                let session = (j as f64 / 44100.0 - truth.intercept) / truth.rate;
+                let position = session * 48000.0;
+                let index = position.floor();
+                if index < 0.0 || index as usize + 1 >= reference.len() {
+                    return 0.0;
+                }
+                let t = (position - index) as f32;
+                let index = index as usize;
+                reference[index] * (1.0 - t) + reference[index + 1] * t
+            })
+            .collect();
+
+        let (fit, points) = estimate_clock(
+            || {
+                Ok(VecSource {
+                    samples: reference.clone(),
+                    sample_rate: 48000,
+                    position: 0,
+                })
+            },
+            || {
+                Ok(VecSource {
+                    samples: external.clone(),
+                    sample_rate: 44100,
+                    position: 0,
+                })
+            },
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(points.len(), MIN_ALIGN_WINDOWS);
+        assert!(
+            (fit.offset_seconds() - truth.offset_seconds()).abs() < 0.0005,
+            "offset: {}",
+            fit.offset_seconds()
+        );
+        assert!(
+            (fit.drift_ppm() - 100.0).abs() < 10.0,
+            "drift: {}",
+            fit.drift_ppm()
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use super::activity;
+use super::bleed;
+use super::loudness;
+use super::storage;
+use super::types::*;
+use super::waveform;
+
+/// Post-stop analysis pass over a finished recording
+///
+/// Every step logs its own failure instead of returning it: a track that
+/// cannot be analyzed must never cost the user the recording itself.
+pub fn run_post_stop_analysis(mut metadata: RecordingMetadata) -> RecordingMetadata {
+    match loudness::analyze_recording(&metadata) {
+        Ok(report) => metadata.loudness = Some(report),
+        Err(e) => log::error!("Loudness analysis failed: {}", e),
+    }
+
+    match bleed::analyze_recording(&metadata) {
+        Ok(report) => metadata.bleed = Some(report),
+        Err(e) => log::error!("Bleed detection failed: {}", e),
+    }
+
+    if let Err(e) = waveform::generate_recording_waveforms(&mut metadata) {
+        log::error!("Waveform generation failed: {}", e);
+    }
+
+    match activity::analyze_recording(&metadata) {
+        Ok(timeline) => {
+            if let Err(e) = storage::write_activity(&metadata.output_directory, &timeline) {
+                log::error!("Failed to save speech activity: {}", e);
+            }
+        }
+        Err(e) => log::error!("Speech activity detection failed: {}", e),
+    }
+
+    if let Err(e) = storage::write_metadata(&metadata.output_directory, &metadata) {
+        log::error!("Failed to save analysis results: {}", e);
+    }
+
+    metadata
+}
@@ -0,0 +1,275 @@
+use super::muxer::WebmMuxer;
+use super::storage::derived_file_path;
+use super::types::*;
+use super::webm::{TrackInfo, TrackKind, WebmBlock, WebmReader};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Codecs a WebM file may carry, anything else needs a Matroska file
+const WEBM_CODECS: [&str; 5] = ["A_OPUS", "A_VORBIS", "V_VP8", "V_VP9", "V_AV1"];
+
+/// One input track, read block by block and shifted onto the output timeline
+struct MuxInput {
+    reader: WebmReader<BufReader<File>>,
+    track: TrackInfo,
+    source_number: u64,
+    shift_ns: i64,
+    next: Option<WebmBlock>,
+}
+
+impl MuxInput {
+    /// Open the first track of `kind` in `path`
+    fn open(
+        path: &Path,
+        kind: TrackKind,
+        output_number: u64,
+        shift_ns: i64,
+    ) -> RecordingResult<Self> {
+        let mut reader = WebmReader::open(path)?;
+        let tracks = reader.read_tracks()?;
+        let mut track = tracks
+            .into_iter()
+            .find(|track| track.kind == kind)
+            .ok_or_else(|| {
+                let name = match kind {
+                    TrackKind::Audio => "audio",
+                    TrackKind::Video => "video",
+                    TrackKind::Other(_) => "matching",
+                };
+                RecordingError::InvalidMedia(format!("no {} track in {:?}", name, path))
+            })?;
+        let source_number = track.number;
+        track.number = output_number;
+        track.uid = Some(output_number);
+
+        let mut input = Self {
+            reader,
+            track,
+            source_number,
+            shift_ns,
+            next: None,
+        };
+        input.advance()?;
+        Ok(input)
+    }
+
+    fn advance(&mut self) -> RecordingResult<()> {
+        self.next = loop {
+            match self.reader.next_block()? {
+                Some(block) if block.track_number == self.source_number => break Some(block),
+                Some(_) => continue,
+                None => break None,
+            }
+        };
+        Ok(())
+    }
+
+    fn next_timestamp_ns(&self) -> Option<i64> {
+        self.next
+            .as_ref()
+            .map(|block| block.timestamp_ns + self.shift_ns)
+    }
+
+    fn write_next(&mut self, muxer: &mut WebmMuxer<BufWriter<File>>) -> RecordingResult<()> {
+        if let Some(block) = self.next.take() {
+            let frame_count = block.frames.len().max(1) as u64;
+            let frame_duration = block
+                .duration_ns
+                .map(|d| d / frame_count)
+                .or(self.track.default_duration_ns)
+                .unwrap_or(0);
+            let start_ns = block.timestamp_ns + self.shift_ns;
+            for (index, frame) in block.frames.iter().enumerate() {
+                muxer.write_frame(
+                    self.track.number,
+                    start_ns + (index as u64 * frame_duration) as i64,
+                    frame_duration,
+                    block.keyframe,
+                    frame,
+                )?;
+            }
+        }
+        self.advance()
+    }
+}
+
+/// Interleave a participant's audio and video into one file
+///
+/// `video_offset_seconds` is the start of the video relative to the audio;
+/// whichever starts first sits at zero. Blocks are copied untouched, the
+/// extension of `output_stem` becomes `.webm` or `.mkv` depending on codecs.
+pub fn mux_audio_video(
+    audio: &Path,
+    video: &Path,
+    video_offset_seconds: f64,
+    output_stem: &Path,
+) -> RecordingResult<PathBuf> {
+    let offset_ns = (video_offset_seconds * 1e9).round() as i64;
+    let mut inputs = [
+        MuxInput::open(audio, TrackKind::Audio, 1, (-offset_ns).max(0))?,
+        MuxInput::open(video, TrackKind::Video, 2, offset_ns.max(0))?,
+    ];
+
+    let webm = inputs
+        .iter()
+        .all(|input| WEBM_CODECS.contains(&input.track.codec_id.as_str()));
+    let (doc_type, extension) = if webm {
+        ("webm", "webm")
+    } else {
+        ("matroska", "mkv")
+    };
+    let output = output_stem.with_extension(extension);
+    let tracks: Vec<TrackInfo> = inputs.iter().map(|input| input.track.clone()).collect();
+    let mut muxer = WebmMuxer::create(&output, doc_type, &tracks)?;
+
+    // Always write the earliest pending block, so timestamps never go back
+    while let Some(input) = inputs
+        .iter_mut()
+        .filter(|input| input.next.is_some())
+        .min_by_key(|input| input.next_timestamp_ns())
+    {
+        input.write_next(&mut muxer)?;
+    }
+
+    muxer.finish()
+}
+
+/// Mux every participant that has both audio and video to `<participant>-av`
+pub fn mux_recording(metadata: &RecordingMetadata) -> RecordingResult<HashMap<String, PathBuf>> {
+    let mut files = HashMap::new();
+
+    for (participant_id, participant) in &metadata.participants {
+        let (Some(audio), Some(video)) = (&participant.audio_file, &participant.video_file) else {
+            continue;
+        };
+
+        log::info!("Muxing audio and video of participant {}", participant_id);
+        let output = mux_audio_video(
+            audio,
+            video,
+            participant.video_offset_seconds.unwrap_or(0.0),
+            &derived_file_path(audio, "av", "webm"),
+        )?;
+        files.insert(participant_id.clone(), output);
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, temp_dir};
+    use crate::webm::{VideoTrackInfo, WebmEvent};
+
+    /// 2 seconds of fake VP8 at 25 fps, a keyframe every second
+    fn write_video(path: &Path, codec_id: &str) {
+        let track = TrackInfo {
+            number: 1,
+            uid: Some(1),
+            kind: TrackKind::Video,
+            codec_id: codec_id.to_string(),
+            codec_private: None,
+            codec_delay_ns: 0,
+            seek_pre_roll_ns: 0,
+            default_duration_ns: None,
+            audio: None,
+            video: Some(VideoTrackInfo {
+                pixel_width: 640,
+                pixel_height: 360,
+            }),
+        };
+        let mut muxer = WebmMuxer::create(path, "webm", &[track]).unwrap();
+        for index in 0..50i64 {
+            muxer
+                .write_frame(1, index * 40_000_000, 40_000_000, index % 25 == 0, &[7])
+                .unwrap();
+        }
+        muxer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_mux_interleaves_with_video_offset() {
+        let dir = temp_dir("avmux");
+        let video = dir.join("p1-Guest-video.webm");
+        write_video(&video, "V_VP8");
+
+        let output = mux_audio_video(&fixture(), &video, 0.5, &dir.join("p1-Guest-av")).unwrap();
+        assert_eq!(output, dir.join("p1-Guest-av.webm"));
+
+        let mut reader = WebmReader::open(&output).unwrap();
+        let mut tracks = Vec::new();
+        let mut blocks = Vec::new();
+        while let Some(event) = reader.next_event().unwrap() {
+            match event {
+                WebmEvent::Tracks(t) => tracks = t,
+                WebmEvent::Block(block) => blocks.push(block),
+                _ => {}
+            }
+        }
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].codec_id, "A_OPUS");
+        assert_eq!(tracks[1].kind, TrackKind::Video);
+        assert!(blocks
+            .windows(2)
+            .all(|pair| pair[0].timestamp_ns <= pair[1].timestamp_ns));
+
+        let audio: Vec<_> = blocks.iter().filter(|b| b.track_number == 1).collect();
+        let video: Vec<_> = blocks.iter().filter(|b| b.track_number == 2).collect();
+        assert_eq!(audio.len(), 100);
+        assert_eq!(audio[0].timestamp_ns, 0);
+        assert_eq!(video.len(), 50);
+        assert_eq!(video[0].timestamp_ns, 500_000_000);
+        assert!(video[0].keyframe && !video[1].keyframe);
+    }
+
+    #[test]
+    fn test_mux_falls_back_to_matroska() {
+        let dir = temp_dir("avmux-mkv");
+        let video = dir.join("p1-Host-video.webm");
+        write_video(&video, "V_MPEG4/ISO/AVC");
+
+        // Video started first: the audio moves later instead
+        let output = mux_audio_video(&fixture(), &video, -0.25, &dir.join("p1-Host-av")).unwrap();
+        assert_eq!(output, dir.join("p1-Host-av.mkv"));
+
+        let mut reader = WebmReader::open(&output).unwrap();
+        let mut doc_type = String::new();
+        let mut first_audio = None;
+        while let Some(event) = reader.next_event().unwrap() {
+            match event {
+                WebmEvent::EbmlHeader { doc_type: d } => doc_type = d,
+                WebmEvent::Block(block) if block.track_number == 1 => {
+                    first_audio.get_or_insert(block.timestamp_ns);
+                }
+                _ => {}
+            }
+        }
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(doc_type, "matroska");
+        assert_eq!(first_audio, Some(250_000_000));
+    }
+
+    #[test]
+    fn test_mux_requires_audio_and_video_tracks() {
+        let dir = temp_dir("avmux-kinds");
+        let video = dir.join("p1-Guest-video.webm");
+        write_video(&video, "V_VP8");
+
+        // Files handed over the wrong way round
+        let result = mux_audio_video(&video, &fixture(), 0.0, &dir.join("p1-Guest-av"));
+        std::fs::remove_dir_all(&dir).ok();
+
+        match result {
+            Err(RecordingError::InvalidMedia(message)) => {
+                assert!(message.starts_with("no audio track"), "{}", message)
+            }
+            other => panic!("expected a missing audio track, got {:?}", other),
+        }
+    }
+}
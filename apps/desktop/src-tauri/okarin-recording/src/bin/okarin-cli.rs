@@ -0,0 +1,243 @@
+// Post-processing of finished recordings without the desktop app, e.g. on a
+// build server or a NAS after upload
+
+use okarin_recording::types::{MixFormat, PublishFormat};
+use okarin_recording::{
+    activity, avmux, export, feed, loudness, mixdown, ogg, probe, publish, repair, storage,
+    MixdownOptions, PublishOptions, RecordingError, RecordingMetadata,
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: okarin-cli <command> <recording-dir> [options]
+       okarin-cli feed <recordings-root>
+
+Commands:
+  inspect      Summarize the recording and check its files
+  probe        Report container, codecs and structural errors of every track
+  repair       Rewrite truncated or unseekable WebM tracks (*-repaired.webm)
+  export-wav   Decode every participant to a WAV aligned on the session start
+  export-ogg   Remux every participant's audio to Ogg Opus, without re-encoding
+  export-av    Interleave each participant's audio and video into one file
+  mixdown      Mix every participant into one file [--format wav|webm]
+  publish      Encode the tagged episode from episode.json [--format mp3|m4a]
+  loudness     Measure EBU R128 loudness of every track
+  timeline     Compute who talks when
+  feed         Regenerate the podcast feed from feed.json in the recordings root
+
+Results are printed as JSON, logs go to stderr (RUST_LOG=info).";
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), RecordingError> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| RecordingError::IoError(std::io::Error::other(e)))?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn describe_file(path: &Path) -> String {
+    match std::fs::metadata(path) {
+        Ok(file) => format!("{} ({} bytes)", path.display(), file.len()),
+        Err(_) => format!("{} (missing)", path.display()),
+    }
+}
+
+fn inspect(metadata: &RecordingMetadata) {
+    println!("Recording:  {}", metadata.id);
+    println!("Room:       {}", metadata.room_id);
+    println!("Started:    {}", metadata.started_at);
+    match metadata.stopped_at {
+        Some(stopped_at) => println!("Stopped:    {}", stopped_at),
+        None => println!("Stopped:    never (interrupted recording?)"),
+    }
+    println!("Length:     {:.1}s", metadata.session_length_seconds());
+
+    let mut participants: Vec<_> = metadata.participants.values().collect();
+    participants.sort_by_key(|p| p.joined_at);
+    println!("\nParticipants:");
+    for participant in participants {
+        println!(
+            "  {} [{}] at {:.3}s for {:.1}s",
+            participant.name,
+            participant.id,
+            metadata.participant_offset_seconds(participant),
+            metadata.participant_length_seconds(participant)
+        );
+        if let Some(audio) = &participant.audio_file {
+            println!("    audio: {}", describe_file(audio));
+        }
+        if let Some(video) = &participant.video_file {
+            println!("    video: {}", describe_file(video));
+        }
+    }
+
+    if !metadata.markers.is_empty() {
+        println!("\nMarkers:");
+        for marker in &metadata.markers {
+            println!(
+                "  {:>9.3}s {:?} {}",
+                marker.position_seconds, marker.kind, marker.label
+            );
+        }
+    }
+
+    println!("\nProcessing:");
+    let done = |present: bool| if present { "yes" } else { "no" };
+    println!("  loudness:      {}", done(metadata.loudness.is_some()));
+    println!(
+        "  normalized:    {}",
+        done(metadata.normalization.is_some())
+    );
+    println!("  bleed:         {}", done(metadata.bleed.is_some()));
+    println!("  trimmed:       {}", done(metadata.trim.is_some()));
+    println!("  cleaned:       {}", done(metadata.cleanup.is_some()));
+    match &metadata.mix_file {
+        Some(mix_file) => println!("  mix:           {}", describe_file(mix_file)),
+        None => println!("  mix:           no"),
+    }
+}
+
+fn parse_format(args: &[String]) -> Result<MixFormat, RecordingError> {
+    match args {
+        [] => Ok(MixFormat::default()),
+        [flag, value] if flag == "--format" => match value.as_str() {
+            "wav" => Ok(MixFormat::Wav),
+            "webm" => Ok(MixFormat::Webm),
+            other => Err(RecordingError::InvalidConfig(format!(
+                "unknown mix format {}, expected wav or webm",
+                other
+            ))),
+        },
+        _ => Err(RecordingError::InvalidConfig(format!(
+            "unexpected arguments {:?}",
+            args
+        ))),
+    }
+}
+
+fn parse_publish_format(args: &[String]) -> Result<PublishFormat, RecordingError> {
+    match args {
+        [] => Ok(PublishFormat::default()),
+        [flag, value] if flag == "--format" => match value.as_str() {
+            "mp3" => Ok(PublishFormat::Mp3),
+            "m4a" => Ok(PublishFormat::M4a),
+            other => Err(RecordingError::InvalidConfig(format!(
+                "unknown publish format {}, expected mp3 or m4a",
+                other
+            ))),
+        },
+        _ => Err(RecordingError::InvalidConfig(format!(
+            "unexpected arguments {:?}",
+            args
+        ))),
+    }
+}
+
+fn run(command: &str, recording_dir: &Path, options: &[String]) -> Result<(), RecordingError> {
+    if command != "mixdown" && command != "publish" && !options.is_empty() {
+        return Err(RecordingError::InvalidConfig(format!(
+            "unexpected arguments {:?}",
+            options
+        )));
+    }
+    // Crashed sessions have no metadata yet, repair and probe find the tracks by name,
+    // and the feed works on the whole recordings root
+    match command {
+        "repair" => return print_json(&repair::repair_recording(recording_dir)?),
+        "feed" => {
+            let settings = storage::load_feed_settings(recording_dir)?.ok_or_else(|| {
+                RecordingError::InvalidConfig(format!(
+                    "no {} in the recordings root",
+                    storage::FEED_SETTINGS_FILENAME
+                ))
+            })?;
+            return print_json(&feed::publish_feed(recording_dir, &settings)?);
+        }
+        "probe" => {
+            let reports = repair::recorded_tracks(recording_dir)?
+                .iter()
+                .map(|track| probe::probe_recording_file(track))
+                .collect::<Result<Vec<_>, _>>()?;
+            return print_json(&reports);
+        }
+        _ => {}
+    }
+    let mut metadata = storage::load_metadata(recording_dir)?;
+
+    match command {
+        "inspect" => {
+            inspect(&metadata);
+            Ok(())
+        }
+        "export-wav" => print_json(&export::export_wav_tracks(&metadata)?),
+        "export-ogg" => print_json(&ogg::export_ogg_tracks(&metadata)?),
+        "export-av" => print_json(&avmux::mux_recording(&metadata)?),
+        "mixdown" => {
+            let options = MixdownOptions {
+                format: parse_format(options)?,
+                ..Default::default()
+            };
+            let mix_file = mixdown::mixdown_recording(&metadata, &options)?;
+            metadata.mix_file = Some(mix_file.clone());
+            storage::write_metadata(recording_dir, &metadata)?;
+            print_json(&mix_file)
+        }
+        "publish" => {
+            let mut episode = storage::load_episode(recording_dir)?.ok_or_else(|| {
+                RecordingError::InvalidConfig(format!(
+                    "no {} in the recording directory",
+                    storage::EPISODE_FILENAME
+                ))
+            })?;
+            let options = PublishOptions {
+                format: parse_publish_format(options)?,
+                ..Default::default()
+            };
+            let export = publish::publish_episode(&metadata, &episode, &options)?;
+            episode.export = Some(export.clone());
+            storage::write_episode(recording_dir, &episode)?;
+            print_json(&export)
+        }
+        "loudness" => {
+            let report = loudness::analyze_recording(&metadata)?;
+            metadata.loudness = Some(report.clone());
+            storage::write_metadata(recording_dir, &metadata)?;
+            print_json(&report)
+        }
+        "timeline" => {
+            let timeline = activity::analyze_recording(&metadata)?;
+            storage::write_activity(recording_dir, &timeline)?;
+            print_json(&timeline)
+        }
+        other => Err(RecordingError::InvalidConfig(format!(
+            "unknown command {}",
+            other
+        ))),
+    }
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, recording_dir, options) = match args.as_slice() {
+        [command, recording_dir, options @ ..] => (command, PathBuf::from(recording_dir), options),
+        [flag] if flag == "--help" || flag == "-h" => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(command, &recording_dir, options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
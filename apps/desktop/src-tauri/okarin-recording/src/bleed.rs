@@ -0,0 +1,226 @@
+use super::correlation::{downmix_decimate, estimate_lag};
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::dsp::linear_to_db;
+use super::types::*;
+use chrono::Utc;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Tracks are compared at 8kHz, plenty for speech and 36x cheaper
+const BLEED_DECIMATION: usize = 6;
+const BLEED_SAMPLE_RATE: f64 = OPUS_SAMPLE_RATE as f64 / BLEED_DECIMATION as f64;
+/// Length of the segments the tracks are compared over
+const BLEED_SEGMENT_SECONDS: f64 = 2.0;
+/// Largest acoustic delay considered, ~17m of distance between the mics
+const MAX_BLEED_DELAY_SECONDS: f64 = 0.05;
+/// Correlation needed to call a segment bleed
+const MIN_BLEED_CORRELATION: f64 = 0.4;
+/// The source must be actually talking for its bleed to be measurable
+const MIN_SOURCE_LEVEL_DB: f64 = -45.0;
+/// Neighbouring segments with delays this close are merged
+const DELAY_MERGE_TOLERANCE_MS: f64 = 1.0;
+
+fn rms_db(samples: &[f32]) -> f64 {
+    if samples.is_empty() {
+        return f64::MIN;
+    }
+    let mean_square = samples
+        .iter()
+        .map(|&s| (s as f64) * (s as f64))
+        .sum::<f64>()
+        / samples.len() as f64;
+    if mean_square > 0.0 {
+        linear_to_db(mean_square.sqrt())
+    } else {
+        f64::MIN
+    }
+}
+
+/// Accumulates flagged segments of one source/target pair
+struct PairState {
+    pair: BleedPair,
+    /// Number of analysis segments merged into the last reported segment
+    merged: usize,
+}
+
+impl PairState {
+    fn add(&mut self, segment: BleedSegment) {
+        if let Some(last) = self.pair.segments.last_mut() {
+            let contiguous = (segment.start_seconds - last.end_seconds).abs() < 1e-6;
+            if contiguous && (segment.delay_ms - last.delay_ms).abs() <= DELAY_MERGE_TOLERANCE_MS {
+                let n = self.merged as f64;
+                last.end_seconds = segment.end_seconds;
+                last.delay_ms = (last.delay_ms * n + segment.delay_ms) / (n + 1.0);
+                last.level_db = (last.level_db * n + segment.level_db) / (n + 1.0);
+                last.correlation = last.correlation.max(segment.correlation);
+                self.merged += 1;
+                return;
+            }
+        }
+        self.pair.segments.push(segment);
+        self.merged = 1;
+    }
+}
+
+/// Compares every ordered pair of tracks segment by segment
+///
+/// Segments are fed already decimated to `BLEED_SAMPLE_RATE` mono, one slice
+/// per participant in the order given to `new`.
+pub struct BleedDetector {
+    pairs: Vec<(usize, usize, PairState)>,
+}
+
+impl BleedDetector {
+    pub fn new(participant_ids: &[String]) -> Self {
+        let mut pairs = Vec::new();
+        for (source, source_id) in participant_ids.iter().enumerate() {
+            for (target, target_id) in participant_ids.iter().enumerate() {
+                if source != target {
+                    let pair = BleedPair {
+                        source_participant_id: source_id.clone(),
+                        target_participant_id: target_id.clone(),
+                        segments: Vec::new(),
+                    };
+                    pairs.push((source, target, PairState { pair, merged: 0 }));
+                }
+            }
+        }
+        Self { pairs }
+    }
+
+    /// Analyze one segment starting at `start_seconds` on the session timeline
+    pub fn push_segment(&mut self, start_seconds: f64, tracks: &[Vec<f32>]) {
+        let max_lag = (MAX_BLEED_DELAY_SECONDS * BLEED_SAMPLE_RATE) as isize;
+        let levels: Vec<f64> = tracks.iter().map(|t| rms_db(t)).collect();
+        let length = tracks.iter().map(Vec::len).max().unwrap_or(0);
+        let end_seconds = start_seconds + length as f64 / BLEED_SAMPLE_RATE;
+
+        for (source, target, state) in &mut self.pairs {
+            if levels[*source] < MIN_SOURCE_LEVEL_DB {
+                continue;
+            }
+            let estimate = match estimate_lag(&tracks[*source], &tracks[*target], 0, max_lag) {
+                Some(estimate) => estimate,
+                None => continue,
+            };
+            // The target holds an attenuated copy, not the original voice
+            if estimate.correlation < MIN_BLEED_CORRELATION || estimate.gain >= 1.0 {
+                continue;
+            }
+
+            state.add(BleedSegment {
+                start_seconds,
+                end_seconds,
+                delay_ms: estimate.lag as f64 * 1000.0 / BLEED_SAMPLE_RATE,
+                level_db: linear_to_db(estimate.gain),
+                correlation: estimate.correlation,
+            });
+        }
+    }
+
+    /// Pairs where bleed was found
+    pub fn finish(self) -> Vec<BleedPair> {
+        self.pairs
+            .into_iter()
+            .map(|(_, _, state)| state.pair)
+            .filter(|pair| !pair.segments.is_empty())
+            .collect()
+    }
+}
+
+struct BleedInput {
+    reader: TimelineReader<BufReader<File>>,
+    channels: u16,
+    finished: bool,
+}
+
+/// Look for every participant's voice leaking into the other tracks
+pub fn analyze_recording(metadata: &RecordingMetadata) -> RecordingResult<BleedReport> {
+    let mut participants: Vec<&ParticipantMetadata> = metadata
+        .participants
+        .values()
+        .filter(|p| p.audio_file.is_some())
+        .collect();
+    participants.sort_by_key(|p| p.joined_at);
+
+    let mut ids = Vec::with_capacity(participants.len());
+    let mut inputs = Vec::with_capacity(participants.len());
+    for participant in participants {
+        if let Some(path) = &participant.audio_file {
+            let decoder = OpusTrackDecoder::open(path)?;
+            let offset = metadata.participant_offset_seconds(participant);
+            ids.push(participant.id.clone());
+            inputs.push(BleedInput {
+                channels: decoder.channels(),
+                reader: TimelineReader::new(decoder, offset),
+                finished: false,
+            });
+        }
+    }
+
+    let mut detector = BleedDetector::new(&ids);
+    if inputs.len() > 1 {
+        log::info!("Detecting bleed between {} tracks", inputs.len());
+        let segment_frames = (BLEED_SEGMENT_SECONDS * OPUS_SAMPLE_RATE as f64) as usize;
+        let mut start_seconds = 0.0;
+
+        loop {
+            let mut segment = Vec::with_capacity(inputs.len());
+            for input in &mut inputs {
+                let block = if input.finished {
+                    None
+                } else {
+                    input.reader.read(segment_frames)?
+                };
+                input.finished = block.is_none();
+                let samples = block.unwrap_or_default();
+                segment.push(downmix_decimate(&samples, input.channels, BLEED_DECIMATION));
+            }
+            if inputs.iter().all(|i| i.finished) {
+                break;
+            }
+
+            detector.push_segment(start_seconds, &segment);
+            start_seconds += BLEED_SEGMENT_SECONDS;
+        }
+    }
+
+    Ok(BleedReport {
+        analyzed_at: Utc::now(),
+        pairs: detector.finish(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::noise;
+
+    #[test]
+    fn test_detects_delayed_attenuated_copy() {
+        let ids = vec!["p1".to_string(), "p2".to_string(), "p3".to_string()];
+        let mut detector = BleedDetector::new(&ids);
+        let segment_len = (BLEED_SEGMENT_SECONDS * BLEED_SAMPLE_RATE) as usize;
+
+        // p1 talks, p2 picks p1 up 10ms later at -20dB, p3 is unrelated
+        for index in 0..3 {
+            let voice = noise(segment_len + 80, index);
+            let p1 = voice[80..].to_vec();
+            let p2: Vec<f32> = voice[..segment_len].iter().map(|s| s * 0.1).collect();
+            let p3 = noise(segment_len, 100 + index);
+            detector.push_segment(index as f64 * BLEED_SEGMENT_SECONDS, &[p1, p2, p3]);
+        }
+
+        let pairs = detector.finish();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].source_participant_id, "p1");
+        assert_eq!(pairs[0].target_participant_id, "p2");
+
+        assert_eq!(pairs[0].segments.len(), 1);
+        let segment = &pairs[0].segments[0];
+        assert_eq!(segment.start_seconds, 0.0);
+        assert_eq!(segment.end_seconds, 3.0 * BLEED_SEGMENT_SECONDS);
+        assert!((segment.delay_ms - 10.0).abs() < 0.2, "{:?}", segment);
+        assert!((segment.level_db - -20.0).abs() < 0.5, "{:?}", segment);
+    }
+}
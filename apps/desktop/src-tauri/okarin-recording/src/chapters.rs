@@ -0,0 +1,239 @@
+use super::id3::{self, Id3Chapter};
+use super::types::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+pub const PODLOVE_CHAPTERS_FILENAME: &str = "chapters.json";
+pub const WEBVTT_CHAPTERS_FILENAME: &str = "chapters.vtt";
+pub const ID3_CHAPTERS_FILENAME: &str = "chapters.id3";
+
+/// A chapter marker turned into a span of the session timeline
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+/// Chapter lists written next to the metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChapterFiles {
+    pub podlove_json: PathBuf,
+    pub webvtt: PathBuf,
+    pub id3: PathBuf,
+}
+
+/// Chapter markers in order, each running until the next one
+pub fn chapters(metadata: &RecordingMetadata) -> Vec<Chapter> {
+    let mut markers: Vec<&Marker> = metadata
+        .markers
+        .iter()
+        .filter(|m| m.kind == MarkerKind::Chapter)
+        .collect();
+    markers.sort_by(|a, b| a.position_seconds.total_cmp(&b.position_seconds));
+
+    let length = metadata.session_length_seconds();
+    markers
+        .iter()
+        .enumerate()
+        .map(|(index, marker)| Chapter {
+            title: marker.label.clone(),
+            start_seconds: marker.position_seconds,
+            end_seconds: markers
+                .get(index + 1)
+                .map_or(length, |next| next.position_seconds)
+                .max(marker.position_seconds),
+        })
+        .collect()
+}
+
+/// `HH:MM:SS.mmm`, the normal play time used by Podlove and WebVTT
+fn timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[derive(Serialize)]
+struct PodloveChapter<'a> {
+    start: String,
+    title: &'a str,
+}
+
+/// Podlove Simple Chapters, JSON flavour used by the Podlove web player
+pub fn podlove_json(chapters: &[Chapter]) -> RecordingResult<String> {
+    let entries: Vec<PodloveChapter> = chapters
+        .iter()
+        .map(|c| PodloveChapter {
+            start: timestamp(c.start_seconds),
+            title: &c.title,
+        })
+        .collect();
+    serde_json::to_string_pretty(&entries)
+        .map_err(|e| RecordingError::IoError(std::io::Error::other(e)))
+}
+
+/// Version of the Podcasting 2.0 JSON chapters format written
+const PODCAST_CHAPTERS_VERSION: &str = "1.2.0";
+
+#[derive(Serialize)]
+struct PodcastChapters<'a> {
+    version: &'static str,
+    chapters: Vec<PodcastChapter<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PodcastChapter<'a> {
+    start_time: f64,
+    end_time: f64,
+    title: &'a str,
+}
+
+/// Podcasting 2.0 JSON chapters, linked from `podcast:chapters` in a feed
+pub fn podcast_chapters_json(chapters: &[Chapter]) -> RecordingResult<String> {
+    let document = PodcastChapters {
+        version: PODCAST_CHAPTERS_VERSION,
+        chapters: chapters
+            .iter()
+            .map(|c| PodcastChapter {
+                start_time: c.start_seconds,
+                end_time: c.end_seconds,
+                title: &c.title,
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&document)
+        .map_err(|e| RecordingError::IoError(std::io::Error::other(e)))
+}
+
+/// WebVTT chapters track
+pub fn webvtt(chapters: &[Chapter]) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for (index, chapter) in chapters.iter().enumerate() {
+        let title = chapter
+            .title
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        vtt.push_str(&format!(
+            "\n{}\n{} --> {}\n{}\n",
+            index + 1,
+            timestamp(chapter.start_seconds),
+            timestamp(chapter.end_seconds),
+            title
+        ));
+    }
+    vtt
+}
+
+/// CHAP/CTOC frames of the chapters, as embedded in exported audio
+pub fn id3_frames(chapters: &[Chapter]) -> Vec<u8> {
+    let ms = |seconds: f64| (seconds.max(0.0) * 1000.0).round() as u32;
+    let chapters: Vec<Id3Chapter> = chapters
+        .iter()
+        .map(|c| Id3Chapter {
+            title: c.title.clone(),
+            start_ms: ms(c.start_seconds),
+            end_ms: ms(c.end_seconds),
+        })
+        .collect();
+    id3::chapter_frames(&chapters)
+}
+
+/// Write every chapter list into the recording directory
+pub fn export_chapters(metadata: &RecordingMetadata) -> RecordingResult<ChapterFiles> {
+    let chapters = chapters(metadata);
+    let dir = &metadata.output_directory;
+    log::info!("Exporting {} chapters to {:?}", chapters.len(), dir);
+
+    let files = ChapterFiles {
+        podlove_json: dir.join(PODLOVE_CHAPTERS_FILENAME),
+        webvtt: dir.join(WEBVTT_CHAPTERS_FILENAME),
+        id3: dir.join(ID3_CHAPTERS_FILENAME),
+    };
+    fs::write(&files.podlove_json, podlove_json(&chapters)?)?;
+    fs::write(&files.webvtt, webvtt(&chapters))?;
+    fs::write(&files.id3, id3::tag(&id3_frames(&chapters)))?;
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::recording;
+    use chrono::Utc;
+
+    fn metadata_with_markers(markers: &[(&str, MarkerKind, f64)]) -> RecordingMetadata {
+        let started_at = Utc::now();
+        RecordingMetadata {
+            markers: markers
+                .iter()
+                .enumerate()
+                .map(|(index, &(label, kind, position_seconds))| Marker {
+                    id: format!("marker-{}", index + 1),
+                    label: label.to_string(),
+                    kind,
+                    position_seconds,
+                    created_at: started_at,
+                })
+                .collect(),
+            ..recording(started_at, 600)
+        }
+    }
+
+    #[test]
+    fn test_chapters_from_markers() {
+        let metadata = metadata_with_markers(&[
+            ("Segment 2", MarkerKind::Chapter, 245.5),
+            ("good quote", MarkerKind::Note, 100.0),
+            ("Intro", MarkerKind::Chapter, 0.0),
+        ]);
+
+        let chapters = chapters(&metadata);
+        assert_eq!(
+            chapters,
+            vec![
+                Chapter {
+                    title: "Intro".to_string(),
+                    start_seconds: 0.0,
+                    end_seconds: 245.5,
+                },
+                Chapter {
+                    title: "Segment 2".to_string(),
+                    start_seconds: 245.5,
+                    end_seconds: 600.0,
+                },
+            ]
+        );
+
+        assert_eq!(
+            webvtt(&chapters),
+            "WEBVTT\n\n1\n00:00:00.000 --> 00:04:05.500\nIntro\n\n\
+             2\n00:04:05.500 --> 00:10:00.000\nSegment 2\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&podlove_json(&chapters).unwrap()).unwrap();
+        assert_eq!(json[1]["start"], "00:04:05.500");
+        assert_eq!(json[1]["title"], "Segment 2");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&podcast_chapters_json(&chapters).unwrap()).unwrap();
+        assert_eq!(json["version"], "1.2.0");
+        assert_eq!(json["chapters"][1]["startTime"], 245.5);
+        assert_eq!(json["chapters"][1]["endTime"], 600.0);
+    }
+
+    #[test]
+    fn test_timestamp_rolls_over_hours() {
+        assert_eq!(timestamp(3725.0629), "01:02:05.063");
+    }
+}
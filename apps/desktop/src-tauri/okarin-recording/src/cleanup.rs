@@ -0,0 +1,447 @@
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::dsp::{linear_to_db, Biquad};
+use super::export::FormatWavWriter;
+use super::storage::derived_file_path;
+use super::types::*;
+use chrono::Utc;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+/// Longest transient repaired as a click (1ms), also the de-clicker look-ahead
+const MAX_CLICK_FRAMES: usize = 48;
+/// A click is a prediction error this many times the recent average
+const CLICK_THRESHOLD_RATIO: f64 = 8.0;
+/// Prediction errors under this level are never clicks
+const MIN_CLICK_LEVEL: f64 = 0.01;
+/// Averaging time of the prediction error
+const CLICK_RESIDUAL_SECONDS: f64 = 0.02;
+
+/// Release of the level detector feeding the expander
+const GATE_ENVELOPE_RELEASE_SECONDS: f64 = 0.05;
+const GATE_OPEN_SECONDS: f64 = 0.001;
+const GATE_CLOSE_SECONDS: f64 = 0.1;
+
+const CLEANUP_BLOCK_FRAMES: usize = 4800;
+
+fn smoothing(seconds: f64, sample_rate: u32) -> f64 {
+    (-1.0 / (seconds * sample_rate as f64)).exp()
+}
+
+/// Repairs short impulses (keyboard, mouth clicks) by interpolation
+///
+/// Every sample is predicted linearly from the two before it. A burst of
+/// prediction errors well above the recent average that ends within
+/// `MAX_CLICK_FRAMES` is a click; anything longer is a real transient
+/// (plosive, speech onset) and is left alone.
+struct DeClicker {
+    channels: usize,
+    /// Last two input samples of every channel
+    history: Vec<[f64; 2]>,
+    /// Mean square prediction error of every channel
+    residual: Vec<f64>,
+    smoothing: f64,
+    primed: usize,
+    delay: VecDeque<f32>,
+    flags: VecDeque<bool>,
+    /// Ignore flags until the current over-long transient is over
+    suppress: bool,
+    last_output: Vec<f32>,
+    repaired: u64,
+}
+
+impl DeClicker {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        Self {
+            channels,
+            history: vec![[0.0; 2]; channels],
+            residual: vec![0.0; channels],
+            smoothing: smoothing(CLICK_RESIDUAL_SECONDS, sample_rate),
+            primed: 0,
+            delay: VecDeque::new(),
+            flags: VecDeque::new(),
+            suppress: false,
+            last_output: vec![0.0; channels],
+            repaired: 0,
+        }
+    }
+
+    fn process_frame(&mut self, frame: &[f32], output: &mut Vec<f32>) {
+        let mut click = false;
+        for (channel, &sample) in frame.iter().enumerate() {
+            let sample = sample as f64;
+            let [previous, before] = self.history[channel];
+            let error = sample - (2.0 * previous - before);
+            let threshold =
+                MIN_CLICK_LEVEL.max(CLICK_THRESHOLD_RATIO * self.residual[channel].sqrt());
+
+            if self.primed >= 2 && error.abs() > threshold {
+                click = true;
+            }
+            // Clipped so that a click barely moves the average
+            let power = (error * error).min(threshold * threshold);
+            self.residual[channel] = power + (self.residual[channel] - power) * self.smoothing;
+            self.history[channel] = [sample, previous];
+        }
+        self.primed = (self.primed + 1).min(2);
+
+        if self.suppress {
+            self.suppress = click;
+            click = false;
+        }
+        self.delay.extend(frame.iter().copied());
+        self.flags.push_back(click);
+
+        if self.flags.len() > MAX_CLICK_FRAMES {
+            self.pop_frame(output, true);
+        }
+    }
+
+    fn flush(&mut self, output: &mut Vec<f32>) {
+        while !self.flags.is_empty() {
+            self.pop_frame(output, false);
+        }
+    }
+
+    fn pop_frame(&mut self, output: &mut Vec<f32>, streaming: bool) {
+        if self.flags.front() == Some(&true) {
+            match self.flags.iter().position(|&flag| !flag) {
+                Some(end) => self.repair(end),
+                None => {
+                    // Too long for a click, let it through untouched
+                    self.flags.iter_mut().for_each(|flag| *flag = false);
+                    self.suppress = streaming;
+                }
+            }
+        }
+
+        self.flags.pop_front();
+        for channel in 0..self.channels {
+            let sample = self.delay.pop_front().unwrap_or(0.0);
+            self.last_output[channel] = sample;
+            output.push(sample);
+        }
+    }
+
+    /// Interpolate the first `end` frames between their clean neighbours
+    fn repair(&mut self, end: usize) {
+        for channel in 0..self.channels {
+            let from = self.last_output[channel];
+            let to = self.delay[end * self.channels + channel];
+            for frame in 0..end {
+                let t = (frame + 1) as f32 / (end + 1) as f32;
+                self.delay[frame * self.channels + channel] = from + (to - from) * t;
+            }
+        }
+        self.flags
+            .iter_mut()
+            .take(end)
+            .for_each(|flag| *flag = false);
+        self.repaired += 1;
+    }
+}
+
+/// Downward expander, channels linked; a high ratio turns it into a gate
+struct Expander {
+    threshold_db: f64,
+    ratio: f64,
+    range_db: f64,
+    envelope_release: f64,
+    open: f64,
+    close: f64,
+    envelope: f64,
+    gain_db: f64,
+}
+
+impl Expander {
+    fn new(settings: &CleanupSettings, sample_rate: u32) -> Self {
+        Self {
+            threshold_db: settings.gate_threshold_db,
+            ratio: settings.gate_ratio.max(1.0),
+            range_db: settings.gate_range_db.max(0.0),
+            envelope_release: smoothing(GATE_ENVELOPE_RELEASE_SECONDS, sample_rate),
+            open: smoothing(GATE_OPEN_SECONDS, sample_rate),
+            close: smoothing(GATE_CLOSE_SECONDS, sample_rate),
+            envelope: 0.0,
+            gain_db: 0.0,
+        }
+    }
+
+    fn process_frame(&mut self, frame: &mut [f32]) {
+        let peak = frame.iter().fold(0f64, |m, s| m.max(s.abs() as f64));
+        self.envelope = if peak > self.envelope {
+            peak
+        } else {
+            peak + (self.envelope - peak) * self.envelope_release
+        };
+
+        let level_db = linear_to_db(self.envelope.max(1e-10));
+        let target_db = if level_db < self.threshold_db {
+            ((level_db - self.threshold_db) * (self.ratio - 1.0)).max(-self.range_db)
+        } else {
+            0.0
+        };
+        let coefficient = if target_db > self.gain_db {
+            self.open
+        } else {
+            self.close
+        };
+        self.gain_db = target_db + (self.gain_db - target_db) * coefficient;
+
+        if self.gain_db < 0.0 {
+            let gain = 10f64.powf(self.gain_db / 20.0) as f32;
+            frame.iter_mut().for_each(|s| *s *= gain);
+        }
+    }
+}
+
+/// High-pass, de-click and expander applied in that order
+pub struct CleanupChain {
+    channels: usize,
+    high_pass: Vec<Biquad>,
+    de_clicker: Option<DeClicker>,
+    expander: Expander,
+    filtered: Vec<f32>,
+    declicked: Vec<f32>,
+}
+
+impl CleanupChain {
+    pub fn new(channels: u16, sample_rate: u32, settings: &CleanupSettings) -> Self {
+        let channels = channels.max(1) as usize;
+        let high_pass = if settings.high_pass_hz > 0.0 {
+            vec![Biquad::high_pass(settings.high_pass_hz, sample_rate); channels]
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            channels,
+            high_pass,
+            de_clicker: settings
+                .de_click
+                .then(|| DeClicker::new(channels, sample_rate)),
+            expander: Expander::new(settings, sample_rate),
+            filtered: Vec::new(),
+            declicked: Vec::new(),
+        }
+    }
+
+    /// Number of clicks interpolated so far
+    pub fn clicks_repaired(&self) -> u64 {
+        self.de_clicker.as_ref().map_or(0, |d| d.repaired)
+    }
+
+    /// Process interleaved samples, appending the (delayed) output
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        self.filtered.clear();
+        if self.high_pass.is_empty() {
+            self.filtered.extend_from_slice(input);
+        } else {
+            for frame in input.chunks_exact(self.channels) {
+                for (sample, filter) in frame.iter().zip(&mut self.high_pass) {
+                    self.filtered.push(filter.process(*sample as f64) as f32);
+                }
+            }
+        }
+
+        self.declicked.clear();
+        match &mut self.de_clicker {
+            Some(de_clicker) => {
+                for frame in self.filtered.chunks_exact(self.channels) {
+                    de_clicker.process_frame(frame, &mut self.declicked);
+                }
+            }
+            None => self.declicked.extend_from_slice(&self.filtered),
+        }
+
+        self.expand(output);
+    }
+
+    /// Push the de-clicker look-ahead out of the chain
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        self.declicked.clear();
+        if let Some(de_clicker) = &mut self.de_clicker {
+            de_clicker.flush(&mut self.declicked);
+        }
+        self.expand(output);
+    }
+
+    fn expand(&mut self, output: &mut Vec<f32>) {
+        for frame in self.declicked.chunks_exact_mut(self.channels) {
+            self.expander.process_frame(frame);
+        }
+        output.extend_from_slice(&self.declicked);
+    }
+}
+
+/// Write a cleaned-up WAV copy of one recorded audio file in the `target` format
+pub fn cleanup_file(
+    source: &Path,
+    output: &Path,
+    settings: &CleanupSettings,
+    target: &MediaFormat,
+) -> RecordingResult<CleanedTrack> {
+    let decoder = OpusTrackDecoder::open(source)?;
+    let channels = decoder.channels();
+    let mut reader = TimelineReader::new(decoder, 0.0);
+    let mut chain = CleanupChain::new(channels, OPUS_SAMPLE_RATE, settings);
+    let mut writer = FormatWavWriter::create(output, channels, target)?;
+    let mut cleaned = Vec::with_capacity(CLEANUP_BLOCK_FRAMES * channels as usize);
+
+    while let Some(block) = reader.read(CLEANUP_BLOCK_FRAMES)? {
+        cleaned.clear();
+        chain.process(&block, &mut cleaned);
+        writer.write(&cleaned)?;
+    }
+    cleaned.clear();
+    chain.flush(&mut cleaned);
+    writer.write(&cleaned)?;
+
+    Ok(CleanedTrack {
+        output_file: writer.finalize()?,
+        settings: settings.clone(),
+        clicks_repaired: chain.clicks_repaired(),
+    })
+}
+
+/// Export cleaned-up copies of every participant's audio
+pub fn cleanup_recording(
+    metadata: &RecordingMetadata,
+    options: &CleanupOptions,
+) -> RecordingResult<CleanupExport> {
+    let default_settings = CleanupSettings::default();
+    let target = metadata.export_format();
+    let mut tracks = HashMap::new();
+
+    for (participant_id, participant) in &metadata.participants {
+        let source = match &participant.audio_file {
+            Some(path) => path,
+            None => continue,
+        };
+        let settings = options
+            .tracks
+            .get(participant_id)
+            .unwrap_or(&default_settings);
+
+        log::info!("Cleaning up audio for participant {}", participant_id);
+        let output = derived_file_path(source, "cleaned", "wav");
+        let track = cleanup_file(source, &output, settings, &target)?;
+        tracks.insert(participant_id.clone(), track);
+    }
+
+    Ok(CleanupExport {
+        exported_at: Utc::now(),
+        tracks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, sine, temp_dir};
+
+    fn rms(samples: &[f32]) -> f64 {
+        (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    fn run(settings: &CleanupSettings, input: &[f32]) -> (Vec<f32>, u64) {
+        let mut chain = CleanupChain::new(1, 48000, settings);
+        let mut output = Vec::new();
+        chain.process(input, &mut output);
+        chain.flush(&mut output);
+        assert_eq!(output.len(), input.len());
+        (output, chain.clicks_repaired())
+    }
+
+    fn only(high_pass_hz: f64, de_click: bool, gate_range_db: f64) -> CleanupSettings {
+        CleanupSettings {
+            high_pass_hz,
+            de_click,
+            gate_range_db,
+            ..CleanupSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_high_pass_removes_rumble() {
+        let settings = only(80.0, false, 0.0);
+        let (rumble, _) = run(&settings, &sine(20.0, 0.5, 48000));
+        let (voice, _) = run(&settings, &sine(1000.0, 0.5, 48000));
+
+        assert!(rms(&rumble[24000..]) < 0.5 / 2f64.sqrt() * 0.1);
+        assert!((rms(&voice[24000..]) / (0.5 / 2f64.sqrt()) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_de_click_interpolates_impulses() {
+        let clean = sine(440.0, 0.3, 48000);
+        let mut input = clean.clone();
+        for position in [5000, 20000, 35000] {
+            input[position] += 0.8;
+        }
+
+        let (output, repaired) = run(&only(0.0, true, 0.0), &input);
+        assert_eq!(repaired, 3);
+        let error = output
+            .iter()
+            .zip(&clean)
+            .fold(0f32, |m, (a, b)| m.max((a - b).abs()));
+        assert!(error < 0.01, "error: {}", error);
+    }
+
+    #[test]
+    fn test_expander_attenuates_below_threshold() {
+        let settings = only(0.0, false, 30.0);
+        let (quiet, _) = run(&settings, &sine(440.0, 0.001, 48000));
+        let (loud, _) = run(&settings, &sine(440.0, 0.5, 48000));
+
+        let reduction = linear_to_db(rms(&quiet[24000..]) / (0.001 / 2f64.sqrt()));
+        assert!((reduction - -30.0).abs() < 1.0, "reduction: {}", reduction);
+        let error = loud[4800..]
+            .iter()
+            .zip(&sine(440.0, 0.5, 48000)[4800..])
+            .fold(0f32, |m, (a, b)| m.max((a - b).abs()));
+        assert!(error < 1e-4, "error: {}", error);
+    }
+
+    #[test]
+    fn test_cleanup_file_keeps_length() {
+        let source = fixture();
+        let dir = temp_dir("cleanup");
+        let output = dir.join("cleaned.wav");
+        let track = cleanup_file(
+            &source,
+            &output,
+            &CleanupSettings::default(),
+            &MediaFormat::default(),
+        )
+        .unwrap();
+
+        let reader = hound::WavReader::open(&output).unwrap();
+        let frames = reader.duration();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(frames, 95688);
+        assert_eq!(track.clicks_repaired, 0);
+    }
+
+    #[test]
+    fn test_cleanup_file_writes_target_format() {
+        let dir = temp_dir("cleanup-target");
+        let output = dir.join("cleaned.wav");
+        let target = MediaFormat {
+            audio_sample_rate: Some(24000),
+            audio_channels: Some(2),
+            ..Default::default()
+        };
+        cleanup_file(&fixture(), &output, &CleanupSettings::default(), &target).unwrap();
+
+        let reader = hound::WavReader::open(&output).unwrap();
+        let spec = reader.spec();
+        let frames = reader.duration();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(spec.sample_rate, 24000);
+        assert_eq!(spec.channels, 2);
+        assert_eq!(frames, 95688 / 2);
+    }
+}
@@ -0,0 +1,204 @@
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    fn conj(self) -> Complex {
+        Complex {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+}
+
+/// In-place iterative radix-2 FFT, `buffer.len()` must be a power of two
+fn fft(buffer: &mut [Complex], inverse: bool) {
+    let n = buffer.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as f64;
+        let step = Complex {
+            re: angle.cos(),
+            im: angle.sin(),
+        };
+        for start in (0..n).step_by(len) {
+            let mut w = Complex { re: 1.0, im: 0.0 };
+            for k in 0..len / 2 {
+                let even = buffer[start + k];
+                let odd = buffer[start + k + len / 2].mul(w);
+                buffer[start + k] = Complex {
+                    re: even.re + odd.re,
+                    im: even.im + odd.im,
+                };
+                buffer[start + k + len / 2] = Complex {
+                    re: even.re - odd.re,
+                    im: even.im - odd.im,
+                };
+                w = w.mul(step);
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        let scale = 1.0 / n as f64;
+        for value in buffer.iter_mut() {
+            value.re *= scale;
+            value.im *= scale;
+        }
+    }
+}
+
+fn to_complex(samples: &[f32], len: usize) -> Vec<Complex> {
+    let mut buffer = vec![Complex::default(); len];
+    for (out, &sample) in buffer.iter_mut().zip(samples) {
+        out.re = sample as f64;
+    }
+    buffer
+}
+
+/// Cross-correlation of `signal` against `reference` for every lag in
+/// `min_lag..=max_lag`
+///
+/// A positive lag means `signal` lags behind `reference`: the value at lag
+/// `k` is the sum of `reference[n] * signal[n + k]`.
+pub fn cross_correlation(
+    reference: &[f32],
+    signal: &[f32],
+    min_lag: isize,
+    max_lag: isize,
+) -> Vec<f64> {
+    let len = (reference.len() + signal.len()).max(1).next_power_of_two();
+    let mut spectrum = to_complex(reference, len);
+    let mut signal_spectrum = to_complex(signal, len);
+    fft(&mut spectrum, false);
+    fft(&mut signal_spectrum, false);
+
+    for (r, s) in spectrum.iter_mut().zip(&signal_spectrum) {
+        *r = r.conj().mul(*s);
+    }
+    fft(&mut spectrum, true);
+
+    (min_lag..=max_lag)
+        .map(|lag| {
+            if lag.unsigned_abs() >= len {
+                0.0
+            } else {
+                spectrum[lag.rem_euclid(len as isize) as usize].re
+            }
+        })
+        .collect()
+}
+
+/// Best alignment of `signal` against `reference`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LagEstimate {
+    /// Samples by which `signal` lags behind `reference`
+    pub lag: isize,
+    /// Normalized correlation at that lag, 1.0 for an exact (scaled) copy
+    pub correlation: f64,
+    /// Least-squares gain of the reference found in the signal
+    pub gain: f64,
+}
+
+/// Find the lag in `min_lag..=max_lag` where `signal` best matches a scaled
+/// copy of `reference`; None when either input is silent
+pub fn estimate_lag(
+    reference: &[f32],
+    signal: &[f32],
+    min_lag: isize,
+    max_lag: isize,
+) -> Option<LagEstimate> {
+    let energy = |s: &[f32]| s.iter().map(|&x| (x as f64) * (x as f64)).sum::<f64>();
+    let (reference_energy, signal_energy) = (energy(reference), energy(signal));
+    if reference_energy <= 0.0 || signal_energy <= 0.0 {
+        return None;
+    }
+
+    let correlation = cross_correlation(reference, signal, min_lag, max_lag);
+    let (index, peak) = correlation
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    Some(LagEstimate {
+        lag: min_lag + index as isize,
+        correlation: peak / (reference_energy * signal_energy).sqrt(),
+        gain: peak / reference_energy,
+    })
+}
+
+/// Average interleaved samples to mono and keep one value every `factor`
+///
+/// The box average doubles as a crude anti-aliasing filter, which is enough
+/// for correlation work where only the envelope of the waveform matters.
+pub fn downmix_decimate(samples: &[f32], channels: u16, factor: usize) -> Vec<f32> {
+    let step = channels.max(1) as usize * factor.max(1);
+    samples
+        .chunks(step)
+        .map(|chunk| chunk.iter().sum::<f32>() / chunk.len() as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::noise;
+
+    #[test]
+    fn test_estimate_lag_of_delayed_copy() {
+        let reference = noise(4000, 7);
+        let mut signal = vec![0.0; 37];
+        signal.extend(reference.iter().map(|s| s * 0.3));
+        signal.truncate(reference.len());
+
+        let estimate = estimate_lag(&reference, &signal, -100, 100).unwrap();
+        assert_eq!(estimate.lag, 37);
+        assert!(estimate.correlation > 0.95, "{:?}", estimate);
+        assert!((estimate.gain - 0.3).abs() < 0.02, "{:?}", estimate);
+
+        // Reversed roles give the opposite lag
+        let estimate = estimate_lag(&signal, &reference, -100, 100).unwrap();
+        assert_eq!(estimate.lag, -37);
+    }
+
+    #[test]
+    fn test_estimate_lag_of_unrelated_signals() {
+        let estimate = estimate_lag(&noise(4000, 1), &noise(4000, 2), -100, 100).unwrap();
+        assert!(estimate.correlation < 0.1, "{:?}", estimate);
+        assert!(estimate_lag(&noise(100, 1), &[0.0; 100], 0, 10).is_none());
+    }
+
+    #[test]
+    fn test_downmix_decimate() {
+        let samples = [0.5, 0.25, 0.25, 0.0, -1.0, 0.0, 0.0, 0.0];
+        assert_eq!(downmix_decimate(&samples, 2, 2), vec![0.25, -0.25]);
+    }
+}
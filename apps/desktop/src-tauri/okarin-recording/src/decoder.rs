@@ -0,0 +1,471 @@
+use super::types::*;
+use super::webm::{TrackInfo, TrackKind, WebmBlock, WebmReader};
+use audiopus::coder::Decoder;
+use audiopus::packet::Packet;
+use audiopus::{Channels, MutSignals, SampleRate};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Opus always decodes at 48kHz, whatever input rate the OpusHead advertises
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+const OPUS_CODEC_ID: &str = "A_OPUS";
+
+/// Largest Opus packet duration (120ms at 48kHz), per channel
+const MAX_FRAME_SAMPLES: usize = 5760;
+
+/// Length assumed for a lost packet before any packet was decoded (20ms)
+const DEFAULT_FRAME_SAMPLES: usize = 960;
+
+/// Gaps shorter than this (in samples) are timestamp jitter, not missing audio
+pub const GAP_TOLERANCE_SAMPLES: u64 = 480;
+
+/// Opus identification header, stored in the Matroska CodecPrivate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusHead {
+    pub version: u8,
+    pub channels: u8,
+    pub pre_skip: u16,
+    pub input_sample_rate: u32,
+    pub output_gain: i16,
+    pub mapping_family: u8,
+}
+
+impl OpusHead {
+    pub fn parse(data: &[u8]) -> RecordingResult<Self> {
+        if data.len() < 19 || &data[..8] != b"OpusHead" {
+            return Err(RecordingError::DecodeError(
+                "missing or invalid OpusHead".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            version: data[8],
+            channels: data[9],
+            pre_skip: u16::from_le_bytes([data[10], data[11]]),
+            input_sample_rate: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            output_gain: i16::from_le_bytes([data[16], data[17]]),
+            mapping_family: data[18],
+        })
+    }
+
+    /// Serialize for a Matroska CodecPrivate or an Ogg identification packet
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = b"OpusHead".to_vec();
+        data.push(self.version);
+        data.push(self.channels);
+        data.extend_from_slice(&self.pre_skip.to_le_bytes());
+        data.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+        data.extend_from_slice(&self.output_gain.to_le_bytes());
+        data.push(self.mapping_family);
+        data
+    }
+
+    /// Linear gain to apply to decoded samples (output_gain is Q7.8 dB)
+    fn gain_factor(&self) -> f32 {
+        10f32.powf(self.output_gain as f32 / (20.0 * 256.0))
+    }
+}
+
+/// A block of decoded, interleaved PCM samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcmFrame {
+    /// Position of the first sample, in seconds from the start of the track
+    pub timestamp: f64,
+    pub samples: Vec<f32>,
+}
+
+/// A fully decoded track, interleaved f32 samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcmBuffer {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl PcmBuffer {
+    /// Number of samples per channel
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration_seconds(&self) -> f64 {
+        self.frame_count() as f64 / self.sample_rate as f64
+    }
+}
+
+/// Decodes the Opus packets of one WebM track, block by block
+///
+/// Shared by the file decoder and the live level meter, which only ever sees
+/// the recording as a stream of chunks.
+pub struct OpusBlockDecoder {
+    head: OpusHead,
+    decoder: Decoder,
+    buffer: Vec<f32>,
+    skip_remaining: usize,
+    /// Samples per channel of the last decoded packet, the length given to a lost one
+    last_frame_samples: usize,
+}
+
+impl OpusBlockDecoder {
+    /// Pick the Opus audio track out of a WebM track list
+    pub fn find_track(tracks: &[TrackInfo]) -> Option<&TrackInfo> {
+        tracks
+            .iter()
+            .find(|t| t.kind == TrackKind::Audio && t.codec_id == OPUS_CODEC_ID)
+    }
+
+    pub fn new(track: &TrackInfo) -> RecordingResult<Self> {
+        let head = OpusHead::parse(track.codec_private.as_deref().unwrap_or_default())?;
+        let channels = match (head.mapping_family, head.channels) {
+            (0, 1) => Channels::Mono,
+            (0, 2) => Channels::Stereo,
+            (family, count) => {
+                return Err(RecordingError::DecodeError(format!(
+                    "unsupported Opus channel mapping {} with {} channels",
+                    family, count
+                )))
+            }
+        };
+
+        let decoder = Decoder::new(SampleRate::Hz48000, channels)
+            .map_err(|e| RecordingError::DecodeError(e.to_string()))?;
+
+        Ok(Self {
+            skip_remaining: head.pre_skip as usize,
+            last_frame_samples: DEFAULT_FRAME_SAMPLES,
+            buffer: vec![0.0; MAX_FRAME_SAMPLES * head.channels as usize],
+            head,
+            decoder,
+        })
+    }
+
+    pub fn head(&self) -> &OpusHead {
+        &self.head
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.head.channels as u16
+    }
+
+    /// Decode every packet of a block, appending the non-empty PCM frames
+    pub fn decode_block(
+        &mut self,
+        block: &WebmBlock,
+        output: &mut VecDeque<PcmFrame>,
+    ) -> RecordingResult<()> {
+        let channels = self.head.channels as usize;
+        let mut timestamp = block.timestamp_seconds();
+
+        for packet in &block.frames {
+            // A single corrupt packet must not cost the rest of the track
+            let decoded = match self.decode_packet(packet) {
+                Ok(decoded) => {
+                    self.last_frame_samples = decoded;
+                    decoded
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Concealing a corrupt Opus packet at {:.3}s: {}",
+                        timestamp,
+                        e
+                    );
+                    self.conceal_packet()
+                }
+            };
+
+            // Drop the encoder priming samples announced by pre-skip
+            let skip = self.skip_remaining.min(decoded);
+            self.skip_remaining -= skip;
+
+            let gain = self.head.gain_factor();
+            let samples: Vec<f32> = self.buffer[skip * channels..decoded * channels]
+                .iter()
+                .map(|s| s * gain)
+                .collect();
+
+            let frame_timestamp = timestamp + skip as f64 / OPUS_SAMPLE_RATE as f64;
+            timestamp += decoded as f64 / OPUS_SAMPLE_RATE as f64;
+
+            if !samples.is_empty() {
+                output.push_back(PcmFrame {
+                    timestamp: frame_timestamp,
+                    samples,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decode one packet into the buffer, returning the samples per channel
+    fn decode_packet(&mut self, packet: &[u8]) -> RecordingResult<usize> {
+        let packet =
+            Packet::try_from(packet).map_err(|e| RecordingError::DecodeError(e.to_string()))?;
+        let signals = MutSignals::try_from(self.buffer.as_mut_slice())
+            .map_err(|e| RecordingError::DecodeError(e.to_string()))?;
+        self.decoder
+            .decode_float(Some(packet), signals, false)
+            .map_err(|e| RecordingError::DecodeError(e.to_string()))
+    }
+
+    /// Fill the buffer in for a lost packet, using the decoder's packet loss
+    /// concealment and falling back to silence
+    fn conceal_packet(&mut self) -> usize {
+        let frame_samples = self.last_frame_samples;
+        let len = frame_samples * self.head.channels as usize;
+        let concealed = MutSignals::try_from(&mut self.buffer[..len])
+            .map_err(|e| e.to_string())
+            .and_then(|signals| {
+                self.decoder
+                    .decode_float(None::<Packet>, signals, false)
+                    .map_err(|e| e.to_string())
+            });
+        match concealed {
+            Ok(decoded) => decoded,
+            Err(_) => {
+                self.buffer[..len].fill(0.0);
+                frame_samples
+            }
+        }
+    }
+}
+
+/// Streams decoded PCM out of the Opus track of a recorded WebM file
+pub struct OpusTrackDecoder<R> {
+    reader: WebmReader<R>,
+    track_number: u64,
+    decoder: OpusBlockDecoder,
+    pending: VecDeque<PcmFrame>,
+}
+
+impl OpusTrackDecoder<BufReader<File>> {
+    /// Open a participant's recorded audio file
+    pub fn open(path: &Path) -> RecordingResult<Self> {
+        Self::new(WebmReader::open(path)?)
+    }
+}
+
+impl<R: Read> OpusTrackDecoder<R> {
+    pub fn new(mut reader: WebmReader<R>) -> RecordingResult<Self> {
+        let tracks = reader.read_tracks()?;
+        let track = OpusBlockDecoder::find_track(&tracks)
+            .ok_or_else(|| RecordingError::DecodeError("no Opus audio track".to_string()))?;
+
+        Ok(Self {
+            track_number: track.number,
+            decoder: OpusBlockDecoder::new(track)?,
+            reader,
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub fn head(&self) -> &OpusHead {
+        self.decoder.head()
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        OPUS_SAMPLE_RATE
+    }
+
+    /// Decode the whole track, filling timestamp gaps with silence so that
+    /// sample positions line up with the recording timeline
+    pub fn decode_all(self) -> RecordingResult<PcmBuffer> {
+        let channels = self.channels();
+        let mut reader = TimelineReader::new(self, 0.0);
+        let mut samples: Vec<f32> = Vec::new();
+
+        while let Some(block) = reader.read(OPUS_SAMPLE_RATE as usize)? {
+            samples.extend_from_slice(&block);
+        }
+
+        Ok(PcmBuffer {
+            sample_rate: OPUS_SAMPLE_RATE,
+            channels,
+            samples,
+        })
+    }
+
+    fn decode_next(&mut self) -> RecordingResult<Option<PcmFrame>> {
+        while let Some(block) = self.reader.next_block()? {
+            if block.track_number != self.track_number {
+                continue;
+            }
+
+            self.decoder.decode_block(&block, &mut self.pending)?;
+            if let Some(frame) = self.pending.pop_front() {
+                return Ok(Some(frame));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for OpusTrackDecoder<R> {
+    type Item = RecordingResult<PcmFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(frame) = self.pending.pop_front() {
+            return Some(Ok(frame));
+        }
+        self.decode_next().transpose()
+    }
+}
+
+/// Reads a decoded track as contiguous blocks placed on a timeline
+///
+/// The leading offset and any gap between packet timestamps are filled with
+/// silence, so the n-th frame returned is always at `n / 48000` seconds on
+/// the timeline. This is what lets several tracks be processed side by side
+/// without holding them fully in memory.
+pub struct TimelineReader<R> {
+    decoder: OpusTrackDecoder<R>,
+    channels: usize,
+    offset: f64,
+    queued_frames: u64,
+    queue: Vec<f32>,
+    finished: bool,
+}
+
+impl<R: Read> TimelineReader<R> {
+    pub fn new(decoder: OpusTrackDecoder<R>, offset_seconds: f64) -> Self {
+        Self {
+            channels: decoder.channels() as usize,
+            decoder,
+            offset: offset_seconds.max(0.0),
+            queued_frames: 0,
+            queue: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    /// Read up to `frames` frames of interleaved samples, None once exhausted
+    pub fn read(&mut self, frames: usize) -> RecordingResult<Option<Vec<f32>>> {
+        let wanted = frames * self.channels;
+
+        while self.queue.len() < wanted && !self.finished {
+            match self.decoder.next() {
+                Some(frame) => {
+                    let frame = frame?;
+                    let expected = ((self.offset + frame.timestamp) * OPUS_SAMPLE_RATE as f64)
+                        .round()
+                        .max(0.0) as u64;
+                    if expected > self.queued_frames + GAP_TOLERANCE_SAMPLES {
+                        let gap = (expected - self.queued_frames) as usize;
+                        self.queue
+                            .resize(self.queue.len() + gap * self.channels, 0.0);
+                        self.queued_frames = expected;
+                    }
+                    self.queued_frames += (frame.samples.len() / self.channels) as u64;
+                    self.queue.extend_from_slice(&frame.samples);
+                }
+                None => self.finished = true,
+            }
+        }
+
+        if self.queue.is_empty() {
+            return Ok(None);
+        }
+
+        let take = wanted.min(self.queue.len());
+        Ok(Some(self.queue.drain(..take).collect()))
+    }
+}
+
+/// Decode a recorded participant audio file to PCM
+pub fn decode_file(path: &Path) -> RecordingResult<PcmBuffer> {
+    OpusTrackDecoder::open(path)?.decode_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    #[test]
+    fn test_opus_head_parse() {
+        let mut data = b"OpusHead".to_vec();
+        data.extend_from_slice(&[1, 2, 0x38, 0x01, 0x80, 0xBB, 0, 0, 0, 0, 0]);
+
+        let head = OpusHead::parse(&data).unwrap();
+        assert_eq!(head.channels, 2);
+        assert_eq!(head.pre_skip, 312);
+        assert_eq!(head.input_sample_rate, 48000);
+        assert_eq!(head.mapping_family, 0);
+        assert_eq!(head.to_bytes(), data[..19]);
+
+        assert!(OpusHead::parse(b"OpusTags").is_err());
+    }
+
+    #[test]
+    fn test_decode_fixture() {
+        let pcm = decode_file(&fixture()).unwrap();
+
+        assert_eq!(pcm.sample_rate, 48000);
+        assert_eq!(pcm.channels, 1);
+        assert!((pcm.duration_seconds() - 2.0).abs() < 0.05);
+
+        // A 440Hz sine crosses zero upwards 440 times per second
+        let settled = &pcm.samples[4800..4800 + 48000];
+        let crossings = settled
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        assert!((438..=442).contains(&crossings), "crossings: {}", crossings);
+
+        let peak = settled.iter().fold(0f32, |m, s| m.max(s.abs()));
+        assert!((0.4..0.6).contains(&peak), "peak: {}", peak);
+    }
+
+    #[test]
+    fn test_decoder_streams_frames_in_order() {
+        let decoder = OpusTrackDecoder::open(&fixture()).unwrap();
+        assert_eq!(decoder.channels(), 1);
+
+        let mut last = -1.0;
+        let mut count = 0;
+        for frame in decoder {
+            let frame = frame.unwrap();
+            assert!(frame.timestamp > last);
+            last = frame.timestamp;
+            count += 1;
+        }
+        assert_eq!(count, 100);
+    }
+
+    #[test]
+    fn test_corrupt_packet_is_concealed() {
+        let mut reader = WebmReader::open(&fixture()).unwrap();
+        let tracks = reader.read_tracks().unwrap();
+        let track = OpusBlockDecoder::find_track(&tracks).unwrap().clone();
+        let mut decoder = OpusBlockDecoder::new(&track).unwrap();
+
+        let mut frames = VecDeque::new();
+        let mut blocks = std::iter::from_fn(|| reader.next_block().unwrap());
+        let first = blocks.next().unwrap();
+        let mut corrupt = blocks.next().unwrap();
+        let next = blocks.next().unwrap();
+        corrupt.frames = vec![Vec::new()];
+
+        decoder.decode_block(&first, &mut frames).unwrap();
+        decoder.decode_block(&corrupt, &mut frames).unwrap();
+        decoder.decode_block(&next, &mut frames).unwrap();
+
+        // The lost packet keeps its place on the timeline and decoding goes on
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].samples.len(), frames[2].samples.len());
+        assert!((frames[2].timestamp - next.timestamp_seconds()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,237 @@
+use super::loudness::TruePeakDetector;
+use std::collections::VecDeque;
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+/// Limiter look-ahead, also the attack ramp length (5ms at 48kHz)
+const LIMITER_LOOKAHEAD_FRAMES: usize = 240;
+/// Spread of the true-peak estimate around the sample that caused it
+const PEAK_DETECTOR_SPREAD_FRAMES: usize = 12;
+const LIMITER_RELEASE_SECONDS: f64 = 0.1;
+
+/// Q of the four second-order sections of an 8th-order Butterworth
+pub const BUTTERWORTH_8_Q: [f64; 4] = [0.5098, 0.6013, 0.9000, 2.5629];
+
+pub fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+pub fn linear_to_db(linear: f64) -> f64 {
+    20.0 * linear.log10()
+}
+
+/// Second-order IIR section (direct form I)
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    pub fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// Butterworth high-pass (RBJ cookbook, Q = 1/sqrt(2))
+    pub fn high_pass(cutoff_hz: f64, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self::new(
+            [
+                (1.0 + cos) / 2.0 / a0,
+                -(1.0 + cos) / a0,
+                (1.0 + cos) / 2.0 / a0,
+            ],
+            [1.0, -2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    /// Low-pass of quality `q` (RBJ cookbook)
+    ///
+    /// Sections with the Q values of `BUTTERWORTH_8_Q` in series make an
+    /// 8th-order Butterworth.
+    pub fn low_pass(cutoff_hz: f64, sample_rate: u32, q: f64) -> Self {
+        let w0 = 2.0 * PI * cutoff_hz / sample_rate as f64;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self::new(
+            [
+                (1.0 - cos) / 2.0 / a0,
+                (1.0 - cos) / a0,
+                (1.0 - cos) / 2.0 / a0,
+            ],
+            [1.0, -2.0 * cos / a0, (1.0 - alpha) / a0],
+        )
+    }
+
+    pub fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// Look-ahead brickwall limiter keeping true peaks under a ceiling
+///
+/// The gain needed for every frame is held over the look-ahead window and
+/// then averaged over the attack length, which guarantees the gain has fully
+/// ramped down by the time the peak leaves the delay line.
+pub struct Limiter {
+    channels: usize,
+    ceiling: f64,
+    release: f64,
+    detector: TruePeakDetector,
+    delay: VecDeque<f32>,
+    delay_frames: usize,
+    hold: VecDeque<(u64, f64)>,
+    hold_frames: u64,
+    envelope: f64,
+    attack: VecDeque<f64>,
+    attack_sum: f64,
+    frame_index: u64,
+    min_gain: f64,
+}
+
+impl Limiter {
+    pub fn new(channels: u16, sample_rate: u32, ceiling_db: f64) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            ceiling: db_to_linear(ceiling_db),
+            release: (-1.0 / (LIMITER_RELEASE_SECONDS * sample_rate as f64)).exp(),
+            detector: TruePeakDetector::new(channels as u16),
+            delay: VecDeque::new(),
+            delay_frames: LIMITER_LOOKAHEAD_FRAMES - 1 + PEAK_DETECTOR_SPREAD_FRAMES,
+            hold: VecDeque::new(),
+            hold_frames: (LIMITER_LOOKAHEAD_FRAMES + PEAK_DETECTOR_SPREAD_FRAMES) as u64,
+            envelope: 1.0,
+            attack: VecDeque::with_capacity(LIMITER_LOOKAHEAD_FRAMES),
+            attack_sum: 0.0,
+            frame_index: 0,
+            min_gain: 1.0,
+        }
+    }
+
+    /// Number of frames the output lags behind the input
+    pub fn latency(&self) -> usize {
+        self.delay_frames
+    }
+
+    /// Largest gain reduction applied so far, in dB (0 or negative)
+    pub fn max_reduction_db(&self) -> f64 {
+        linear_to_db(self.min_gain)
+    }
+
+    /// Process interleaved samples, appending the (delayed) output
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for frame in input.chunks_exact(self.channels) {
+            self.process_frame(frame, output);
+        }
+    }
+
+    /// Push the look-ahead tail through the limiter
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        let silence = vec![0.0; self.channels];
+        for _ in 0..self.delay_frames {
+            self.process_frame(&silence, output);
+        }
+        self.delay.clear();
+    }
+
+    fn process_frame(&mut self, frame: &[f32], output: &mut Vec<f32>) {
+        let peak = self.detector.process_frame(frame);
+        let required = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        // Sliding minimum of the required gain over the hold window
+        while self.hold.back().is_some_and(|&(_, g)| g >= required) {
+            self.hold.pop_back();
+        }
+        self.hold.push_back((self.frame_index, required));
+        while self
+            .hold
+            .front()
+            .is_some_and(|&(i, _)| i + self.hold_frames <= self.frame_index)
+        {
+            self.hold.pop_front();
+        }
+        let held = self.hold.front().map_or(1.0, |&(_, g)| g);
+
+        // Instant drop, exponential release; never above the held gain
+        self.envelope = if held < self.envelope {
+            held
+        } else {
+            held + (self.envelope - held) * self.release
+        };
+
+        self.attack.push_back(self.envelope);
+        self.attack_sum += self.envelope;
+        if self.attack.len() > LIMITER_LOOKAHEAD_FRAMES {
+            self.attack_sum -= self.attack.pop_front().unwrap_or(0.0);
+        }
+        let gain = self.attack_sum / self.attack.len() as f64;
+
+        self.delay.extend(frame.iter().copied());
+        if self.delay.len() > self.delay_frames * self.channels {
+            self.min_gain = self.min_gain.min(gain);
+            for _ in 0..self.channels {
+                let sample = self.delay.pop_front().unwrap_or(0.0);
+                output.push((sample as f64 * gain) as f32);
+            }
+        }
+
+        self.frame_index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sine;
+
+    #[test]
+    fn test_limiter_keeps_true_peak_under_ceiling() {
+        let input = sine(997.0, 2.0, 48000);
+        let mut limiter = Limiter::new(1, 48000, -1.0);
+        let mut output = Vec::new();
+        limiter.process(&input, &mut output);
+        limiter.flush(&mut output);
+
+        assert_eq!(output.len(), input.len());
+
+        let mut detector = TruePeakDetector::new(1);
+        let peak = output
+            .iter()
+            .map(|s| detector.process_frame(&[*s]))
+            .fold(0.0, f64::max);
+        assert!(linear_to_db(peak) <= -0.9, "peak: {}", linear_to_db(peak));
+        assert!(limiter.max_reduction_db() < -6.0);
+    }
+
+    #[test]
+    fn test_limiter_passes_quiet_audio_unchanged() {
+        let input = sine(997.0, 0.25, 4800);
+        let mut limiter = Limiter::new(1, 48000, -1.0);
+        let mut output = Vec::new();
+        limiter.process(&input, &mut output);
+        limiter.flush(&mut output);
+
+        assert_eq!(output, input);
+        assert_eq!(limiter.max_reduction_db(), 0.0);
+    }
+}
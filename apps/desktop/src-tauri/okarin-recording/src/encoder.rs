@@ -0,0 +1,147 @@
+// Note: l'enregistrement n'encode rien, MediaRecorder du navigateur produit
+// déjà des chunks WebM encodés (Opus pour audio, VP9/H264 pour vidéo) qui sont
+// écrits tels quels dans track.rs. L'encodeur ci-dessous ne sert qu'aux exports.
+use super::decoder::{OpusHead, OPUS_SAMPLE_RATE};
+use super::types::*;
+use super::webm::{AudioTrackInfo, TrackInfo, TrackKind};
+use audiopus::coder::Encoder;
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+
+/// Samples per channel of every encoded packet (20ms)
+pub const OPUS_FRAME_SAMPLES: usize = 960;
+/// Bitrate of exported Opus files, per stream
+pub const EXPORT_OPUS_BITRATE: i32 = 128_000;
+/// Recommended Matroska SeekPreRoll for Opus
+const OPUS_SEEK_PRE_ROLL_NS: u64 = 80_000_000;
+const MAX_PACKET_BYTES: usize = 4000;
+
+/// One encoded Opus packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpusPacket {
+    /// Position of the packet's first sample, pre-skip included
+    pub position: u64,
+    pub data: Vec<u8>,
+}
+
+impl OpusPacket {
+    pub fn timestamp_ns(&self) -> i64 {
+        (self.position * 1_000_000_000 / OPUS_SAMPLE_RATE as u64) as i64
+    }
+}
+
+/// Encodes interleaved f32 PCM into 20ms Opus packets
+pub struct OpusEncoder {
+    encoder: Encoder,
+    channels: usize,
+    head: OpusHead,
+    pending: Vec<f32>,
+    position: u64,
+    buffer: Vec<u8>,
+}
+
+impl OpusEncoder {
+    pub fn new(channels: u16, bitrate: i32) -> RecordingResult<Self> {
+        let opus_channels = match channels {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            other => {
+                return Err(RecordingError::InvalidConfig(format!(
+                    "Opus export supports 1 or 2 channels, not {}",
+                    other
+                )))
+            }
+        };
+        let encode_error = |e: audiopus::Error| RecordingError::TrackError(e.to_string());
+
+        let mut encoder = Encoder::new(SampleRate::Hz48000, opus_channels, Application::Audio)
+            .map_err(encode_error)?;
+        encoder
+            .set_bitrate(Bitrate::BitsPerSecond(bitrate))
+            .map_err(encode_error)?;
+        let pre_skip = encoder.lookahead().map_err(encode_error)? as u16;
+
+        Ok(Self {
+            encoder,
+            channels: channels as usize,
+            head: OpusHead {
+                version: 1,
+                channels: channels as u8,
+                pre_skip,
+                input_sample_rate: OPUS_SAMPLE_RATE,
+                output_gain: 0,
+                mapping_family: 0,
+            },
+            pending: Vec::new(),
+            position: 0,
+            buffer: vec![0; MAX_PACKET_BYTES],
+        })
+    }
+
+    pub fn head(&self) -> &OpusHead {
+        &self.head
+    }
+
+    /// WebM track entry describing the encoded stream
+    pub fn track_info(&self, number: u64) -> TrackInfo {
+        TrackInfo {
+            number,
+            uid: Some(number),
+            kind: TrackKind::Audio,
+            codec_id: "A_OPUS".to_string(),
+            codec_private: Some(self.head.to_bytes()),
+            codec_delay_ns: self.head.pre_skip as u64 * 1_000_000_000 / OPUS_SAMPLE_RATE as u64,
+            seek_pre_roll_ns: OPUS_SEEK_PRE_ROLL_NS,
+            default_duration_ns: None,
+            audio: Some(AudioTrackInfo {
+                sampling_frequency: OPUS_SAMPLE_RATE as f64,
+                channels: self.channels as u64,
+                bit_depth: None,
+            }),
+            video: None,
+        }
+    }
+
+    /// Encode interleaved samples, appending every completed packet
+    pub fn encode(
+        &mut self,
+        samples: &[f32],
+        packets: &mut Vec<OpusPacket>,
+    ) -> RecordingResult<()> {
+        self.pending.extend_from_slice(samples);
+        let frame_len = OPUS_FRAME_SAMPLES * self.channels;
+
+        let mut start = 0;
+        while self.pending.len() - start >= frame_len {
+            let frame = &self.pending[start..start + frame_len];
+            let len = self
+                .encoder
+                .encode_float(frame, &mut self.buffer)
+                .map_err(|e| RecordingError::TrackError(e.to_string()))?;
+            packets.push(OpusPacket {
+                position: self.position,
+                data: self.buffer[..len].to_vec(),
+            });
+            self.position += OPUS_FRAME_SAMPLES as u64;
+            start += frame_len;
+        }
+        self.pending.drain(..start);
+
+        Ok(())
+    }
+
+    /// Push the encoder look-ahead out and pad the last packet with silence
+    ///
+    /// Returns the number of real (non-padding) samples per channel encoded,
+    /// pre-skip excluded, which Ogg needs for the final granule position.
+    pub fn finish(&mut self, packets: &mut Vec<OpusPacket>) -> RecordingResult<u64> {
+        let buffered = (self.pending.len() / self.channels) as u64;
+        let total = self.position + buffered;
+
+        let needed = buffered as usize + self.head.pre_skip as usize;
+        let padded = needed.div_ceil(OPUS_FRAME_SAMPLES) * OPUS_FRAME_SAMPLES;
+        let silence = vec![0.0; (padded - buffered as usize) * self.channels];
+        self.encode(&silence, packets)?;
+
+        Ok(total)
+    }
+}
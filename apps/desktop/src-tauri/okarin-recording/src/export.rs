@@ -0,0 +1,143 @@
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::format::FormatConverter;
+use super::storage::derived_file_path;
+use super::types::*;
+use super::wav::WavFileWriter;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const EXPORT_BLOCK_FRAMES: usize = 4800;
+
+/// WAV writer storing decoded 48kHz audio in the format of a recording
+///
+/// The sample rate and channel count of `target` are used when set, the
+/// recorded ones otherwise.
+pub struct FormatWavWriter {
+    converter: FormatConverter,
+    writer: WavFileWriter,
+    converted: Vec<f32>,
+}
+
+impl FormatWavWriter {
+    pub fn create(output: &Path, channels: u16, target: &MediaFormat) -> RecordingResult<Self> {
+        let output_channels = target.audio_channels.unwrap_or(channels);
+        let output_rate = target.audio_sample_rate.unwrap_or(OPUS_SAMPLE_RATE);
+        Ok(Self {
+            converter: FormatConverter::new(
+                channels,
+                OPUS_SAMPLE_RATE,
+                output_channels,
+                output_rate,
+            ),
+            writer: WavFileWriter::create(output, output_channels, output_rate)?,
+            converted: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> RecordingResult<()> {
+        self.converted.clear();
+        self.converter.process(samples, &mut self.converted);
+        self.writer.write(&self.converted)
+    }
+
+    /// Write the frames the converter held back and close the file
+    pub fn finalize(mut self) -> RecordingResult<PathBuf> {
+        self.converted.clear();
+        self.converter.flush(&mut self.converted);
+        self.writer.write(&self.converted)?;
+        self.writer.finalize()
+    }
+}
+
+/// Decode a recorded track to WAV, preceded by `offset_seconds` of silence
+pub fn export_wav_file(
+    source: &Path,
+    offset_seconds: f64,
+    target: &MediaFormat,
+    output: &Path,
+) -> RecordingResult<PathBuf> {
+    let decoder = OpusTrackDecoder::open(source)?;
+    let mut writer = FormatWavWriter::create(output, decoder.channels(), target)?;
+    let mut reader = TimelineReader::new(decoder, offset_seconds);
+
+    while let Some(block) = reader.read(EXPORT_BLOCK_FRAMES)? {
+        writer.write(&block)?;
+    }
+    writer.finalize()
+}
+
+/// Decode every participant's audio to a WAV starting at the session start,
+/// so the files line up when dropped into any editor
+///
+/// Files are written in the format the recording was configured for.
+pub fn export_wav_tracks(
+    metadata: &RecordingMetadata,
+) -> RecordingResult<HashMap<String, PathBuf>> {
+    let target = metadata.export_format();
+    let mut tracks = HashMap::new();
+
+    for (participant_id, participant) in &metadata.participants {
+        let source = match &participant.audio_file {
+            Some(path) => path,
+            None => continue,
+        };
+
+        log::info!("Exporting WAV for participant {}", participant_id);
+        let output = export_wav_file(
+            source,
+            metadata.participant_offset_seconds(participant),
+            &target,
+            &derived_file_path(source, "audio", "wav"),
+        )?;
+        tracks.insert(participant_id.clone(), output);
+    }
+
+    Ok(tracks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, temp_dir};
+    use crate::wav::WavFileReader;
+
+    #[test]
+    fn test_export_wav_pads_offset() {
+        let source = fixture();
+        let dir = temp_dir("export");
+        let output = dir.join("export.wav");
+
+        export_wav_file(&source, 0.5, &MediaFormat::default(), &output).unwrap();
+        let mut reader = WavFileReader::open(&output).unwrap();
+        assert_eq!(reader.channels(), 1);
+        // Half a second of silence, then the 2s track
+        assert_eq!(reader.frame_count(), 24000 + 96000);
+
+        let lead = reader.read(24000).unwrap().unwrap();
+        assert!(lead.iter().all(|&s| s == 0.0));
+        let tone = reader.read(4800).unwrap().unwrap();
+        assert!(tone.iter().any(|s| s.abs() > 0.4));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_export_wav_uses_target_format() {
+        let source = fixture();
+        let dir = temp_dir("export-target");
+        let output = dir.join("export.wav");
+        let target = MediaFormat {
+            audio_sample_rate: Some(44100),
+            audio_channels: Some(2),
+            ..Default::default()
+        };
+
+        export_wav_file(&source, 0.0, &target, &output).unwrap();
+        let reader = WavFileReader::open(&output).unwrap();
+        assert_eq!(reader.channels(), 2);
+        assert_eq!(reader.sample_rate(), 44100);
+        assert!(reader.frame_count().abs_diff(88200) <= 1);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,508 @@
+use super::chapters;
+use super::library;
+use super::storage;
+use super::types::*;
+use chrono::Utc;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+pub const FEED_FILENAME: &str = "feed.xml";
+/// Files of the output directory written by the last run, the only ones
+/// ever deleted
+pub const FEED_MANIFEST_FILENAME: &str = "feed-files.json";
+const ITUNES_NAMESPACE: &str = "http://www.itunes.com/dtds/podcast-1.0.dtd";
+const PODCAST_NAMESPACE: &str = "https://podcastindex.org/namespace/1.0";
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// A finished recording with a published file
+#[derive(Debug, Clone)]
+pub struct FeedEpisode {
+    pub metadata: RecordingMetadata,
+    pub episode: EpisodeMetadata,
+    pub export: PublishExport,
+}
+
+/// Every recording of the library that is stopped and published, newest first
+///
+/// Recordings that cannot be read are skipped with a warning, a broken
+/// recording must not take the whole feed down.
+pub fn published_episodes(recordings_root: &Path) -> RecordingResult<Vec<FeedEpisode>> {
+    let mut episodes = Vec::new();
+
+    for entry in library::list_recordings(recordings_root)? {
+        if !entry.published || entry.stopped_at.is_none() {
+            continue;
+        }
+        let dir = &entry.directory;
+        let loaded = storage::load_metadata(dir)
+            .and_then(|metadata| storage::load_episode(dir).map(|episode| (metadata, episode)));
+        let (metadata, episode) = match loaded {
+            Ok((metadata, Some(episode))) => (metadata, episode),
+            Ok((_, None)) => continue,
+            Err(e) => {
+                log::warn!("Skipping {:?} in the feed: {}", dir, e);
+                continue;
+            }
+        };
+        let Some(export) = episode.export.clone() else {
+            continue;
+        };
+        if !export.file.is_file() {
+            continue;
+        }
+        episodes.push(FeedEpisode {
+            metadata,
+            episode,
+            export,
+        });
+    }
+
+    Ok(episodes)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Percent-encode a file name for use in a URL path
+fn url_escape(name: &str) -> String {
+    name.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn public_url(settings: &FeedSettings, name: &str) -> String {
+    format!(
+        "{}/{}",
+        settings.base_url.trim_end_matches('/'),
+        url_escape(name)
+    )
+}
+
+/// `HH:MM:SS`, as read by every podcast client
+fn itunes_duration(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Copy unless the target already has the same length and is newer
+fn copy_if_changed(source: &Path, target: &Path) -> RecordingResult<()> {
+    if let (Ok(from), Ok(to)) = (fs::metadata(source), fs::metadata(target)) {
+        let newer = match (from.modified(), to.modified()) {
+            (Ok(from), Ok(to)) => to >= from,
+            _ => false,
+        };
+        if newer && from.len() == to.len() {
+            return Ok(());
+        }
+    }
+    fs::copy(source, target)?;
+    Ok(())
+}
+
+/// Copy an episode's files to the output directory and describe it as an `<item>`
+///
+/// The names of the files copied or written are added to `files`.
+fn feed_item(
+    settings: &FeedSettings,
+    episode: &FeedEpisode,
+    files: &mut HashSet<String>,
+) -> RecordingResult<String> {
+    let FeedEpisode {
+        metadata,
+        episode,
+        export,
+    } = episode;
+    let audio_name = format!("{}.{}", metadata.id, export.format.extension());
+    copy_if_changed(&export.file, &settings.output_dir.join(&audio_name))?;
+    let size_bytes = fs::metadata(&export.file)?.len();
+    files.insert(audio_name.clone());
+
+    let title = if episode.title.is_empty() {
+        &metadata.id
+    } else {
+        &episode.title
+    };
+    let mut item = String::from("    <item>\n");
+    item.push_str(&format!("      <title>{}</title>\n", xml_escape(title)));
+    if !episode.description.is_empty() {
+        item.push_str(&format!(
+            "      <description>{}</description>\n",
+            xml_escape(&episode.description)
+        ));
+    }
+    item.push_str(&format!(
+        "      <guid isPermaLink=\"false\">{}</guid>\n",
+        xml_escape(&metadata.id)
+    ));
+    item.push_str(&format!(
+        "      <pubDate>{}</pubDate>\n",
+        metadata.started_at.to_rfc2822()
+    ));
+    item.push_str(&format!(
+        "      <enclosure url=\"{}\" length=\"{}\" type=\"{}\"/>\n",
+        xml_escape(&public_url(settings, &audio_name)),
+        size_bytes,
+        export.format.mime_type()
+    ));
+    item.push_str(&format!(
+        "      <itunes:duration>{}</itunes:duration>\n",
+        itunes_duration(export.duration_seconds)
+    ));
+    if let Some(number) = episode.episode_number {
+        item.push_str(&format!(
+            "      <itunes:episode>{}</itunes:episode>\n",
+            number
+        ));
+    }
+
+    if let Some(artwork) = episode.artwork_path.as_deref().filter(|p| p.is_file()) {
+        let extension = artwork
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_else(|| "jpg".to_string());
+        let cover_name = format!("{}-cover.{}", metadata.id, extension);
+        copy_if_changed(artwork, &settings.output_dir.join(&cover_name))?;
+        files.insert(cover_name.clone());
+        item.push_str(&format!(
+            "      <itunes:image href=\"{}\"/>\n",
+            xml_escape(&public_url(settings, &cover_name))
+        ));
+    }
+
+    let chapters = chapters::chapters(metadata);
+    if !chapters.is_empty() {
+        let chapters_name = format!("{}-chapters.json", metadata.id);
+        fs::write(
+            settings.output_dir.join(&chapters_name),
+            chapters::podcast_chapters_json(&chapters)?,
+        )?;
+        files.insert(chapters_name.clone());
+        item.push_str(&format!(
+            "      <podcast:chapters url=\"{}\" type=\"application/json+chapters\"/>\n",
+            xml_escape(&public_url(settings, &chapters_name))
+        ));
+    }
+
+    // Whoever opened the room hosts it
+    let mut participants: Vec<&ParticipantMetadata> = metadata.participants.values().collect();
+    participants.sort_by_key(|p| p.joined_at);
+    for (index, participant) in participants.iter().enumerate() {
+        item.push_str(&format!(
+            "      <podcast:person role=\"{}\">{}</podcast:person>\n",
+            if index == 0 { "host" } else { "guest" },
+            xml_escape(&participant.name)
+        ));
+    }
+
+    item.push_str("    </item>\n");
+    Ok(item)
+}
+
+/// Names listed in the manifest of the output directory, none on a first run
+fn read_manifest(output_dir: &Path) -> HashSet<String> {
+    let path = output_dir.join(FEED_MANIFEST_FILENAME);
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(_) => return HashSet::new(),
+    };
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        log::warn!("Ignoring unreadable {:?}: {}", path, e);
+        HashSet::new()
+    })
+}
+
+/// Delete the files of the last run the feed no longer links to, then record
+/// the ones it does
+///
+/// Only files listed in the manifest are ever deleted: anything put in the
+/// directory by hand, a landing page, a trailer or the show artwork, is left
+/// alone.
+fn update_manifest(output_dir: &Path, files: &HashSet<String>) -> RecordingResult<()> {
+    for name in read_manifest(output_dir).difference(files) {
+        // Plain file names only, the manifest could have been edited
+        if Path::new(name).file_name() != Some(OsStr::new(name)) {
+            continue;
+        }
+        let path = output_dir.join(name);
+        log::info!("Removing {:?}, no longer in the feed", path);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+
+    let mut names: Vec<&String> = files.iter().collect();
+    names.sort();
+    let json = serde_json::to_string_pretty(&names)
+        .map_err(|e| RecordingError::IoError(std::io::Error::other(e)))?;
+    fs::write(output_dir.join(FEED_MANIFEST_FILENAME), json)?;
+    Ok(())
+}
+
+/// The whole RSS 2.0 document
+fn feed_xml(settings: &FeedSettings, items: &[String]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<rss version=\"2.0\" xmlns:itunes=\"{}\" xmlns:podcast=\"{}\" xmlns:atom=\"{}\">\n",
+        ITUNES_NAMESPACE, PODCAST_NAMESPACE, ATOM_NAMESPACE
+    ));
+    xml.push_str("  <channel>\n");
+
+    let mut element = |name: &str, value: &str| {
+        xml.push_str(&format!("    <{0}>{1}</{0}>\n", name, xml_escape(value)));
+    };
+    element("title", &settings.title);
+    element("link", &settings.link);
+    element("description", &settings.description);
+    if let Some(language) = &settings.language {
+        element("language", language);
+    }
+    element("lastBuildDate", &Utc::now().to_rfc2822());
+    element("generator", "okarin");
+    element("itunes:author", &settings.author);
+    element(
+        "itunes:explicit",
+        if settings.explicit { "true" } else { "false" },
+    );
+
+    xml.push_str(&format!(
+        "    <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>\n",
+        xml_escape(&public_url(settings, FEED_FILENAME))
+    ));
+    xml.push_str(&format!(
+        "    <itunes:owner>\n      <itunes:name>{}</itunes:name>\n",
+        xml_escape(&settings.author)
+    ));
+    if let Some(email) = &settings.owner_email {
+        xml.push_str(&format!(
+            "      <itunes:email>{}</itunes:email>\n",
+            xml_escape(email)
+        ));
+    }
+    xml.push_str("    </itunes:owner>\n");
+    if let Some(image_url) = &settings.image_url {
+        xml.push_str(&format!(
+            "    <itunes:image href=\"{}\"/>\n",
+            xml_escape(image_url)
+        ));
+    }
+    if let Some(category) = &settings.category {
+        xml.push_str(&format!(
+            "    <itunes:category text=\"{}\"/>\n",
+            xml_escape(category)
+        ));
+    }
+
+    for item in items {
+        xml.push_str(item);
+    }
+    xml.push_str("  </channel>\n</rss>\n");
+    xml
+}
+
+/// Generate `feed.xml` in the output directory from every published recording
+///
+/// Audio, cover art and chapters are copied next to the feed, so the
+/// directory can be synced as is. The feed is rebuilt from scratch on every
+/// run and replaced in one rename, a sync never sees half a file. Episode
+/// files of earlier runs it no longer links to are deleted afterwards, as
+/// listed in `feed-files.json`.
+pub fn publish_feed(
+    recordings_root: &Path,
+    settings: &FeedSettings,
+) -> RecordingResult<FeedExport> {
+    if settings.base_url.is_empty() {
+        return Err(RecordingError::InvalidConfig(
+            "the feed needs the public URL of its directory".to_string(),
+        ));
+    }
+    fs::create_dir_all(&settings.output_dir)?;
+
+    let episodes = published_episodes(recordings_root)?;
+    log::info!(
+        "Writing a feed of {} episodes to {:?}",
+        episodes.len(),
+        settings.output_dir
+    );
+    let mut files = HashSet::new();
+    let items = episodes
+        .iter()
+        .map(|episode| feed_item(settings, episode, &mut files))
+        .collect::<RecordingResult<Vec<_>>>()?;
+
+    let feed_file = settings.output_dir.join(FEED_FILENAME);
+    let partial = settings.output_dir.join(format!("{}.part", FEED_FILENAME));
+    fs::write(&partial, feed_xml(settings, &items))?;
+    fs::rename(&partial, &feed_file)?;
+    update_manifest(&settings.output_dir, &files)?;
+
+    Ok(FeedExport {
+        generated_at: Utc::now(),
+        feed_file,
+        episodes: episodes.into_iter().map(|e| e.metadata.id).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{participant, participants, recording, temp_dir};
+    use chrono::Duration;
+    use std::path::PathBuf;
+
+    fn write_recording(root: &Path, id: &str, days_ago: i64, stopped: bool) -> PathBuf {
+        let dir = root.join(id);
+        fs::create_dir_all(&dir).unwrap();
+        let started_at = Utc::now() - Duration::days(days_ago);
+        let metadata = RecordingMetadata {
+            id: id.to_string(),
+            stopped_at: stopped.then(|| started_at + Duration::seconds(90)),
+            participants: participants([
+                participant("p1", "Ada & Bob", started_at),
+                participant("p2", "Cleo", started_at + Duration::seconds(5)),
+            ]),
+            output_directory: dir.clone(),
+            markers: vec![Marker {
+                id: "marker-1".to_string(),
+                label: "Intro".to_string(),
+                kind: MarkerKind::Chapter,
+                position_seconds: 0.0,
+                created_at: started_at,
+            }],
+            ..recording(started_at, 90)
+        };
+        storage::write_metadata(&dir, &metadata).unwrap();
+
+        let file = dir.join("episode.mp3");
+        fs::write(&file, [0xFF; 1234]).unwrap();
+        let episode = EpisodeMetadata {
+            title: format!("Episode <{}>", id),
+            show: "Okarin Radio".to_string(),
+            description: String::new(),
+            episode_number: Some(days_ago as u32),
+            artwork_path: None,
+            export: Some(PublishExport {
+                exported_at: Utc::now(),
+                format: PublishFormat::Mp3,
+                file,
+                size_bytes: 1234,
+                duration_seconds: 3725.4,
+            }),
+        };
+        storage::write_episode(&dir, &episode).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_publish_feed() {
+        let root = temp_dir("feed");
+        write_recording(&root, "older", 7, true);
+        write_recording(&root, "newer", 1, true);
+        write_recording(&root, "unfinished", 0, false);
+        let settings = FeedSettings {
+            title: "Okarin Radio".to_string(),
+            description: "Talks".to_string(),
+            link: "https://example.com".to_string(),
+            author: "Okarin".to_string(),
+            owner_email: None,
+            language: Some("en".to_string()),
+            image_url: None,
+            category: Some("Technology".to_string()),
+            explicit: false,
+            base_url: "https://cdn.example.com/podcast/".to_string(),
+            output_dir: root.join("public"),
+        };
+
+        // Left over from a recording deleted since the last run, next to
+        // files put there by hand
+        let public = root.join("public");
+        fs::create_dir_all(&public).unwrap();
+        let gone = ["gone.mp3", "gone-chapters.json", "gone-cover.png"];
+        let by_hand = ["index.html", "show-cover.png", "trailer.mp3"];
+        for name in gone.iter().chain(&by_hand) {
+            fs::write(public.join(name), b"old").unwrap();
+        }
+        fs::write(
+            public.join(FEED_MANIFEST_FILENAME),
+            serde_json::to_string(&gone).unwrap(),
+        )
+        .unwrap();
+
+        let export = publish_feed(&root, &settings).unwrap();
+        let xml = fs::read_to_string(&export.feed_file).unwrap();
+        let chapters_copied = public.join("newer-chapters.json").is_file();
+        let audio_copied = fs::metadata(public.join("older.mp3")).map(|m| m.len());
+        let mut left: Vec<String> = fs::read_dir(&public)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        let manifest = read_manifest(&public);
+        fs::remove_dir_all(&root).ok();
+
+        assert_eq!(export.episodes, vec!["newer", "older"]);
+        assert!(chapters_copied);
+        assert_eq!(audio_copied.unwrap(), 1234);
+        assert!(xml.contains("<title>Episode &lt;newer&gt;</title>"));
+        assert!(xml.contains(
+            "<enclosure url=\"https://cdn.example.com/podcast/newer.mp3\" length=\"1234\" type=\"audio/mpeg\"/>"
+        ));
+        assert!(xml.contains("<itunes:duration>01:02:05</itunes:duration>"));
+        assert!(xml.contains("<podcast:person role=\"host\">Ada &amp; Bob</podcast:person>"));
+        assert!(xml.contains("<podcast:person role=\"guest\">Cleo</podcast:person>"));
+        assert!(xml.contains(
+            "<podcast:chapters url=\"https://cdn.example.com/podcast/older-chapters.json\""
+        ));
+        assert!(xml.find("newer.mp3").unwrap() < xml.find("older.mp3").unwrap());
+        assert!(!xml.contains("unfinished"));
+        assert_eq!(
+            left,
+            vec![
+                "feed-files.json",
+                "feed.xml",
+                "index.html",
+                "newer-chapters.json",
+                "newer.mp3",
+                "older-chapters.json",
+                "older.mp3",
+                "show-cover.png",
+                "trailer.mp3",
+            ]
+        );
+        let mut manifest: Vec<String> = manifest.into_iter().collect();
+        manifest.sort();
+        assert_eq!(
+            manifest,
+            vec![
+                "newer-chapters.json",
+                "newer.mp3",
+                "older-chapters.json",
+                "older.mp3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_url_escape() {
+        assert_eq!(
+            url_escape("recording-my room-1.mp3"),
+            "recording-my%20room-1.mp3"
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use super::decoder::OpusHead;
+use super::dsp::{Biquad, BUTTERWORTH_8_Q};
+use super::types::*;
+use super::webm::{TrackInfo, TrackKind, WebmDemuxer, WebmEvent};
+
+/// Span of video blocks measured before the frame rate is compared
+const FPS_MEASURE_SECONDS: f64 = 3.0;
+/// Relative frame rate deviation tolerated, browsers drop frames under load
+const FPS_TOLERANCE: f64 = 0.1;
+/// Cutoff of the anti-alias filter when lowering the rate, relative to the
+/// output rate, leaving the filter room to roll off below the Nyquist
+const ANTI_ALIAS_CUTOFF: f64 = 0.4;
+
+fn mismatch(
+    field: &str,
+    configured: Option<f64>,
+    actual: Option<f64>,
+    tolerance: f64,
+) -> Option<FormatMismatch> {
+    let (configured, actual) = (configured?, actual?);
+    ((actual - configured).abs() > configured * tolerance).then(|| FormatMismatch {
+        field: field.to_string(),
+        configured,
+        actual,
+    })
+}
+
+/// Checks a participant's incoming WebM chunks against the configured format
+///
+/// Only the head of the stream is parsed: the Tracks element for the audio
+/// and picture parameters, then a few seconds of video blocks for the frame
+/// rate. Later chunks are ignored.
+pub struct LiveFormatCheck {
+    configured: MediaFormat,
+    demuxer: WebmDemuxer,
+    format: MediaFormat,
+    video_track: Option<u64>,
+    first_video_ns: Option<i64>,
+    video_blocks: u64,
+    done: bool,
+}
+
+impl LiveFormatCheck {
+    pub fn new(configured: MediaFormat) -> Self {
+        Self {
+            configured,
+            demuxer: WebmDemuxer::new(),
+            format: MediaFormat::default(),
+            video_track: None,
+            first_video_ns: None,
+            video_blocks: 0,
+            done: false,
+        }
+    }
+
+    /// What the stream turned out to contain so far
+    pub fn format(&self) -> &MediaFormat {
+        &self.format
+    }
+
+    /// Parse a chunk, returning the mismatches it revealed
+    pub fn push_chunk(&mut self, chunk: &[u8]) -> Vec<FormatMismatch> {
+        let mut mismatches = Vec::new();
+        if self.done {
+            return mismatches;
+        }
+
+        self.demuxer.push(chunk);
+        loop {
+            let event = match self.demuxer.next_event() {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("Format check stopped on unreadable stream: {}", e);
+                    self.done = true;
+                    break;
+                }
+            };
+            match event {
+                WebmEvent::Tracks(tracks) => {
+                    mismatches.extend(self.check_tracks(&tracks));
+                    self.done = self.video_track.is_none();
+                }
+                WebmEvent::Block(block) if Some(block.track_number) == self.video_track => {
+                    let first = *self.first_video_ns.get_or_insert(block.timestamp_ns);
+                    self.video_blocks += 1;
+                    let span = (block.timestamp_ns - first) as f64 / 1e9;
+                    if span >= FPS_MEASURE_SECONDS {
+                        let fps = (self.video_blocks - 1) as f64 / span;
+                        self.format.video_fps = Some(fps);
+                        mismatches.extend(mismatch(
+                            "video_fps",
+                            self.configured.video_fps,
+                            Some(fps),
+                            FPS_TOLERANCE,
+                        ));
+                        self.done = true;
+                    }
+                }
+                _ => {}
+            }
+            if self.done {
+                break;
+            }
+        }
+
+        if self.done {
+            // Nothing more to learn, release the parse buffer
+            self.demuxer = WebmDemuxer::new();
+        }
+        mismatches
+    }
+
+    fn check_tracks(&mut self, tracks: &[TrackInfo]) -> Vec<FormatMismatch> {
+        for track in tracks {
+            match track.kind {
+                TrackKind::Audio => {
+                    // The container rate of Opus is always 48kHz, the OpusHead
+                    // carries the rate the browser captured at
+                    let head = OpusHead::parse(track.codec_private.as_deref().unwrap_or_default());
+                    self.format.audio_sample_rate = head
+                        .ok()
+                        .map(|head| head.input_sample_rate)
+                        .filter(|&rate| rate > 0)
+                        .or_else(|| track.audio.as_ref().map(|a| a.sampling_frequency as u32));
+                    self.format.audio_channels = track.audio.as_ref().map(|a| a.channels as u16);
+                }
+                TrackKind::Video => {
+                    self.video_track = Some(track.number);
+                    self.format.video_width = track.video.as_ref().map(|v| v.pixel_width as u32);
+                    self.format.video_height = track.video.as_ref().map(|v| v.pixel_height as u32);
+                }
+                TrackKind::Other(_) => {}
+            }
+        }
+
+        let configured = &self.configured;
+        let actual = &self.format;
+        [
+            mismatch(
+                "audio_sample_rate",
+                configured.audio_sample_rate.map(f64::from),
+                actual.audio_sample_rate.map(f64::from),
+                0.0,
+            ),
+            // A mono microphone fills every configured channel on export
+            mismatch(
+                "audio_channels",
+                configured.audio_channels.map(f64::from),
+                actual
+                    .audio_channels
+                    .filter(|&channels| channels != 1)
+                    .map(f64::from),
+                0.0,
+            ),
+            mismatch(
+                "video_width",
+                configured.video_width.map(f64::from),
+                actual.video_width.map(f64::from),
+                0.0,
+            ),
+            mismatch(
+                "video_height",
+                configured.video_height.map(f64::from),
+                actual.video_height.map(f64::from),
+                0.0,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Converts interleaved audio to another channel count and sample rate
+///
+/// Channels are duplicated or averaged, the rate is changed by linear
+/// interpolation, which is plenty for speech exports. When the rate is
+/// lowered an 8th-order Butterworth low-pass runs first, so content above
+/// the new Nyquist does not fold back into the audible band.
+pub struct FormatConverter {
+    input_channels: usize,
+    output_channels: usize,
+    /// Anti-alias sections of every output channel, empty unless downsampling
+    filters: Vec<Vec<Biquad>>,
+    /// Input frames per output frame
+    step: f64,
+    /// Position of the next output frame in `pending`, in input frames
+    position: f64,
+    pending: Vec<f32>,
+}
+
+impl FormatConverter {
+    pub fn new(
+        input_channels: u16,
+        input_rate: u32,
+        output_channels: u16,
+        output_rate: u32,
+    ) -> Self {
+        let output_channels = output_channels.max(1) as usize;
+        let filters = if output_rate > 0 && output_rate < input_rate {
+            let cutoff = output_rate as f64 * ANTI_ALIAS_CUTOFF;
+            let sections: Vec<Biquad> = BUTTERWORTH_8_Q
+                .iter()
+                .map(|&q| Biquad::low_pass(cutoff, input_rate, q))
+                .collect();
+            vec![sections; output_channels]
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            input_channels: input_channels.max(1) as usize,
+            output_channels,
+            filters,
+            step: input_rate as f64 / output_rate.max(1) as f64,
+            position: 0.0,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for frame in input.chunks_exact(self.input_channels) {
+            let start = self.pending.len();
+            match (self.input_channels, self.output_channels) {
+                (i, o) if i == o => self.pending.extend_from_slice(frame),
+                (_, 1) => self
+                    .pending
+                    .push(frame.iter().sum::<f32>() / frame.len() as f32),
+                (1, o) => self.pending.extend(std::iter::repeat_n(frame[0], o)),
+                (_, o) => self
+                    .pending
+                    .extend((0..o).map(|c| frame.get(c).copied().unwrap_or(0.0))),
+            }
+            for (sample, sections) in self.pending[start..].iter_mut().zip(&mut self.filters) {
+                let filtered = sections
+                    .iter_mut()
+                    .fold(*sample as f64, |s, section| section.process(s));
+                *sample = filtered as f32;
+            }
+        }
+        self.drain(output, false);
+    }
+
+    /// Emit the frames held back for interpolation
+    pub fn flush(&mut self, output: &mut Vec<f32>) {
+        self.drain(output, true);
+        self.pending.clear();
+        self.position = 0.0;
+    }
+
+    fn drain(&mut self, output: &mut Vec<f32>, flush: bool) {
+        let channels = self.output_channels;
+        let frames = self.pending.len() / channels;
+
+        loop {
+            let index = self.position as usize;
+            // Interpolating needs the next frame too, except for the very last
+            let needed = if flush { index + 1 } else { index + 2 };
+            if needed > frames {
+                break;
+            }
+            let t = (self.position - index as f64) as f32;
+            for channel in 0..channels {
+                let a = self.pending[index * channels + channel];
+                let b = self
+                    .pending
+                    .get((index + 1) * channels + channel)
+                    .copied()
+                    .unwrap_or(a);
+                output.push(a + (b - a) * t);
+            }
+            self.position += self.step;
+        }
+
+        let consumed = (self.position as usize).min(frames);
+        self.pending.drain(..consumed * channels);
+        self.position -= consumed as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, sine};
+
+    fn check_fixture(config: RecordingConfig) -> (LiveFormatCheck, Vec<FormatMismatch>) {
+        let data = std::fs::read(fixture()).unwrap();
+        let mut check = LiveFormatCheck::new(config.media_format());
+        let mismatches = data.chunks(500).flat_map(|c| check.push_chunk(c)).collect();
+        (check, mismatches)
+    }
+
+    fn rms(samples: &[f32]) -> f64 {
+        (samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_live_check_accepts_mono_into_stereo() {
+        let (check, mismatches) = check_fixture(RecordingConfig::default());
+        assert_eq!(mismatches, Vec::new());
+        assert_eq!(check.format().audio_channels, Some(1));
+        assert_eq!(check.format().audio_sample_rate, Some(48000));
+        assert_eq!(check.format().video_width, None);
+    }
+
+    #[test]
+    fn test_live_check_reports_rate_mismatch() {
+        let (_, mismatches) = check_fixture(RecordingConfig {
+            audio_sample_rate: 44100,
+            audio_channels: 1,
+            ..Default::default()
+        });
+        assert_eq!(
+            mismatches,
+            vec![FormatMismatch {
+                field: "audio_sample_rate".to_string(),
+                configured: 44100.0,
+                actual: 48000.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_converter_halves_rate_and_doubles_channels() {
+        let mut converter = FormatConverter::new(1, 48000, 2, 24000);
+        let input: Vec<f32> = (0..9600).map(|i| i as f32).collect();
+        let mut output = Vec::new();
+        for block in input.chunks(1000) {
+            converter.process(block, &mut output);
+        }
+        converter.flush(&mut output);
+
+        assert_eq!(output.len(), 4800 * 2);
+        assert!(output.chunks(2).all(|frame| frame[0] == frame[1]));
+    }
+
+    #[test]
+    fn test_converter_filters_above_output_nyquist() {
+        let convert = |frequency| {
+            let mut converter = FormatConverter::new(1, 48000, 1, 16000);
+            let mut output = Vec::new();
+            converter.process(&sine(frequency, 0.5, 48000), &mut output);
+            converter.flush(&mut output);
+            // Past the filter's settling time
+            rms(&output[1600..])
+        };
+
+        // 15kHz would fold back to 1kHz
+        assert!(convert(15000.0) < 0.005);
+        assert!((convert(1000.0) - 0.5 * std::f64::consts::FRAC_1_SQRT_2).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_converter_interpolates_between_frames() {
+        let mut converter = FormatConverter::new(2, 2, 1, 4);
+        let mut output = Vec::new();
+        converter.process(&[0.0, 0.0, 1.0, 1.0], &mut output);
+        converter.flush(&mut output);
+        // The last frame is held until the end of its span
+        assert_eq!(output, vec![0.0, 0.5, 1.0, 1.0]);
+    }
+}
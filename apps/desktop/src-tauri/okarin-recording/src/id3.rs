@@ -0,0 +1,130 @@
+/// Element ID of the table of contents listing every chapter
+const TOC_ELEMENT_ID: &str = "toc";
+/// CTOC flags: top-level table, entries ordered
+const CTOC_TOP_LEVEL_ORDERED: u8 = 0x03;
+/// Text encoding byte for UTF-8
+const UTF8: u8 = 0x03;
+/// APIC picture type of the cover art
+const PICTURE_FRONT_COVER: u8 = 0x03;
+
+/// One chapter as written to a CHAP frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Id3Chapter {
+    pub title: String,
+    pub start_ms: u32,
+    pub end_ms: u32,
+}
+
+/// 28-bit integer spread over 4 bytes with the top bit of each cleared
+pub fn synchsafe(value: u32) -> [u8; 4] {
+    [
+        ((value >> 21) & 0x7F) as u8,
+        ((value >> 14) & 0x7F) as u8,
+        ((value >> 7) & 0x7F) as u8,
+        (value & 0x7F) as u8,
+    ]
+}
+
+pub fn frame(id: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(10 + payload.len());
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&synchsafe(payload.len() as u32));
+    bytes.extend_from_slice(&[0, 0]);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// `T***` text frame, UTF-8 encoded
+pub fn text_frame(id: &[u8; 4], text: &str) -> Vec<u8> {
+    let mut payload = vec![UTF8];
+    payload.extend_from_slice(text.as_bytes());
+    frame(id, &payload)
+}
+
+/// APIC frame holding the front cover
+pub fn picture_frame(mime_type: &str, image: &[u8]) -> Vec<u8> {
+    let mut payload = vec![UTF8];
+    payload.extend_from_slice(mime_type.as_bytes());
+    payload.push(0);
+    payload.push(PICTURE_FRONT_COVER);
+    // Empty description
+    payload.push(0);
+    payload.extend_from_slice(image);
+    frame(b"APIC", &payload)
+}
+
+/// CTOC frame followed by one CHAP frame per chapter
+pub fn chapter_frames(chapters: &[Id3Chapter]) -> Vec<u8> {
+    let chapters = &chapters[..chapters.len().min(u8::MAX as usize)];
+    let element_id = |index: usize| format!("chp{}", index);
+
+    let mut toc = Vec::new();
+    toc.extend_from_slice(TOC_ELEMENT_ID.as_bytes());
+    toc.push(0);
+    toc.push(CTOC_TOP_LEVEL_ORDERED);
+    toc.push(chapters.len() as u8);
+    for index in 0..chapters.len() {
+        toc.extend_from_slice(element_id(index).as_bytes());
+        toc.push(0);
+    }
+    let mut frames = frame(b"CTOC", &toc);
+
+    for (index, chapter) in chapters.iter().enumerate() {
+        let mut chap = Vec::new();
+        chap.extend_from_slice(element_id(index).as_bytes());
+        chap.push(0);
+        chap.extend_from_slice(&chapter.start_ms.to_be_bytes());
+        chap.extend_from_slice(&chapter.end_ms.to_be_bytes());
+        // Byte offsets unused, times are authoritative
+        chap.extend_from_slice(&u32::MAX.to_be_bytes());
+        chap.extend_from_slice(&u32::MAX.to_be_bytes());
+        chap.extend(text_frame(b"TIT2", &chapter.title));
+        frames.extend(frame(b"CHAP", &chap));
+    }
+
+    frames
+}
+
+/// Complete ID3v2.4 tag around already encoded frames
+pub fn tag(frames: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(10 + frames.len());
+    bytes.extend_from_slice(b"ID3");
+    bytes.extend_from_slice(&[4, 0, 0]);
+    bytes.extend_from_slice(&synchsafe(frames.len() as u32));
+    bytes.extend_from_slice(frames);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synchsafe() {
+        assert_eq!(synchsafe(0x7F), [0, 0, 0, 0x7F]);
+        assert_eq!(synchsafe(0x80), [0, 0, 1, 0]);
+        assert_eq!(synchsafe(0x0FFF_FFFF), [0x7F; 4]);
+    }
+
+    #[test]
+    fn test_chapter_frames_layout() {
+        let frames = chapter_frames(&[Id3Chapter {
+            title: "Intro".to_string(),
+            start_ms: 0,
+            end_ms: 61_500,
+        }]);
+
+        let toc_payload = b"toc\0\x03\x01chp0\0";
+        assert_eq!(&frames[..4], b"CTOC");
+        assert_eq!(&frames[4..8], &synchsafe(toc_payload.len() as u32));
+        assert_eq!(&frames[10..10 + toc_payload.len()], toc_payload);
+
+        let chap = &frames[10 + toc_payload.len()..];
+        assert_eq!(&chap[..4], b"CHAP");
+        assert_eq!(&chap[10..15], b"chp0\0");
+        assert_eq!(&chap[15..19], &0u32.to_be_bytes());
+        assert_eq!(&chap[19..23], &61_500u32.to_be_bytes());
+        assert_eq!(&chap[31..35], b"TIT2");
+        assert_eq!(&chap[41..], b"\x03Intro");
+    }
+}
@@ -0,0 +1,590 @@
+use super::chapters;
+use super::decoder::OPUS_SAMPLE_RATE;
+use super::types::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const OTIO_FILENAME: &str = "session.otio";
+pub const FCPXML_FILENAME: &str = "session.fcpxml";
+
+/// Timelines are expressed in audio samples so sync stays sample accurate
+const TIMELINE_RATE: u32 = OPUS_SAMPLE_RATE;
+
+/// Timeline files written next to the metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineFiles {
+    pub otio: PathBuf,
+    pub fcpxml: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MediaKind {
+    Audio,
+    Video,
+}
+
+/// One participant file placed on the session timeline
+struct TimelineClip<'a> {
+    participant: &'a str,
+    kind: MediaKind,
+    file: &'a Path,
+    offset_seconds: f64,
+    length_seconds: f64,
+}
+
+/// Marker, chapter or pause span on the session timeline
+struct TimelineMarker {
+    label: String,
+    kind: Option<MarkerKind>,
+    start_seconds: f64,
+    duration_seconds: f64,
+}
+
+/// Audio then video clip of every participant, in joining order
+fn timeline_clips(metadata: &RecordingMetadata) -> Vec<TimelineClip<'_>> {
+    let mut participants: Vec<&ParticipantMetadata> = metadata.participants.values().collect();
+    participants.sort_by_key(|p| p.joined_at);
+
+    let mut clips = Vec::new();
+    for participant in participants {
+        let offset = metadata.participant_offset_seconds(participant);
+        let end = offset + metadata.participant_length_seconds(participant);
+        // The video track starts a little off the audio one
+        let video_start = (offset + participant.video_offset_seconds.unwrap_or(0.0)).max(0.0);
+        let files = [
+            (MediaKind::Audio, &participant.audio_file, offset),
+            (MediaKind::Video, &participant.video_file, video_start),
+        ];
+        for (kind, file, start) in files {
+            if let Some(file) = file {
+                clips.push(TimelineClip {
+                    participant: &participant.name,
+                    kind,
+                    file,
+                    offset_seconds: start,
+                    length_seconds: (end - start).max(0.0),
+                });
+            }
+        }
+    }
+    clips
+}
+
+fn timeline_markers(metadata: &RecordingMetadata) -> Vec<TimelineMarker> {
+    let chapters = chapters::chapters(metadata);
+    let mut markers: Vec<TimelineMarker> = metadata
+        .markers
+        .iter()
+        .map(|marker| TimelineMarker {
+            label: marker.label.clone(),
+            kind: Some(marker.kind),
+            start_seconds: marker.position_seconds,
+            duration_seconds: match marker.kind {
+                MarkerKind::Note => 0.0,
+                MarkerKind::Chapter => chapters
+                    .iter()
+                    .find(|c| c.start_seconds == marker.position_seconds)
+                    .map_or(0.0, |c| c.end_seconds - c.start_seconds),
+            },
+        })
+        .collect();
+
+    // Paused time is not in the tracks, a pause is the point they resume at
+    markers.extend(metadata.pauses.iter().map(|pause| TimelineMarker {
+        label: "Paused".to_string(),
+        kind: None,
+        start_seconds: pause.position_seconds,
+        duration_seconds: 0.0,
+    }));
+
+    markers.sort_by(|a, b| a.start_seconds.total_cmp(&b.start_seconds));
+    markers
+}
+
+fn samples(seconds: f64) -> u64 {
+    (seconds.max(0.0) * TIMELINE_RATE as f64).round() as u64
+}
+
+/// `file://` URL of a path, percent-encoding anything outside the unreserved set
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+fn otio_time(seconds: f64) -> Value {
+    json!({
+        "OTIO_SCHEMA": "RationalTime.1",
+        "rate": TIMELINE_RATE as f64,
+        "value": samples(seconds) as f64,
+    })
+}
+
+fn otio_range(start_seconds: f64, duration_seconds: f64) -> Value {
+    json!({
+        "OTIO_SCHEMA": "TimeRange.1",
+        "start_time": otio_time(start_seconds),
+        "duration": otio_time(duration_seconds),
+    })
+}
+
+fn otio_track(clip: &TimelineClip) -> Value {
+    let (kind, suffix) = match clip.kind {
+        MediaKind::Audio => ("Audio", "audio"),
+        MediaKind::Video => ("Video", "video"),
+    };
+    let mut children = Vec::new();
+    if samples(clip.offset_seconds) > 0 {
+        children.push(json!({
+            "OTIO_SCHEMA": "Gap.1",
+            "name": "",
+            "source_range": otio_range(0.0, clip.offset_seconds),
+            "effects": [],
+            "markers": [],
+            "enabled": true,
+            "metadata": {},
+        }));
+    }
+    children.push(json!({
+        "OTIO_SCHEMA": "Clip.2",
+        "name": format!("{} {}", clip.participant, suffix),
+        "source_range": otio_range(0.0, clip.length_seconds),
+        "media_references": {
+            "DEFAULT_MEDIA": {
+                "OTIO_SCHEMA": "ExternalReference.1",
+                "name": "",
+                "target_url": file_url(clip.file),
+                "available_range": null,
+                "metadata": {},
+            }
+        },
+        "active_media_reference_key": "DEFAULT_MEDIA",
+        "effects": [],
+        "markers": [],
+        "enabled": true,
+        "metadata": {},
+    }));
+
+    json!({
+        "OTIO_SCHEMA": "Track.1",
+        "name": clip.participant,
+        "kind": kind,
+        "source_range": null,
+        "children": children,
+        "effects": [],
+        "markers": [],
+        "enabled": true,
+        "metadata": {},
+    })
+}
+
+fn otio_marker(marker: &TimelineMarker) -> Value {
+    let color = match marker.kind {
+        Some(MarkerKind::Note) => "RED",
+        Some(MarkerKind::Chapter) => "GREEN",
+        None => "YELLOW",
+    };
+    json!({
+        "OTIO_SCHEMA": "Marker.2",
+        "name": marker.label,
+        "color": color,
+        "marked_range": otio_range(marker.start_seconds, marker.duration_seconds),
+        "comment": "",
+        "metadata": {},
+    })
+}
+
+/// OpenTimelineIO timeline, one track per participant file
+pub fn otio_timeline(metadata: &RecordingMetadata) -> Value {
+    let clips = timeline_clips(metadata);
+    // Video tracks first, NLEs stack them above the audio
+    let tracks: Vec<Value> = clips
+        .iter()
+        .filter(|c| c.kind == MediaKind::Video)
+        .chain(clips.iter().filter(|c| c.kind == MediaKind::Audio))
+        .map(otio_track)
+        .collect();
+    let markers: Vec<Value> = timeline_markers(metadata).iter().map(otio_marker).collect();
+
+    json!({
+        "OTIO_SCHEMA": "Timeline.1",
+        "name": metadata.id,
+        "global_start_time": null,
+        "metadata": {},
+        "tracks": {
+            "OTIO_SCHEMA": "Stack.1",
+            "name": "tracks",
+            "source_range": null,
+            "children": tracks,
+            "effects": [],
+            "markers": markers,
+            "enabled": true,
+            "metadata": {},
+        },
+    })
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Rational FCPXML time, `0s` or `samples/rate` seconds
+fn fcp_time(samples: u64) -> String {
+    if samples == 0 {
+        "0s".to_string()
+    } else {
+        format!("{}/{}s", samples, TIMELINE_RATE)
+    }
+}
+
+/// Width, height and frame rate of the sequence
+///
+/// Taken from the first video track found while recording, then from the
+/// configured format, field by field, with the `RecordingConfig` defaults for
+/// older recordings that have neither.
+fn sequence_format(metadata: &RecordingMetadata) -> (u32, u32, f64) {
+    let mut participants: Vec<&ParticipantMetadata> = metadata
+        .participants
+        .values()
+        .filter(|p| p.video_file.is_some())
+        .collect();
+    participants.sort_by_key(|p| p.joined_at);
+    let formats: Vec<&MediaFormat> = participants
+        .iter()
+        .map(|p| &p.format)
+        .chain(metadata.configured_format.as_ref())
+        .collect();
+
+    let defaults = RecordingConfig::default();
+    let width = formats.iter().find_map(|f| f.video_width);
+    let height = formats.iter().find_map(|f| f.video_height);
+    let fps = formats
+        .iter()
+        .find_map(|f| f.video_fps.filter(|fps| *fps >= 1.0));
+    (
+        width.unwrap_or(defaults.video_width),
+        height.unwrap_or(defaults.video_height),
+        fps.unwrap_or(defaults.video_fps as f64),
+    )
+}
+
+/// FCPXML 1.9 project: a gap spanning the session with every participant
+/// file connected to it on its own lane
+pub fn fcpxml(metadata: &RecordingMetadata) -> String {
+    let (width, height, fps) = sequence_format(metadata);
+    let frame = ((TIMELINE_RATE as f64 / fps).round() as u64).max(1);
+    // Video has to start on a frame boundary
+    let to_frame = |samples: u64| (samples + frame / 2) / frame * frame;
+
+    let clips = timeline_clips(metadata);
+    let markers = timeline_markers(metadata);
+    let duration = clips
+        .iter()
+        .map(|c| samples(c.offset_seconds + c.length_seconds))
+        .chain(
+            markers
+                .iter()
+                .map(|m| samples(m.start_seconds + m.duration_seconds)),
+        )
+        .max()
+        .unwrap_or(0)
+        .max(samples(metadata.session_length_seconds()));
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE fcpxml>\n");
+    xml.push_str("<fcpxml version=\"1.9\">\n  <resources>\n");
+    xml.push_str(&format!(
+        "    <format id=\"r0\" frameDuration=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+        fcp_time(frame),
+        width,
+        height
+    ));
+    for (index, clip) in clips.iter().enumerate() {
+        let media = match clip.kind {
+            MediaKind::Audio => format!(
+                "hasAudio=\"1\" audioSources=\"1\" audioRate=\"{}\"",
+                TIMELINE_RATE
+            ),
+            MediaKind::Video => "hasVideo=\"1\" format=\"r0\"".to_string(),
+        };
+        xml.push_str(&format!(
+            "    <asset id=\"r{}\" name=\"{}\" start=\"0s\" duration=\"{}\" {}>\n",
+            index + 1,
+            xml_escape(&clip.file.file_name().unwrap_or_default().to_string_lossy()),
+            fcp_time(samples(clip.length_seconds)),
+            media
+        ));
+        xml.push_str(&format!(
+            "      <media-rep kind=\"original-media\" src=\"{}\"/>\n    </asset>\n",
+            xml_escape(&file_url(clip.file))
+        ));
+    }
+    xml.push_str("  </resources>\n  <library>\n");
+    xml.push_str(&format!(
+        "    <event name=\"{}\">\n      <project name=\"{}\">\n",
+        xml_escape(&metadata.room_id),
+        xml_escape(&metadata.id)
+    ));
+    xml.push_str(&format!(
+        "        <sequence format=\"r0\" duration=\"{}\" tcStart=\"0s\" tcFormat=\"NDF\" \
+         audioLayout=\"stereo\" audioRate=\"48k\">\n          <spine>\n",
+        fcp_time(duration)
+    ));
+    xml.push_str(&format!(
+        "            <gap name=\"Session\" offset=\"0s\" start=\"0s\" duration=\"{}\">\n",
+        fcp_time(duration)
+    ));
+
+    // Video lanes above the gap, audio lanes below
+    let (mut video_lane, mut audio_lane) = (0i32, 0i32);
+    for (index, clip) in clips.iter().enumerate() {
+        let (lane, offset, suffix) = match clip.kind {
+            MediaKind::Audio => {
+                audio_lane -= 1;
+                (audio_lane, samples(clip.offset_seconds), "audio")
+            }
+            MediaKind::Video => {
+                video_lane += 1;
+                (video_lane, to_frame(samples(clip.offset_seconds)), "video")
+            }
+        };
+        xml.push_str(&format!(
+            "              <asset-clip ref=\"r{}\" lane=\"{}\" offset=\"{}\" name=\"{}\" \
+             start=\"0s\" duration=\"{}\"/>\n",
+            index + 1,
+            lane,
+            fcp_time(offset),
+            xml_escape(&format!("{} {}", clip.participant, suffix)),
+            fcp_time(samples(clip.length_seconds))
+        ));
+    }
+
+    for marker in &markers {
+        let element = match marker.kind {
+            Some(MarkerKind::Chapter) => "chapter-marker",
+            _ => "marker",
+        };
+        let poster = if element == "chapter-marker" {
+            " posterOffset=\"0s\""
+        } else {
+            ""
+        };
+        xml.push_str(&format!(
+            "              <{} start=\"{}\" duration=\"{}\" value=\"{}\"{}/>\n",
+            element,
+            fcp_time(samples(marker.start_seconds)),
+            fcp_time(samples(marker.duration_seconds).max(frame)),
+            xml_escape(&marker.label),
+            poster
+        ));
+    }
+
+    xml.push_str("            </gap>\n          </spine>\n        </sequence>\n");
+    xml.push_str("      </project>\n    </event>\n  </library>\n</fcpxml>\n");
+    xml
+}
+
+/// Write the OpenTimelineIO and FCPXML timelines into the recording directory
+pub fn export_timelines(metadata: &RecordingMetadata) -> RecordingResult<TimelineFiles> {
+    let dir = &metadata.output_directory;
+    log::info!("Exporting interchange timelines to {:?}", dir);
+
+    let files = TimelineFiles {
+        otio: dir.join(OTIO_FILENAME),
+        fcpxml: dir.join(FCPXML_FILENAME),
+    };
+    let otio = serde_json::to_string_pretty(&otio_timeline(metadata))
+        .map_err(|e| RecordingError::IoError(std::io::Error::other(e)))?;
+    fs::write(&files.otio, otio)?;
+    fs::write(&files.fcpxml, fcpxml(metadata))?;
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{participants, recording};
+    use chrono::{Duration, Utc};
+
+    fn session() -> RecordingMetadata {
+        let started_at = Utc::now();
+        let dir = PathBuf::from("/recordings/recording-room-1");
+        let participant = |id: &str, name: &str, joined: i64, video: bool| ParticipantMetadata {
+            audio_file: Some(dir.join(format!("{}-audio.webm", id))),
+            video_file: video.then(|| dir.join(format!("{}-video.webm", id))),
+            ..ParticipantMetadata::new(
+                id.to_string(),
+                name.to_string(),
+                started_at + Duration::milliseconds(joined),
+            )
+        };
+
+        RecordingMetadata {
+            id: "recording-room-1".to_string(),
+            participants: participants([
+                participant("p1", "Host", 0, true),
+                participant("p2", "Zoë & co", 12_510, false),
+            ]),
+            output_directory: dir.clone(),
+            markers: vec![Marker {
+                id: "marker-1".to_string(),
+                label: "Intro".to_string(),
+                kind: MarkerKind::Chapter,
+                position_seconds: 0.0,
+                created_at: started_at,
+            }],
+            pauses: vec![Pause {
+                paused_at: started_at + Duration::seconds(100),
+                resumed_at: Some(started_at + Duration::seconds(120)),
+                position_seconds: 100.0,
+            }],
+            ..recording(started_at, 600)
+        }
+    }
+
+    #[test]
+    fn test_otio_timeline() {
+        let otio = otio_timeline(&session());
+        let tracks = otio["tracks"]["children"].as_array().unwrap();
+
+        let kinds: Vec<&str> = tracks.iter().map(|t| t["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["Video", "Audio", "Audio"]);
+
+        // Late joiner starts after a gap of their offset
+        let guest = &tracks[2]["children"];
+        assert_eq!(guest[0]["OTIO_SCHEMA"], "Gap.1");
+        assert_eq!(guest[0]["source_range"]["duration"]["value"], 600480.0);
+        assert_eq!(guest[1]["source_range"]["duration"]["value"], 27239520.0);
+        assert_eq!(
+            guest[1]["media_references"]["DEFAULT_MEDIA"]["target_url"],
+            "file:///recordings/recording-room-1/p2-audio.webm"
+        );
+
+        let markers = otio["tracks"]["markers"].as_array().unwrap();
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[1]["name"], "Paused");
+        assert_eq!(markers[1]["marked_range"]["start_time"]["value"], 4800000.0);
+        assert_eq!(markers[1]["marked_range"]["duration"]["value"], 0.0);
+    }
+
+    #[test]
+    fn test_fcpxml() {
+        let xml = fcpxml(&session());
+
+        assert!(xml.contains("<format id=\"r0\" frameDuration=\"1600/48000s\""));
+        assert!(xml.contains("<asset id=\"r2\" name=\"p1-video.webm\""));
+        assert!(xml.contains("src=\"file:///recordings/recording-room-1/p1-video.webm\""));
+        assert!(xml.contains("<asset-clip ref=\"r2\" lane=\"1\" offset=\"0s\" name=\"Host video\""));
+        assert!(xml.contains(
+            "<asset-clip ref=\"r3\" lane=\"-2\" offset=\"600480/48000s\" name=\"Zoë &amp; co audio\""
+        ));
+        assert!(xml
+            .contains("<chapter-marker start=\"0s\" duration=\"27840000/48000s\" value=\"Intro\""));
+        assert!(xml.contains(
+            "<marker start=\"4800000/48000s\" duration=\"1600/48000s\" value=\"Paused\"/>"
+        ));
+        assert!(xml.ends_with("</fcpxml>\n"));
+    }
+
+    #[test]
+    fn test_file_url_escapes() {
+        assert_eq!(
+            file_url(Path::new("/rec/Zoë & co.webm")),
+            "file:///rec/Zo%C3%AB%20%26%20co.webm"
+        );
+        assert_eq!(
+            file_url(Path::new("C:\\rec\\a.webm")),
+            "file:///C:/rec/a.webm"
+        );
+    }
+
+    #[test]
+    fn test_fcpxml_uses_recorded_format() {
+        let mut metadata = session();
+        metadata.configured_format = Some(MediaFormat {
+            video_width: Some(1280),
+            video_height: Some(720),
+            video_fps: Some(30.0),
+            ..Default::default()
+        });
+        assert!(fcpxml(&metadata).contains(
+            "<format id=\"r0\" frameDuration=\"1600/48000s\" width=\"1280\" height=\"720\"/>"
+        ));
+
+        // The host camera ran at 25 fps whatever was asked for
+        let host = metadata.participants.get_mut("p1").unwrap();
+        host.format.video_fps = Some(25.0);
+        assert!(fcpxml(&metadata).contains(
+            "<format id=\"r0\" frameDuration=\"1920/48000s\" width=\"1280\" height=\"720\"/>"
+        ));
+    }
+
+    #[test]
+    fn test_marker_after_pause_lines_up_with_clips() {
+        // Guest joins 10s after the 20s pause ends, 110s into the tracks, and
+        // the host marks the moment
+        let mut metadata = session();
+        let joined_at = metadata.started_at + Duration::seconds(130);
+        metadata.participants.get_mut("p2").unwrap().joined_at = joined_at;
+        metadata.markers.push(Marker {
+            id: "marker-2".to_string(),
+            label: "Guest joins".to_string(),
+            kind: MarkerKind::Note,
+            position_seconds: metadata.session_position_seconds(joined_at),
+            created_at: joined_at,
+        });
+
+        let otio = otio_timeline(&metadata);
+        let guest = &otio["tracks"]["children"][2]["children"];
+        let marker = otio["tracks"]["markers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|m| m["name"] == "Guest joins")
+            .unwrap();
+        assert_eq!(guest[0]["source_range"]["duration"]["value"], 5280000.0);
+        assert_eq!(marker["marked_range"]["start_time"]["value"], 5280000.0);
+
+        let xml = fcpxml(&metadata);
+        assert!(xml.contains("lane=\"-2\" offset=\"5280000/48000s\""));
+        assert!(xml.contains("<marker start=\"5280000/48000s\""));
+    }
+    #[test]
+    fn test_video_is_placed_at_its_offset() {
+        // The host camera started half a second after the microphone
+        let mut metadata = session();
+        let host = metadata.participants.get_mut("p1").unwrap();
+        host.video_offset_seconds = Some(0.5);
+
+        let otio = otio_timeline(&metadata);
+        let video = &otio["tracks"]["children"][0]["children"];
+        assert_eq!(video[0]["OTIO_SCHEMA"], "Gap.1");
+        assert_eq!(video[0]["source_range"]["duration"]["value"], 24000.0);
+        assert_eq!(video[1]["source_range"]["duration"]["value"], 27816000.0);
+        let audio = &otio["tracks"]["children"][1]["children"];
+        assert_eq!(audio[0]["OTIO_SCHEMA"], "Clip.2");
+
+        let xml = fcpxml(&metadata);
+        assert!(xml.contains("lane=\"1\" offset=\"24000/48000s\" name=\"Host video\""));
+        assert!(xml.contains("lane=\"-1\" offset=\"0s\" name=\"Host audio\""));
+    }
+}
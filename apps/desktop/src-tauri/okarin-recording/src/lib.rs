@@ -0,0 +1,52 @@
+pub mod activity;
+pub mod align;
+pub mod analysis;
+pub mod avmux;
+pub mod bleed;
+pub mod chapters;
+pub mod cleanup;
+pub mod correlation;
+pub mod decoder;
+pub mod dsp;
+pub mod encoder;
+pub mod export;
+pub mod feed;
+pub mod format;
+pub mod id3;
+pub mod interchange;
+pub mod library;
+pub mod loudness;
+pub mod meter;
+pub mod mixdown;
+pub mod mixer;
+pub mod mp4;
+pub mod muxer;
+pub mod normalize;
+pub mod ogg;
+pub mod probe;
+pub mod project;
+pub mod publish;
+pub mod recorder;
+pub mod repair;
+pub mod storage;
+pub mod sync;
+#[cfg(test)]
+mod test_support;
+pub mod track;
+pub mod trim;
+pub mod types;
+pub mod wav;
+pub mod waveform;
+pub mod webm;
+
+pub use recorder::RecordingManager;
+pub use storage::StorageManager;
+pub use track::TrackRecorder;
+pub use types::{
+    ActivityTimeline, AudioLevel, BleedReport, CleanupExport, CleanupOptions, EpisodeMetadata,
+    ExternalAlignment, FeedExport, FeedSettings, FormatMismatch, LibraryEntry, LibraryIndex,
+    LibraryQuery, LoudnessReport, Marker, MarkerKind, MediaFormat, MixdownOptions,
+    NormalizationExport, NormalizationOptions, ProbeReport, PublishExport, PublishFormat,
+    PublishOptions, RecordingConfig, RecordingError, RecordingMetadata, RecordingResult,
+    RecordingStatus, RepairReport, TrackWarning, TrimExport,
+};
@@ -0,0 +1,281 @@
+use super::storage;
+use super::types::*;
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Latest change of the JSON files describing a recording
+fn modified_at(dir: &Path) -> Option<DateTime<Utc>> {
+    [storage::METADATA_FILENAME, storage::EPISODE_FILENAME]
+        .iter()
+        .filter_map(|name| fs::metadata(dir.join(name)).and_then(|m| m.modified()).ok())
+        .max()
+        .map(DateTime::<Utc>::from)
+}
+
+fn read_entry(dir: &Path, modified_at: DateTime<Utc>) -> RecordingResult<LibraryEntry> {
+    let metadata = storage::load_metadata(dir)?;
+    let episode = storage::load_episode(dir)?;
+
+    let mut participants: Vec<&ParticipantMetadata> = metadata.participants.values().collect();
+    participants.sort_by_key(|p| p.joined_at);
+
+    Ok(LibraryEntry {
+        id: metadata.id.clone(),
+        room_id: metadata.room_id.clone(),
+        directory: dir.to_path_buf(),
+        started_at: metadata.started_at,
+        stopped_at: metadata.stopped_at,
+        duration_seconds: metadata.session_length_seconds(),
+        participants: participants.iter().map(|p| p.name.clone()).collect(),
+        episode_title: episode
+            .as_ref()
+            .map(|e| e.title.clone())
+            .filter(|title| !title.is_empty()),
+        published: episode.is_some_and(|e| e.export.is_some()),
+        modified_at,
+    })
+}
+
+/// Bring `library.json` up to date with the recordings root
+///
+/// Only recordings whose JSON files changed since the last scan are parsed
+/// again. Unreadable recordings are left out with a warning, and a corrupt
+/// index is rebuilt from scratch. A root that does not exist yet is an empty
+/// library.
+pub fn refresh_index(recordings_root: &Path) -> RecordingResult<LibraryIndex> {
+    // Entries are keyed by directory, the same root reached through a
+    // relative path or a symlink must find them again
+    let recordings_root = match recordings_root.canonicalize() {
+        Ok(root) => root,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(LibraryIndex::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let recordings_root = recordings_root.as_path();
+
+    let previous = storage::load_library_index(recordings_root).unwrap_or_else(|e| {
+        log::warn!("Rebuilding the library index: {}", e);
+        LibraryIndex::default()
+    });
+    let mut known: HashMap<PathBuf, LibraryEntry> = previous
+        .recordings
+        .into_iter()
+        .map(|entry| (entry.directory.clone(), entry))
+        .collect();
+
+    let mut recordings = Vec::new();
+    let mut changed = previous.updated_at.is_none();
+    for entry in fs::read_dir(recordings_root)? {
+        let dir = entry?.path();
+        if !dir.join(storage::METADATA_FILENAME).is_file() {
+            continue;
+        }
+        let Some(modified_at) = modified_at(&dir) else {
+            continue;
+        };
+
+        match known.remove(&dir) {
+            Some(entry) if entry.modified_at == modified_at => recordings.push(entry),
+            _ => {
+                changed = true;
+                match read_entry(&dir, modified_at) {
+                    Ok(entry) => recordings.push(entry),
+                    Err(e) => log::warn!("Leaving {:?} out of the library: {}", dir, e),
+                }
+            }
+        }
+    }
+    // Whatever is left was deleted or moved away
+    changed |= !known.is_empty();
+
+    recordings.sort_by_key(|entry| Reverse(entry.started_at));
+    let mut index = LibraryIndex {
+        updated_at: previous.updated_at,
+        recordings,
+    };
+    if changed {
+        log::info!(
+            "Library index of {:?} now lists {} recordings",
+            recordings_root,
+            index.recordings.len()
+        );
+        index.updated_at = Some(Utc::now());
+        storage::write_library_index(recordings_root, &index)?;
+    }
+    Ok(index)
+}
+
+/// Every recording of the recordings root, newest first
+pub fn list_recordings(recordings_root: &Path) -> RecordingResult<Vec<LibraryEntry>> {
+    Ok(refresh_index(recordings_root)?.recordings)
+}
+
+pub fn find_recording(recordings_root: &Path, id: &str) -> RecordingResult<LibraryEntry> {
+    refresh_index(recordings_root)?
+        .recordings
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| RecordingError::RecordingNotFound(id.to_string()))
+}
+
+/// Full metadata of a recording of the library
+pub fn get_recording(recordings_root: &Path, id: &str) -> RecordingResult<RecordingMetadata> {
+    storage::load_metadata(&find_recording(recordings_root, id)?.directory)
+}
+
+/// Whether an entry passes every filter of the query
+///
+/// The text is also looked for in the start date, `2026-03` finds every
+/// session of March 2026.
+pub fn entry_matches(entry: &LibraryEntry, query: &LibraryQuery) -> bool {
+    if query.from.is_some_and(|from| entry.started_at < from)
+        || query.to.is_some_and(|to| entry.started_at >= to)
+    {
+        return false;
+    }
+
+    let Some(text) = query
+        .text
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    else {
+        return true;
+    };
+    let text = text.to_lowercase();
+    let date = entry.started_at.format("%Y-%m-%d").to_string();
+    std::iter::once(&entry.room_id)
+        .chain(&entry.participants)
+        .chain(&entry.episode_title)
+        .chain(std::iter::once(&date))
+        .any(|field| field.to_lowercase().contains(&text))
+}
+
+pub fn search_recordings(
+    recordings_root: &Path,
+    query: &LibraryQuery,
+) -> RecordingResult<Vec<LibraryEntry>> {
+    let mut recordings = list_recordings(recordings_root)?;
+    recordings.retain(|entry| entry_matches(entry, query));
+    Ok(recordings)
+}
+
+/// Delete a recording directory with every file in it, and drop it from the index
+pub fn delete_recording(recordings_root: &Path, id: &str) -> RecordingResult<LibraryEntry> {
+    let entry = find_recording(recordings_root, id)?;
+
+    // Never follow a stale or hand-edited index outside the recordings root
+    let root = recordings_root.canonicalize()?;
+    let dir = entry.directory.canonicalize()?;
+    if dir == root || !dir.starts_with(&root) {
+        return Err(RecordingError::InvalidConfig(format!(
+            "{:?} is not inside the recordings root",
+            entry.directory
+        )));
+    }
+
+    log::info!("Deleting recording {} at {:?}", id, dir);
+    fs::remove_dir_all(&dir)?;
+    refresh_index(recordings_root)?;
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{participant, participants, recording, temp_dir};
+    use chrono::{Duration, TimeZone};
+
+    fn write_recording(root: &Path, id: &str, room_id: &str, day: u32, names: &[&str]) {
+        let dir = root.join(id);
+        fs::create_dir_all(&dir).unwrap();
+        let started_at = Utc.with_ymd_and_hms(2026, 3, day, 18, 0, 0).unwrap();
+        let joined = names.iter().enumerate().map(|(index, name)| {
+            let joined_at = started_at + Duration::seconds(index as i64);
+            participant(&format!("p{}", index + 1), name, joined_at)
+        });
+        let metadata = RecordingMetadata {
+            id: id.to_string(),
+            room_id: room_id.to_string(),
+            participants: participants(joined),
+            output_directory: dir.clone(),
+            ..recording(started_at, 600)
+        };
+        storage::write_metadata(&dir, &metadata).unwrap();
+    }
+
+    fn ids(entries: &[LibraryEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_index_search_and_delete() {
+        let root = temp_dir("library");
+        fs::create_dir_all(root.join("not-a-recording")).unwrap();
+        write_recording(&root, "rec-a", "weekly", 2, &["Ada", "Grace"]);
+        write_recording(&root, "rec-b", "interview", 9, &["Linus"]);
+
+        let listed = list_recordings(&root).unwrap();
+        assert_eq!(ids(&listed), vec!["rec-b", "rec-a"]);
+        assert_eq!(listed[1].participants, vec!["Ada", "Grace"]);
+        assert_eq!(listed[1].duration_seconds, 600.0);
+        assert!(root.join(storage::LIBRARY_INDEX_FILENAME).is_file());
+
+        let search = |text: Option<&str>, from: Option<u32>| {
+            let query = LibraryQuery {
+                text: text.map(str::to_string),
+                from: from.map(|day| Utc.with_ymd_and_hms(2026, 3, day, 0, 0, 0).unwrap()),
+                to: None,
+            };
+            search_recordings(&root, &query).unwrap()
+        };
+        assert_eq!(ids(&search(Some("grace"), None)), vec!["rec-a"]);
+        assert_eq!(ids(&search(Some("INTER"), None)), vec!["rec-b"]);
+        assert_eq!(ids(&search(Some("2026-03-02"), None)), vec!["rec-a"]);
+        assert_eq!(ids(&search(None, Some(5))), vec!["rec-b"]);
+        assert_eq!(ids(&search(Some("ada"), Some(5))), Vec::<&str>::new());
+
+        assert_eq!(get_recording(&root, "rec-a").unwrap().room_id, "weekly");
+        let deleted = delete_recording(&root, "rec-a").unwrap();
+        let remaining = storage::load_library_index(&root).unwrap();
+        let missing = get_recording(&root, "rec-a");
+        let dir_left = deleted.directory.exists();
+        fs::remove_dir_all(&root).ok();
+
+        assert!(!dir_left);
+        assert_eq!(ids(&remaining.recordings), vec!["rec-b"]);
+        assert!(matches!(missing, Err(RecordingError::RecordingNotFound(_))));
+    }
+
+    #[test]
+    fn test_missing_root_is_an_empty_library() {
+        let dir = temp_dir("library-missing");
+        let root = dir.join("Okarin");
+        let entries = list_recordings(&root).unwrap();
+        let created = root.exists();
+        fs::remove_dir_all(&dir).ok();
+        assert!(entries.is_empty());
+        assert!(!created);
+    }
+
+    #[test]
+    fn test_index_is_keyed_by_canonical_root() {
+        let root = temp_dir("library-canonical");
+        write_recording(&root, "rec-a", "weekly", 2, &["Ada"]);
+
+        let first = list_recordings(&root).unwrap();
+        let indexed_at = storage::load_library_index(&root).unwrap().updated_at;
+        // Same root spelled differently, nothing changed so nothing is rewritten
+        let second = list_recordings(&root.join("rec-a").join("..")).unwrap();
+        let reindexed_at = storage::load_library_index(&root).unwrap().updated_at;
+        let canonical = root.canonicalize().unwrap();
+        fs::remove_dir_all(&root).ok();
+
+        assert_eq!(first[0].directory, canonical.join("rec-a"));
+        assert_eq!(second[0].directory, first[0].directory);
+        assert_eq!(reindexed_at, indexed_at);
+    }
+}
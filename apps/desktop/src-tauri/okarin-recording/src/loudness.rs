@@ -0,0 +1,358 @@
+use super::decoder::{OpusTrackDecoder, OPUS_SAMPLE_RATE};
+use super::dsp::Biquad;
+use super::mixer::{TrackMixer, MIX_CHANNELS};
+use super::types::*;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// Gating block hop (100ms), the unit all R128 windows are built from
+const SUB_BLOCKS_PER_SECOND: u32 = 10;
+/// Momentary window: 400ms
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+/// Short-term window: 3s
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const INTEGRATED_RELATIVE_GATE_LU: f64 = -10.0;
+const RANGE_RELATIVE_GATE_LU: f64 = -20.0;
+
+/// True-peak oversampling factor and interpolation filter length per phase
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Frames read per analysis step when streaming decoded tracks
+const ANALYSIS_BLOCK_FRAMES: usize = 4800;
+
+/// ITU-R BS.1770 K-weighting: high-shelf pre-filter followed by the RLB high-pass
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// Windowed-sinc interpolation filter for 4x true-peak oversampling,
+/// split into one set of taps per output phase
+fn true_peak_filter() -> Vec<[f64; TAPS_PER_PHASE]> {
+    let length = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (length - 1) as f64 / 2.0;
+    let mut phases = vec![[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+
+    for n in 0..length {
+        let t = (n as f64 - center) / OVERSAMPLING as f64;
+        let sinc = if t == 0.0 {
+            1.0
+        } else {
+            (PI * t).sin() / (PI * t)
+        };
+        let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / length as f64).cos();
+        phases[n % OVERSAMPLING][n / OVERSAMPLING] = sinc * window;
+    }
+
+    phases
+}
+
+/// Estimates inter-sample peaks by 4x oversampling, one frame at a time
+pub struct TruePeakDetector {
+    filter: Vec<[f64; TAPS_PER_PHASE]>,
+    history: Vec<[f64; TAPS_PER_PHASE]>,
+}
+
+impl TruePeakDetector {
+    pub fn new(channels: u16) -> Self {
+        Self {
+            filter: true_peak_filter(),
+            history: vec![[0.0; TAPS_PER_PHASE]; channels.max(1) as usize],
+        }
+    }
+
+    /// Highest absolute (oversampled) value around one interleaved frame
+    pub fn process_frame(&mut self, frame: &[f32]) -> f64 {
+        let mut peak = 0.0f64;
+        for (history, &sample) in self.history.iter_mut().zip(frame) {
+            let sample = sample as f64;
+            history.copy_within(0..TAPS_PER_PHASE - 1, 1);
+            history[0] = sample;
+            for phase in &self.filter {
+                let value: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+                peak = peak.max(value.abs());
+            }
+            peak = peak.max(sample.abs());
+        }
+        peak
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+/// Streaming EBU R128 meter (integrated, LRA, short-term max, true peak)
+pub struct LoudnessMeter {
+    channels: usize,
+    sub_block_frames: usize,
+    filters: Vec<[Biquad; 2]>,
+    /// Weighted energy sum of the sub-block being filled, per channel
+    current: Vec<f64>,
+    current_frames: usize,
+    /// Mean-square energy (summed over channels) of each completed 100ms sub-block
+    sub_blocks: Vec<f64>,
+    peak_detector: TruePeakDetector,
+    true_peak: f64,
+    total_frames: u64,
+    sample_rate: u32,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            sub_block_frames: (sample_rate / SUB_BLOCKS_PER_SECOND) as usize,
+            filters: (0..channels).map(|_| k_weighting(sample_rate)).collect(),
+            current: vec![0.0; channels],
+            current_frames: 0,
+            sub_blocks: Vec::new(),
+            peak_detector: TruePeakDetector::new(channels as u16),
+            true_peak: 0.0,
+            total_frames: 0,
+            sample_rate,
+        }
+    }
+
+    /// Feed interleaved samples
+    pub fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let sample = sample as f64;
+
+                let [shelf, high_pass] = &mut self.filters[channel];
+                let weighted = high_pass.process(shelf.process(sample));
+                self.current[channel] += weighted * weighted;
+            }
+            self.true_peak = self.true_peak.max(self.peak_detector.process_frame(frame));
+
+            self.current_frames += 1;
+            self.total_frames += 1;
+            if self.current_frames == self.sub_block_frames {
+                let energy = self.current.iter().sum::<f64>() / self.sub_block_frames as f64;
+                self.sub_blocks.push(energy);
+                self.current.iter_mut().for_each(|e| *e = 0.0);
+                self.current_frames = 0;
+            }
+        }
+    }
+
+    /// Mean energies of every (overlapping) window of `length` sub-blocks
+    fn windows(&self, length: usize) -> Vec<f64> {
+        self.sub_blocks
+            .windows(length)
+            .map(|w| w.iter().sum::<f64>() / length as f64)
+            .collect()
+    }
+
+    pub fn finish(&self) -> LoudnessMeasurement {
+        // Integrated loudness: 400ms blocks, absolute then relative gate
+        let momentary: Vec<f64> = self
+            .windows(MOMENTARY_SUB_BLOCKS)
+            .into_iter()
+            .filter(|&e| energy_to_lufs(e) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        let integrated = gated_mean(&momentary, INTEGRATED_RELATIVE_GATE_LU).map(energy_to_lufs);
+
+        // Loudness range: 3s blocks, 10th to 95th percentile after gating
+        let short_term: Vec<f64> = self
+            .windows(SHORT_TERM_SUB_BLOCKS)
+            .into_iter()
+            .filter(|&e| energy_to_lufs(e) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        let short_term_max = short_term
+            .iter()
+            .cloned()
+            .fold(None, |max: Option<f64>, e| {
+                Some(max.map_or(e, |m| m.max(e)))
+            })
+            .map(energy_to_lufs);
+
+        let loudness_range = match short_term.iter().sum::<f64>() / short_term.len().max(1) as f64 {
+            mean if mean > 0.0 => {
+                let gate = lufs_to_energy(energy_to_lufs(mean) + RANGE_RELATIVE_GATE_LU);
+                let mut values: Vec<f64> = short_term
+                    .iter()
+                    .filter(|&&e| e > gate)
+                    .map(|&e| energy_to_lufs(e))
+                    .collect();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                percentile(&values, 0.95) - percentile(&values, 0.10)
+            }
+            _ => 0.0,
+        };
+
+        let true_peak = if self.true_peak > 0.0 {
+            Some(20.0 * self.true_peak.log10())
+        } else {
+            None
+        };
+
+        LoudnessMeasurement {
+            integrated_lufs: integrated,
+            loudness_range_lu: loudness_range,
+            short_term_max_lufs: short_term_max,
+            true_peak_dbtp: true_peak,
+            duration_seconds: self.total_frames as f64 / self.sample_rate as f64,
+        }
+    }
+}
+
+/// Mean energy of the blocks above `relative_gate` LU below the ungated mean
+fn gated_mean(blocks: &[f64], relative_gate: f64) -> Option<f64> {
+    if blocks.is_empty() {
+        return None;
+    }
+    let mean = blocks.iter().sum::<f64>() / blocks.len() as f64;
+    let gate = lufs_to_energy(energy_to_lufs(mean) + relative_gate);
+    let gated: Vec<f64> = blocks.iter().cloned().filter(|&e| e > gate).collect();
+    if gated.is_empty() {
+        return None;
+    }
+    Some(gated.iter().sum::<f64>() / gated.len() as f64)
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[index.min(sorted.len() - 1)]
+}
+
+/// Measure a single recorded participant audio file
+pub fn analyze_file(path: &std::path::Path) -> RecordingResult<LoudnessMeasurement> {
+    let decoder = OpusTrackDecoder::open(path)?;
+    let mut meter = LoudnessMeter::new(decoder.channels(), OPUS_SAMPLE_RATE);
+    for frame in decoder {
+        meter.push(&frame?.samples);
+    }
+    Ok(meter.finish())
+}
+
+/// Measure every participant track and the summed mix of a recording
+pub fn analyze_recording(metadata: &RecordingMetadata) -> RecordingResult<LoudnessReport> {
+    let mut tracks = HashMap::new();
+    for (participant_id, participant) in &metadata.participants {
+        if let Some(path) = &participant.audio_file {
+            log::info!("Analyzing loudness for participant: {}", participant_id);
+            tracks.insert(participant_id.clone(), analyze_file(path)?);
+        }
+    }
+
+    let mut mixer = TrackMixer::open(metadata)?;
+    let mix = if mixer.track_count() > 0 {
+        let mut meter = LoudnessMeter::new(MIX_CHANNELS, OPUS_SAMPLE_RATE);
+        while let Some(block) = mixer.read(ANALYSIS_BLOCK_FRAMES)? {
+            meter.push(&block);
+        }
+        Some(meter.finish())
+    } else {
+        None
+    };
+
+    Ok(LoudnessReport {
+        analyzed_at: Utc::now(),
+        tracks,
+        mix,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{interleave, sine};
+
+    fn db(value: f64) -> f64 {
+        10f64.powf(value / 20.0)
+    }
+
+    #[test]
+    fn test_stereo_sine_reference_level() {
+        // EBU Tech 3341 case 1: stereo 1kHz at -23 dBFS reads -23 LUFS
+        let mut meter = LoudnessMeter::new(2, 48000);
+        meter.push(&interleave(&sine(1000.0, db(-23.0), 20 * 48000), 2));
+        let result = meter.finish();
+
+        let integrated = result.integrated_lufs.unwrap();
+        assert!(
+            (integrated + 23.0).abs() < 0.1,
+            "integrated: {}",
+            integrated
+        );
+        assert!(result.loudness_range_lu < 0.1);
+        let short_term = result.short_term_max_lufs.unwrap();
+        assert!((short_term + 23.0).abs() < 0.1);
+        let true_peak = result.true_peak_dbtp.unwrap();
+        assert!((true_peak + 23.0).abs() < 0.2, "true peak: {}", true_peak);
+    }
+
+    #[test]
+    fn test_loudness_range() {
+        // EBU Tech 3342 case 1: 20s at -20 dBFS then 20s at -30 dBFS gives 10 LU
+        let mut meter = LoudnessMeter::new(2, 48000);
+        meter.push(&interleave(&sine(1000.0, db(-20.0), 20 * 48000), 2));
+        meter.push(&interleave(&sine(1000.0, db(-30.0), 20 * 48000), 2));
+        let range = meter.finish().loudness_range_lu;
+        assert!((range - 10.0).abs() < 1.0, "range: {}", range);
+    }
+
+    #[test]
+    fn test_true_peak_between_samples() {
+        // fs/4 sine with a 45 degree phase: samples sit at 0.707, the wave peaks at 1.0
+        let samples: Vec<f32> = (0..48000)
+            .map(|n| (0.5 * PI * n as f64 + PI / 4.0).sin() as f32)
+            .collect();
+        let mut meter = LoudnessMeter::new(1, 48000);
+        meter.push(&samples);
+        let true_peak = meter.finish().true_peak_dbtp.unwrap();
+        assert!(true_peak > -0.6, "true peak: {}", true_peak);
+    }
+
+    #[test]
+    fn test_silence_is_gated() {
+        let mut meter = LoudnessMeter::new(1, 48000);
+        meter.push(&vec![0.0; 48000 * 5]);
+        let result = meter.finish();
+        assert_eq!(result.integrated_lufs, None);
+        assert_eq!(result.short_term_max_lufs, None);
+        assert_eq!(result.true_peak_dbtp, None);
+        assert!((result.duration_seconds - 5.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,370 @@
+use super::decoder::{OpusBlockDecoder, PcmFrame, GAP_TOLERANCE_SAMPLES, OPUS_SAMPLE_RATE};
+use super::dsp::linear_to_db;
+use super::types::*;
+use super::webm::{WebmDemuxer, WebmEvent};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Length of one metering window, giving ~10 level updates per second
+const LEVEL_WINDOW_FRAMES: u64 = OPUS_SAMPLE_RATE as u64 / 10;
+/// Reported floor for digital silence, which has no finite dB value
+const METER_FLOOR_DB: f64 = -100.0;
+/// Windows peaking below this are counted as flat-line silence
+const SILENCE_THRESHOLD_DB: f64 = -60.0;
+/// Samples at or above this magnitude are counted as clipped
+const CLIPPING_THRESHOLD: f32 = 0.999;
+/// Minimum spacing between two clipping warnings of the same track
+const CLIPPING_WARNING_INTERVAL_FRAMES: u64 = OPUS_SAMPLE_RATE as u64 * 5;
+
+/// Something the level meter wants the frontend to know
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeterEvent {
+    Level(AudioLevel),
+    Warning(TrackWarning),
+}
+
+/// Receives meter events from the track recording threads
+pub type MeterListener = Arc<dyn Fn(MeterEvent) + Send + Sync>;
+
+fn level_db(linear: f64) -> f64 {
+    if linear > 0.0 {
+        linear_to_db(linear).max(METER_FLOOR_DB)
+    } else {
+        METER_FLOOR_DB
+    }
+}
+
+/// RMS/peak meter with silence and clipping detection for one track
+pub struct LevelMeter {
+    participant_id: String,
+    channels: usize,
+    silence_warning_frames: u64,
+    position: u64,
+    window_frames: u64,
+    sum_squares: f64,
+    peak: f32,
+    clipped: u64,
+    silent_frames: u64,
+    muted_warned: bool,
+    last_clipping_warning: Option<u64>,
+}
+
+impl LevelMeter {
+    pub fn new(participant_id: String, channels: u16, silence_warning_seconds: u32) -> Self {
+        Self {
+            participant_id,
+            channels: channels.max(1) as usize,
+            silence_warning_frames: silence_warning_seconds as u64 * OPUS_SAMPLE_RATE as u64,
+            position: 0,
+            window_frames: 0,
+            sum_squares: 0.0,
+            peak: 0.0,
+            clipped: 0,
+            silent_frames: 0,
+            muted_warned: false,
+            last_clipping_warning: None,
+        }
+    }
+
+    /// Meter interleaved samples, appending an event for every completed window
+    pub fn process(&mut self, samples: &[f32], events: &mut Vec<MeterEvent>) {
+        for frame in samples.chunks_exact(self.channels) {
+            for &sample in frame {
+                self.sum_squares += (sample as f64) * (sample as f64);
+                self.peak = self.peak.max(sample.abs());
+                if sample.abs() >= CLIPPING_THRESHOLD {
+                    self.clipped += 1;
+                }
+            }
+            self.window_frames += 1;
+            self.position += 1;
+
+            if self.window_frames == LEVEL_WINDOW_FRAMES {
+                self.finish_window(events);
+            }
+        }
+    }
+
+    /// Account for a stretch with no audio at all (e.g. DTX or a dropped chunk)
+    pub fn process_gap(&mut self, frames: u64, events: &mut Vec<MeterEvent>) {
+        self.window_frames = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+        self.clipped = 0;
+        self.position += frames;
+        self.silent_frames += frames;
+        self.check_silence(events);
+    }
+
+    fn finish_window(&mut self, events: &mut Vec<MeterEvent>) {
+        let samples = (self.window_frames as usize * self.channels) as f64;
+        let rms_db = level_db((self.sum_squares / samples).sqrt());
+        let peak_db = level_db(self.peak as f64);
+        let timestamp_seconds = self.position as f64 / OPUS_SAMPLE_RATE as f64;
+
+        events.push(MeterEvent::Level(AudioLevel {
+            participant_id: self.participant_id.clone(),
+            timestamp_seconds,
+            rms_db,
+            peak_db,
+        }));
+
+        if peak_db < SILENCE_THRESHOLD_DB {
+            self.silent_frames += self.window_frames;
+            self.check_silence(events);
+        } else {
+            self.silent_frames = 0;
+            self.muted_warned = false;
+        }
+
+        let clipping_due = self
+            .last_clipping_warning
+            .is_none_or(|last| self.position - last >= CLIPPING_WARNING_INTERVAL_FRAMES);
+        if self.clipped > 0 && clipping_due {
+            events.push(MeterEvent::Warning(TrackWarning::Clipping {
+                participant_id: self.participant_id.clone(),
+                timestamp_seconds,
+                clipped_samples: self.clipped,
+            }));
+            self.last_clipping_warning = Some(self.position);
+        }
+
+        self.window_frames = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+        self.clipped = 0;
+    }
+
+    fn check_silence(&mut self, events: &mut Vec<MeterEvent>) {
+        if !self.muted_warned && self.silent_frames >= self.silence_warning_frames {
+            events.push(MeterEvent::Warning(TrackWarning::PossiblyMuted {
+                participant_id: self.participant_id.clone(),
+                silent_seconds: self.silent_frames as f64 / OPUS_SAMPLE_RATE as f64,
+            }));
+            self.muted_warned = true;
+        }
+    }
+}
+
+/// Meters a participant's audio straight from the incoming WebM chunks
+///
+/// Metering is best effort: the first demux or decode error is logged and
+/// the meter goes quiet, the recording itself is never affected.
+pub struct LiveAudioMeter {
+    participant_id: String,
+    silence_warning_seconds: u32,
+    listener: MeterListener,
+    demuxer: WebmDemuxer,
+    track: Option<(u64, OpusBlockDecoder)>,
+    meter: Option<LevelMeter>,
+    next_frame: u64,
+    frames: VecDeque<PcmFrame>,
+    events: Vec<MeterEvent>,
+    failed: bool,
+}
+
+impl LiveAudioMeter {
+    pub fn new(
+        participant_id: String,
+        silence_warning_seconds: u32,
+        listener: MeterListener,
+    ) -> Self {
+        Self {
+            participant_id,
+            silence_warning_seconds,
+            listener,
+            demuxer: WebmDemuxer::new(),
+            track: None,
+            meter: None,
+            next_frame: 0,
+            frames: VecDeque::new(),
+            events: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Feed a chunk as received from the frontend
+    pub fn push_chunk(&mut self, chunk: &[u8]) {
+        if self.failed {
+            return;
+        }
+
+        self.demuxer.push(chunk);
+        if let Err(e) = self.drain() {
+            log::warn!(
+                "Level metering disabled for participant {}: {}",
+                self.participant_id,
+                e
+            );
+            self.failed = true;
+        }
+
+        for event in self.events.drain(..) {
+            (self.listener)(event);
+        }
+    }
+
+    fn drain(&mut self) -> RecordingResult<()> {
+        while let Some(event) = self.demuxer.next_event()? {
+            match event {
+                WebmEvent::Tracks(tracks) => {
+                    let track = OpusBlockDecoder::find_track(&tracks).ok_or_else(|| {
+                        RecordingError::DecodeError("no Opus audio track".to_string())
+                    })?;
+                    let decoder = OpusBlockDecoder::new(track)?;
+                    self.meter = Some(LevelMeter::new(
+                        self.participant_id.clone(),
+                        decoder.channels(),
+                        self.silence_warning_seconds,
+                    ));
+                    self.track = Some((track.number, decoder));
+                }
+                WebmEvent::Block(block) => {
+                    let (number, decoder) = match &mut self.track {
+                        Some((number, decoder)) => (*number, decoder),
+                        None => continue,
+                    };
+                    if block.track_number != number {
+                        continue;
+                    }
+                    decoder.decode_block(&block, &mut self.frames)?;
+                    self.meter_frames();
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn meter_frames(&mut self) {
+        let meter = match &mut self.meter {
+            Some(meter) => meter,
+            None => return,
+        };
+
+        while let Some(frame) = self.frames.pop_front() {
+            let start = (frame.timestamp * OPUS_SAMPLE_RATE as f64).round().max(0.0) as u64;
+            if start > self.next_frame + GAP_TOLERANCE_SAMPLES {
+                meter.process_gap(start - self.next_frame, &mut self.events);
+                self.next_frame = start;
+            }
+            self.next_frame += (frame.samples.len() / meter.channels) as u64;
+            meter.process(&frame.samples, &mut self.events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+    use parking_lot::Mutex;
+
+    fn levels(events: &[MeterEvent]) -> Vec<&AudioLevel> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                MeterEvent::Level(level) => Some(level),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_level_meter_reports_rms_and_peak() {
+        let mut meter = LevelMeter::new("p1".to_string(), 1, 10);
+        let samples: Vec<f32> = (0..48000)
+            .map(|n| 0.5 * (2.0 * std::f32::consts::PI * 1000.0 * n as f32 / 48000.0).sin())
+            .collect();
+        let mut events = Vec::new();
+        meter.process(&samples, &mut events);
+
+        assert_eq!(events.len(), 10);
+        let level = levels(&events)[9];
+        assert!((level.timestamp_seconds - 1.0).abs() < 1e-9);
+        // A sine's RMS sits 3dB under its peak
+        assert!(
+            (level.peak_db - -6.02).abs() < 0.05,
+            "peak: {}",
+            level.peak_db
+        );
+        assert!((level.rms_db - -9.03).abs() < 0.05, "rms: {}", level.rms_db);
+    }
+
+    #[test]
+    fn test_level_meter_warns_once_about_silence() {
+        let mut meter = LevelMeter::new("p1".to_string(), 2, 2);
+        let mut events = Vec::new();
+        // Two seconds of stereo silence, then a gap and more silence
+        meter.process(&vec![0.0; 48000 * 2 * 2], &mut events);
+        meter.process_gap(48000, &mut events);
+        meter.process(&vec![0.0; 48000 * 2], &mut events);
+
+        let warnings: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                MeterEvent::Warning(warning) => Some(warning),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![&TrackWarning::PossiblyMuted {
+                participant_id: "p1".to_string(),
+                silent_seconds: 2.0,
+            }]
+        );
+        assert!(levels(&events).iter().all(|l| l.rms_db == METER_FLOOR_DB));
+    }
+
+    #[test]
+    fn test_level_meter_warns_about_clipping() {
+        let mut meter = LevelMeter::new("p1".to_string(), 1, 10);
+        let mut samples = vec![0.1; 4800];
+        samples[100] = 1.0;
+        samples[200] = -1.0;
+        let mut events = Vec::new();
+        meter.process(&samples, &mut events);
+        // Same clipping right after is not reported again
+        meter.process(&samples, &mut events);
+
+        let clipping: Vec<_> = events
+            .iter()
+            .filter(|e| matches!(e, MeterEvent::Warning(TrackWarning::Clipping { .. })))
+            .collect();
+        assert_eq!(clipping.len(), 1);
+        assert!(matches!(
+            clipping[0],
+            MeterEvent::Warning(TrackWarning::Clipping {
+                clipped_samples: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_live_meter_on_fixture_chunks() {
+        let data = std::fs::read(fixture()).unwrap();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let listener: MeterListener = Arc::new(move |event| sink.lock().push(event));
+
+        // Odd chunk sizes split elements across chunk boundaries
+        let mut meter = LiveAudioMeter::new("p1".to_string(), 10, listener);
+        for chunk in data.chunks(777) {
+            meter.push_chunk(chunk);
+        }
+
+        let events = received.lock();
+        let levels = levels(&events);
+        assert_eq!(levels.len(), 19);
+        let level = levels[10];
+        assert!(
+            (level.peak_db - -6.0).abs() < 1.0,
+            "peak: {}",
+            level.peak_db
+        );
+        assert!(events.iter().all(|e| matches!(e, MeterEvent::Level(_))));
+    }
+}
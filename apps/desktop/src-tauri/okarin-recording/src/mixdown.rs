@@ -0,0 +1,211 @@
+use super::decoder::OPUS_SAMPLE_RATE;
+use super::dsp::Limiter;
+use super::encoder::{OpusEncoder, OpusPacket, EXPORT_OPUS_BITRATE, OPUS_FRAME_SAMPLES};
+use super::format::FormatConverter;
+use super::mixer::{TrackMixer, MIX_CHANNELS};
+use super::muxer::WebmMuxer;
+use super::types::*;
+use super::wav::WavFileWriter;
+use std::path::{Path, PathBuf};
+
+/// File name (without extension) of the mixdown in the recording directory
+pub const MIX_FILE_STEM: &str = "mix";
+/// Ceiling of the limiter that keeps summed voices from clipping
+const MIX_CEILING_DB: f64 = -1.0;
+const MIX_BLOCK_FRAMES: usize = 4800;
+
+/// Where the processed mix goes
+enum MixSink {
+    Wav {
+        writer: WavFileWriter,
+        converter: FormatConverter,
+        converted: Vec<f32>,
+    },
+    Webm {
+        encoder: OpusEncoder,
+        muxer: Box<WebmMuxer<std::io::BufWriter<std::fs::File>>>,
+        packets: Vec<OpusPacket>,
+    },
+}
+
+impl MixSink {
+    /// `sample_rate` applies to WAV only, Opus is always 48kHz
+    fn create(path: &Path, format: MixFormat, sample_rate: u32) -> RecordingResult<Self> {
+        match format {
+            MixFormat::Wav => Ok(MixSink::Wav {
+                writer: WavFileWriter::create(path, MIX_CHANNELS, sample_rate)?,
+                converter: FormatConverter::new(
+                    MIX_CHANNELS,
+                    OPUS_SAMPLE_RATE,
+                    MIX_CHANNELS,
+                    sample_rate,
+                ),
+                converted: Vec::new(),
+            }),
+            MixFormat::Webm => {
+                let encoder = OpusEncoder::new(MIX_CHANNELS, EXPORT_OPUS_BITRATE)?;
+                let muxer = WebmMuxer::create(path, "webm", &[encoder.track_info(1)])?;
+                Ok(MixSink::Webm {
+                    encoder,
+                    muxer: Box::new(muxer),
+                    packets: Vec::new(),
+                })
+            }
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) -> RecordingResult<()> {
+        match self {
+            MixSink::Wav {
+                writer,
+                converter,
+                converted,
+            } => {
+                converted.clear();
+                converter.process(samples, converted);
+                writer.write(converted)
+            }
+            MixSink::Webm {
+                encoder,
+                muxer,
+                packets,
+            } => {
+                encoder.encode(samples, packets)?;
+                write_packets(muxer, packets)
+            }
+        }
+    }
+
+    fn finish(self) -> RecordingResult<PathBuf> {
+        match self {
+            MixSink::Wav {
+                mut writer,
+                mut converter,
+                mut converted,
+            } => {
+                converted.clear();
+                converter.flush(&mut converted);
+                writer.write(&converted)?;
+                writer.finalize()
+            }
+            MixSink::Webm {
+                mut encoder,
+                mut muxer,
+                mut packets,
+            } => {
+                encoder.finish(&mut packets)?;
+                write_packets(&mut muxer, &mut packets)?;
+                muxer.finish()
+            }
+        }
+    }
+}
+
+fn write_packets(
+    muxer: &mut WebmMuxer<std::io::BufWriter<std::fs::File>>,
+    packets: &mut Vec<OpusPacket>,
+) -> RecordingResult<()> {
+    let frame_ns = OPUS_FRAME_SAMPLES as u64 * 1_000_000_000 / OPUS_SAMPLE_RATE as u64;
+    for packet in packets.drain(..) {
+        muxer.write_frame(1, packet.timestamp_ns(), frame_ns, true, &packet.data)?;
+    }
+    Ok(())
+}
+
+/// Path of the mixdown for a given format
+pub fn mix_file_path(recording_dir: &Path, format: MixFormat) -> PathBuf {
+    recording_dir.join(format!("{}.{}", MIX_FILE_STEM, format.extension()))
+}
+
+/// Sum every participant on the session timeline into a stereo reference mix
+pub fn mixdown_recording(
+    metadata: &RecordingMetadata,
+    options: &MixdownOptions,
+) -> RecordingResult<PathBuf> {
+    let mut mixer = TrackMixer::open_with_settings(metadata, &options.tracks)?;
+    if mixer.track_count() == 0 {
+        return Err(RecordingError::InvalidConfig(
+            "recording has no audio to mix".to_string(),
+        ));
+    }
+
+    let path = mix_file_path(&metadata.output_directory, options.format);
+    log::info!("Mixing {} tracks down to {:?}", mixer.track_count(), path);
+
+    let sample_rate = metadata
+        .configured_format
+        .as_ref()
+        .and_then(|format| format.audio_sample_rate)
+        .unwrap_or(OPUS_SAMPLE_RATE);
+    let mut sink = MixSink::create(&path, options.format, sample_rate)?;
+    let mut limiter = Limiter::new(MIX_CHANNELS, OPUS_SAMPLE_RATE, MIX_CEILING_DB);
+    let mut limited = Vec::with_capacity(MIX_BLOCK_FRAMES * MIX_CHANNELS as usize);
+
+    while let Some(block) = mixer.read(MIX_BLOCK_FRAMES)? {
+        limited.clear();
+        limiter.process(&block, &mut limited);
+        sink.write(&limited)?;
+    }
+    limited.clear();
+    limiter.flush(&mut limited);
+    sink.write(&limited)?;
+
+    sink.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decoder::decode_file;
+    use super::*;
+    use crate::test_support::{fixture_metadata, temp_dir};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_mixdown_pans_track() {
+        let dir = temp_dir("mixdown-wav");
+        let options = MixdownOptions {
+            format: MixFormat::Wav,
+            tracks: HashMap::from([(
+                "p1".to_string(),
+                TrackMixSettings {
+                    gain_db: -6.0,
+                    pan: -1.0,
+                },
+            )]),
+        };
+
+        let path = mixdown_recording(&fixture_metadata(dir.clone()), &options).unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        let samples: Vec<i32> = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let peak = |side: usize| {
+            samples
+                .iter()
+                .skip(side)
+                .step_by(2)
+                .fold(0f64, |m, &s| m.max((s as f64 / 8_388_607.0).abs()))
+        };
+        assert!((0.2..0.3).contains(&peak(0)), "left: {}", peak(0));
+        assert_eq!(peak(1), 0.0);
+    }
+
+    #[test]
+    fn test_mixdown_to_webm_decodes_back() {
+        let dir = temp_dir("mixdown-webm");
+        let options = MixdownOptions {
+            format: MixFormat::Webm,
+            tracks: HashMap::new(),
+        };
+
+        let path = mixdown_recording(&fixture_metadata(dir.clone()), &options).unwrap();
+        let pcm = decode_file(&path).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(pcm.channels, 2);
+        assert!((pcm.duration_seconds() - 2.0).abs() < 0.05);
+        let peak = pcm.samples[4800..].iter().fold(0f32, |m, s| m.max(s.abs()));
+        assert!((0.4..0.6).contains(&peak), "peak: {}", peak);
+    }
+}
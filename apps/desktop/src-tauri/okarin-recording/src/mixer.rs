@@ -0,0 +1,164 @@
+use super::decoder::{OpusTrackDecoder, TimelineReader};
+use super::types::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Number of output channels of the mix
+pub const MIX_CHANNELS: u16 = 2;
+
+struct MixerInput {
+    reader: TimelineReader<BufReader<File>>,
+    gains: (f32, f32),
+    finished: bool,
+}
+
+/// Sums every participant's decoded audio into a stereo stream, each track
+/// placed on the session timeline at its join offset
+pub struct TrackMixer {
+    inputs: Vec<MixerInput>,
+}
+
+impl TrackMixer {
+    /// Open every participant audio file listed in the recording metadata
+    pub fn open(metadata: &RecordingMetadata) -> RecordingResult<Self> {
+        Self::open_with_settings(metadata, &HashMap::new())
+    }
+
+    /// Like `open`, with per-participant gain and pan (unity/center when absent)
+    pub fn open_with_settings(
+        metadata: &RecordingMetadata,
+        settings: &HashMap<String, TrackMixSettings>,
+    ) -> RecordingResult<Self> {
+        let mut participants: Vec<&ParticipantMetadata> = metadata
+            .participants
+            .values()
+            .filter(|p| p.audio_file.is_some())
+            .collect();
+        participants.sort_by_key(|p| p.joined_at);
+
+        let mut inputs = Vec::with_capacity(participants.len());
+        for participant in participants {
+            if let Some(path) = &participant.audio_file {
+                let decoder = OpusTrackDecoder::open(path)?;
+                let offset = metadata.participant_offset_seconds(participant);
+                let gains = settings
+                    .get(&participant.id)
+                    .map_or((1.0, 1.0), TrackMixSettings::stereo_gains);
+                inputs.push(MixerInput {
+                    reader: TimelineReader::new(decoder, offset),
+                    gains,
+                    finished: false,
+                });
+            }
+        }
+
+        Ok(Self { inputs })
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Read up to `frames` frames of interleaved stereo mix, None once every track is exhausted
+    pub fn read(&mut self, frames: usize) -> RecordingResult<Option<Vec<f32>>> {
+        let mut mix = vec![0.0f32; frames * MIX_CHANNELS as usize];
+        let mut longest = 0;
+
+        for input in self.inputs.iter_mut().filter(|i| !i.finished) {
+            let channels = input.reader.channels();
+            match input.reader.read(frames)? {
+                Some(block) => {
+                    let block_frames =
+                        add_to_stereo_with_gains(&mut mix, &block, channels, input.gains);
+                    longest = longest.max(block_frames);
+                }
+                None => input.finished = true,
+            }
+        }
+
+        if longest == 0 {
+            return Ok(None);
+        }
+
+        mix.truncate(longest * MIX_CHANNELS as usize);
+        Ok(Some(mix))
+    }
+}
+
+/// Add an interleaved block to a stereo buffer, duplicating mono input on
+/// both sides; returns the number of frames added
+pub fn add_to_stereo(mix: &mut [f32], block: &[f32], channels: u16) -> usize {
+    add_to_stereo_with_gains(mix, block, channels, (1.0, 1.0))
+}
+
+/// `add_to_stereo` with a gain applied to each side
+pub fn add_to_stereo_with_gains(
+    mix: &mut [f32],
+    block: &[f32],
+    channels: u16,
+    (left_gain, right_gain): (f32, f32),
+) -> usize {
+    let channels = channels.max(1) as usize;
+    let frames = block.len() / channels;
+
+    for (out, frame) in mix.chunks_exact_mut(2).zip(block.chunks_exact(channels)) {
+        let (left, right) = if channels == 1 {
+            (frame[0], frame[0])
+        } else {
+            (frame[0], frame[1])
+        };
+        out[0] += left * left_gain;
+        out[1] += right * right_gain;
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture_metadata;
+    use chrono::Duration;
+
+    #[test]
+    fn test_add_to_stereo() {
+        let mut mix = vec![0.0; 6];
+        assert_eq!(add_to_stereo(&mut mix, &[0.5, -0.5], 1), 2);
+        assert_eq!(add_to_stereo(&mut mix, &[0.25, 0.125, 0.25, 0.5], 2), 2);
+        assert_eq!(mix, vec![0.75, 0.625, -0.25, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_marker_after_pause_lines_up_with_the_mix() {
+        // Paused from 0.2s to 0.6s, guest joins at 1s and the host drops a
+        // marker as they do
+        let mut metadata = fixture_metadata(std::env::temp_dir());
+        let started_at = metadata.started_at;
+        let at = |millis| started_at + Duration::milliseconds(millis);
+        metadata.pauses = vec![Pause {
+            paused_at: at(200),
+            resumed_at: Some(at(600)),
+            position_seconds: metadata.session_position_seconds(at(200)),
+        }];
+        for participant in metadata.participants.values_mut() {
+            participant.joined_at = at(1000);
+        }
+        let marker_seconds = metadata.session_position_seconds(at(1000));
+
+        let mut mixer = TrackMixer::open(&metadata).unwrap();
+        let mut mix = Vec::new();
+        while let Some(block) = mixer.read(4800).unwrap() {
+            mix.extend(block);
+        }
+        let first_sound = mix.iter().position(|s| s.abs() > 0.01).unwrap() / 2;
+        let first_sound_seconds = first_sound as f64 / 48000.0;
+
+        assert!(
+            (first_sound_seconds - marker_seconds).abs() < 0.02,
+            "marker at {}s, guest heard from {}s",
+            marker_seconds,
+            first_sound_seconds
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use super::chapters::Chapter;
+use super::types::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Timescale of the movie header, milliseconds
+const MOVIE_TIMESCALE: u32 = 1000;
+/// Nero chapter times are in 100ns units
+const CHPL_TIMESCALE: f64 = 10_000_000.0;
+/// MPEG-4 Audio object type indication in the DecoderConfigDescriptor
+const OBJECT_TYPE_AAC: u8 = 0x40;
+/// `ilst` data types
+const DATA_UTF8: u32 = 1;
+const DATA_BINARY: u32 = 0;
+const DATA_JPEG: u32 = 13;
+const DATA_PNG: u32 = 14;
+/// Identity transformation of the movie and track headers
+const UNITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+/// AAC track as written to the `moov`
+#[derive(Debug, Clone)]
+pub struct Mp4AudioTrack {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// AudioSpecificConfig given by the encoder
+    pub decoder_config: Vec<u8>,
+    /// Samples per channel of every access unit, 1024 for AAC-LC
+    pub frame_samples: u32,
+    /// Encoder priming, hidden by the edit list
+    pub delay_samples: u32,
+    /// Samples per channel of actual audio, padding excluded
+    pub duration_samples: u64,
+    pub bitrate: u32,
+    /// Size of every access unit, in file order
+    pub sample_sizes: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverFormat {
+    Jpeg,
+    Png,
+}
+
+/// iTunes-style tags and Nero chapters
+#[derive(Debug, Clone, Default)]
+pub struct Mp4Tags {
+    pub title: String,
+    pub album: String,
+    pub track_number: Option<u32>,
+    pub cover: Option<(CoverFormat, Vec<u8>)>,
+    pub chapters: Vec<Chapter>,
+}
+
+pub fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + body.len());
+    bytes.extend_from_slice(&(8 + body.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+/// Box starting with a version byte and 24 bits of flags
+pub fn full_box(kind: &[u8; 4], version: u8, flags: u32, body: &[u8]) -> Vec<u8> {
+    let mut content = ((version as u32) << 24 | (flags & 0x00FF_FFFF))
+        .to_be_bytes()
+        .to_vec();
+    content.extend_from_slice(body);
+    mp4_box(kind, &content)
+}
+
+/// MPEG-4 descriptor (ES, DecoderConfig, ...), always small enough for a one-byte length
+fn descriptor(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![tag, body.len() as u8];
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+fn u32s(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+fn mvhd(duration_ms: u32) -> Vec<u8> {
+    let mut body = u32s(&[0, 0, MOVIE_TIMESCALE, duration_ms, 0x0001_0000]);
+    // Volume, then 10 reserved bytes
+    body.extend_from_slice(&0x0100u16.to_be_bytes());
+    body.extend_from_slice(&[0; 10]);
+    body.extend(u32s(&UNITY_MATRIX));
+    body.extend_from_slice(&[0; 24]);
+    // Next track ID
+    body.extend(u32s(&[2]));
+    full_box(b"mvhd", 0, 0, &body)
+}
+
+fn tkhd(duration_ms: u32) -> Vec<u8> {
+    let mut body = u32s(&[0, 0, 1, 0, duration_ms, 0, 0]);
+    // Layer, alternate group, volume, reserved
+    body.extend([0u16, 0, 0x0100, 0].iter().flat_map(|v| v.to_be_bytes()));
+    body.extend(u32s(&UNITY_MATRIX));
+    // Width and height, none for audio
+    body.extend(u32s(&[0, 0]));
+    // Enabled, in movie
+    full_box(b"tkhd", 0, 0x000003, &body)
+}
+
+fn edts(track: &Mp4AudioTrack, duration_ms: u32) -> Vec<u8> {
+    let body = u32s(&[1, duration_ms, track.delay_samples, 0x0001_0000]);
+    mp4_box(b"edts", &full_box(b"elst", 0, 0, &body))
+}
+
+fn mdia(track: &Mp4AudioTrack, chunk_offset: u32) -> Vec<u8> {
+    let media_duration = track.sample_sizes.len() as u32 * track.frame_samples;
+    let mut mdhd = u32s(&[0, 0, track.sample_rate, media_duration]);
+    // Language "und", pre-defined
+    mdhd.extend_from_slice(&[0x55, 0xC4, 0, 0]);
+
+    let mut hdlr = u32s(&[0]);
+    hdlr.extend_from_slice(b"soun");
+    hdlr.extend(u32s(&[0, 0, 0]));
+    hdlr.extend_from_slice(b"SoundHandler\0");
+
+    let url = full_box(b"url ", 0, 1, &[]);
+    let mut dref = u32s(&[1]);
+    dref.extend(url);
+    let dinf = mp4_box(b"dinf", &full_box(b"dref", 0, 0, &dref));
+
+    let minf = [
+        full_box(b"smhd", 0, 0, &[0; 4]),
+        dinf,
+        stbl(track, chunk_offset),
+    ]
+    .concat();
+
+    [
+        full_box(b"mdhd", 0, 0, &mdhd),
+        full_box(b"hdlr", 0, 0, &hdlr),
+        mp4_box(b"minf", &minf),
+    ]
+    .concat()
+}
+
+fn esds(track: &Mp4AudioTrack) -> Vec<u8> {
+    let max_size = track.sample_sizes.iter().copied().max().unwrap_or(0);
+    let mut config = vec![OBJECT_TYPE_AAC, 0x15];
+    config.extend_from_slice(&max_size.to_be_bytes()[1..]);
+    config.extend(u32s(&[track.bitrate, track.bitrate]));
+    config.extend(descriptor(0x05, &track.decoder_config));
+
+    // ES ID 0, no dependencies
+    let mut es = vec![0, 0, 0];
+    es.extend(descriptor(0x04, &config));
+    es.extend(descriptor(0x06, &[0x02]));
+    full_box(b"esds", 0, 0, &descriptor(0x03, &es))
+}
+
+fn stbl(track: &Mp4AudioTrack, chunk_offset: u32) -> Vec<u8> {
+    let mut mp4a = vec![0; 6];
+    // Data reference index, then the version, revision and vendor of QuickTime
+    mp4a.extend_from_slice(&1u16.to_be_bytes());
+    mp4a.extend_from_slice(&[0; 8]);
+    mp4a.extend_from_slice(&track.channels.to_be_bytes());
+    mp4a.extend_from_slice(&16u16.to_be_bytes());
+    mp4a.extend_from_slice(&[0; 4]);
+    mp4a.extend(u32s(&[track.sample_rate << 16]));
+    mp4a.extend(esds(track));
+    let mut stsd = u32s(&[1]);
+    stsd.extend(mp4_box(b"mp4a", &mp4a));
+
+    let count = track.sample_sizes.len() as u32;
+    let mut stsz = u32s(&[0, count]);
+    stsz.extend(u32s(&track.sample_sizes));
+
+    // Every access unit sits in one chunk right after the `moov`
+    let body = [
+        full_box(b"stsd", 0, 0, &stsd),
+        full_box(b"stts", 0, 0, &u32s(&[1, count, track.frame_samples])),
+        full_box(b"stsc", 0, 0, &u32s(&[1, 1, count, 1])),
+        full_box(b"stsz", 0, 0, &stsz),
+        full_box(b"stco", 0, 0, &u32s(&[1, chunk_offset])),
+    ]
+    .concat();
+    mp4_box(b"stbl", &body)
+}
+
+/// Nero `chpl` box, read by most podcast players
+fn chpl(chapters: &[Chapter]) -> Vec<u8> {
+    let chapters = &chapters[..chapters.len().min(u8::MAX as usize)];
+    let mut body = u32s(&[0]);
+    body.push(chapters.len() as u8);
+    for chapter in chapters {
+        let start = (chapter.start_seconds.max(0.0) * CHPL_TIMESCALE).round() as u64;
+        body.extend_from_slice(&start.to_be_bytes());
+        let title = truncate_utf8(&chapter.title, u8::MAX as usize);
+        body.push(title.len() as u8);
+        body.extend_from_slice(title.as_bytes());
+    }
+    full_box(b"chpl", 1, 0, &body)
+}
+
+fn truncate_utf8(text: &str, max_bytes: usize) -> &str {
+    let mut end = text.len().min(max_bytes);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn ilst_item(kind: &[u8; 4], data_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut data = u32s(&[data_type, 0]);
+    data.extend_from_slice(payload);
+    mp4_box(kind, &mp4_box(b"data", &data))
+}
+
+fn meta(tags: &Mp4Tags) -> Vec<u8> {
+    let mut ilst = ilst_item(b"\xA9nam", DATA_UTF8, tags.title.as_bytes());
+    ilst.extend(ilst_item(b"\xA9alb", DATA_UTF8, tags.album.as_bytes()));
+    if let Some(number) = tags.track_number {
+        let mut trkn = vec![0, 0];
+        trkn.extend_from_slice(&(number.min(u16::MAX as u32) as u16).to_be_bytes());
+        trkn.extend_from_slice(&[0; 4]);
+        ilst.extend(ilst_item(b"trkn", DATA_BINARY, &trkn));
+    }
+    if let Some((format, image)) = &tags.cover {
+        let data_type = match format {
+            CoverFormat::Jpeg => DATA_JPEG,
+            CoverFormat::Png => DATA_PNG,
+        };
+        ilst.extend(ilst_item(b"covr", data_type, image));
+    }
+
+    let mut hdlr = u32s(&[0]);
+    hdlr.extend_from_slice(b"mdirappl");
+    hdlr.extend(u32s(&[0, 0]));
+    hdlr.push(0);
+
+    let mut body = full_box(b"hdlr", 0, 0, &hdlr);
+    body.extend(mp4_box(b"ilst", &ilst));
+    full_box(b"meta", 0, 0, &body)
+}
+
+fn moov(track: &Mp4AudioTrack, tags: &Mp4Tags, chunk_offset: u32) -> Vec<u8> {
+    let duration_ms =
+        (track.duration_samples * MOVIE_TIMESCALE as u64 / track.sample_rate.max(1) as u64) as u32;
+    let trak = [
+        tkhd(duration_ms),
+        edts(track, duration_ms),
+        mp4_box(b"mdia", &mdia(track, chunk_offset)),
+    ]
+    .concat();
+
+    let mut udta = meta(tags);
+    if !tags.chapters.is_empty() {
+        udta.extend(chpl(&tags.chapters));
+    }
+
+    let body = [
+        mvhd(duration_ms),
+        mp4_box(b"trak", &trak),
+        mp4_box(b"udta", &udta),
+    ]
+    .concat();
+    mp4_box(b"moov", &body)
+}
+
+/// Write an M4A with the `moov` first, so players can start before the download ends
+///
+/// `access_units` holds the raw AAC frames back to back, as sized in `track`.
+pub fn write_m4a<R: Read>(
+    output: &Path,
+    track: &Mp4AudioTrack,
+    tags: &Mp4Tags,
+    access_units: &mut R,
+) -> RecordingResult<PathBuf> {
+    let mut ftyp = b"M4A ".to_vec();
+    ftyp.extend(u32s(&[0]));
+    ftyp.extend_from_slice(b"M4A mp42isom");
+    let ftyp = mp4_box(b"ftyp", &ftyp);
+
+    let data_size: u64 = track.sample_sizes.iter().map(|&s| s as u64).sum();
+    // The moov size does not depend on the offset value, measure it first
+    let moov_size = moov(track, tags, 0).len() as u64;
+    let chunk_offset = ftyp.len() as u64 + moov_size + 8;
+    if chunk_offset + data_size > u32::MAX as u64 {
+        return Err(RecordingError::InvalidConfig(
+            "episode too large for an M4A file".to_string(),
+        ));
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    writer.write_all(&ftyp)?;
+    writer.write_all(&moov(track, tags, chunk_offset as u32))?;
+    writer.write_all(&(8 + data_size as u32).to_be_bytes())?;
+    writer.write_all(b"mdat")?;
+    let copied = io::copy(access_units, &mut writer)?;
+    if copied != data_size {
+        return Err(RecordingError::TrackError(format!(
+            "expected {} bytes of AAC, got {}",
+            data_size, copied
+        )));
+    }
+    writer.flush()?;
+
+    Ok(output.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_write_m4a_offsets_and_tags() {
+        let dir = temp_dir("mp4");
+        let output = dir.join("episode.m4a");
+        let track = Mp4AudioTrack {
+            sample_rate: 48000,
+            channels: 2,
+            decoder_config: vec![0x11, 0x90],
+            frame_samples: 1024,
+            delay_samples: 2048,
+            duration_samples: 48000,
+            bitrate: 128_000,
+            sample_sizes: vec![3, 5, 2],
+        };
+        let tags = Mp4Tags {
+            title: "Pilot".to_string(),
+            album: "Okarin Radio".to_string(),
+            track_number: Some(7),
+            cover: None,
+            chapters: vec![Chapter {
+                title: "Intro".to_string(),
+                start_seconds: 1.5,
+                end_seconds: 2.0,
+            }],
+        };
+        let units = [1u8, 1, 1, 2, 2, 2, 2, 2, 3, 3];
+
+        write_m4a(&output, &track, &tags, &mut &units[..]).unwrap();
+        let data = std::fs::read(&output).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let find = |needle: &[u8]| {
+            data.windows(needle.len())
+                .position(|w| w == needle)
+                .unwrap()
+        };
+        assert_eq!(&data[4..12], b"ftypM4A ");
+        assert_eq!(
+            &data[data.len() - 14..],
+            &[b"mdat".as_slice(), &units].concat()[..]
+        );
+
+        let stco = find(b"stco");
+        let offset = u32::from_be_bytes(data[stco + 12..stco + 16].try_into().unwrap());
+        assert_eq!(offset as usize, data.len() - units.len());
+
+        let stsz = find(b"stsz");
+        assert_eq!(&data[stsz + 8..stsz + 28], &u32s(&[0, 3, 3, 5, 2])[..]);
+        let elst = find(b"elst");
+        assert_eq!(&data[elst + 8..elst + 20], &u32s(&[1, 1000, 2048])[..]);
+
+        let chpl = find(b"chpl");
+        assert_eq!(data[chpl + 12], 1);
+        assert_eq!(&data[chpl + 13..chpl + 21], &15_000_000u64.to_be_bytes());
+        assert_eq!(&data[chpl + 21..chpl + 27], b"\x05Intro");
+        assert!(find(b"trkn") < find(b"chpl"));
+        find(b"data\0\0\0\x01\0\0\0\0Okarin Radio");
+    }
+}
@@ -0,0 +1,320 @@
+use super::types::*;
+use super::webm::*;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Name written in MuxingApp / WritingApp
+const MUXING_APP: &str = "okarin";
+/// Start a new cluster on the first keyframe after this long
+const CLUSTER_TARGET_MS: i64 = 1000;
+/// Block timestamps are signed 16-bit offsets from the cluster timestamp
+const CLUSTER_MAX_MS: i64 = i16::MAX as i64;
+/// Segment size placeholder: 8-byte vint, patched in `finish`
+const SEGMENT_SIZE_LEN: usize = 8;
+
+/// Encode an EBML element ID, which already carries its length marker
+fn id_bytes(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(3);
+    bytes[skip..].to_vec()
+}
+
+/// Encode a data size as the shortest EBML vint
+pub fn size_vint(size: u64) -> Vec<u8> {
+    let length = (1..=8usize)
+        .find(|&len| size < (1u64 << (7 * len)) - 1)
+        .unwrap_or(8);
+    let marked = size | (1u64 << (7 * length));
+    marked.to_be_bytes()[8 - length..].to_vec()
+}
+
+/// Encode a data size on exactly `length` bytes
+fn size_vint_fixed(size: u64, length: usize) -> Vec<u8> {
+    let marked = size | (1u64 << (7 * length));
+    marked.to_be_bytes()[8 - length..].to_vec()
+}
+
+pub fn element(id: u32, body: &[u8]) -> Vec<u8> {
+    let mut bytes = id_bytes(id);
+    bytes.extend(size_vint(body.len() as u64));
+    bytes.extend_from_slice(body);
+    bytes
+}
+
+pub fn uint_element(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    element(id, &bytes[skip..])
+}
+
+pub fn float_element(id: u32, value: f64) -> Vec<u8> {
+    element(id, &value.to_be_bytes())
+}
+
+pub fn string_element(id: u32, value: &str) -> Vec<u8> {
+    element(id, value.as_bytes())
+}
+
+fn track_type(kind: &TrackKind) -> u64 {
+    match kind {
+        TrackKind::Video => 1,
+        TrackKind::Audio => 2,
+        TrackKind::Other(value) => *value,
+    }
+}
+
+/// Serialize a TrackEntry
+pub fn track_entry(track: &TrackInfo) -> Vec<u8> {
+    let mut body = uint_element(TRACK_NUMBER_ID, track.number);
+    body.extend(uint_element(
+        TRACK_UID_ID,
+        track.uid.unwrap_or(track.number),
+    ));
+    body.extend(uint_element(TRACK_TYPE_ID, track_type(&track.kind)));
+    body.extend(string_element(CODEC_ID_ID, &track.codec_id));
+    if let Some(private) = &track.codec_private {
+        body.extend(element(CODEC_PRIVATE_ID, private));
+    }
+    if track.codec_delay_ns > 0 {
+        body.extend(uint_element(CODEC_DELAY_ID, track.codec_delay_ns));
+    }
+    if track.seek_pre_roll_ns > 0 {
+        body.extend(uint_element(SEEK_PRE_ROLL_ID, track.seek_pre_roll_ns));
+    }
+    if let Some(duration) = track.default_duration_ns {
+        body.extend(uint_element(DEFAULT_DURATION_ID, duration));
+    }
+    if let Some(audio) = &track.audio {
+        let mut audio_body = float_element(SAMPLING_FREQUENCY_ID, audio.sampling_frequency);
+        audio_body.extend(uint_element(CHANNELS_ID, audio.channels));
+        if let Some(bit_depth) = audio.bit_depth {
+            audio_body.extend(uint_element(BIT_DEPTH_ID, bit_depth));
+        }
+        body.extend(element(AUDIO_ID, &audio_body));
+    }
+    if let Some(video) = &track.video {
+        let mut video_body = uint_element(PIXEL_WIDTH_ID, video.pixel_width);
+        video_body.extend(uint_element(PIXEL_HEIGHT_ID, video.pixel_height));
+        body.extend(element(VIDEO_ID, &video_body));
+    }
+    element(TRACK_ENTRY_ID, &body)
+}
+
+/// Writes a Matroska/WebM file with known element sizes
+///
+/// Clusters are buffered in memory and written whole; the Segment size and
+/// the Duration are patched when the file is finished, so the output is
+/// seekable and never needs repair.
+pub struct WebmMuxer<W: Write + Seek> {
+    output: W,
+    path: PathBuf,
+    segment_size_offset: u64,
+    segment_data_offset: u64,
+    duration_offset: u64,
+    cluster: Vec<u8>,
+    cluster_timestamp_ms: Option<i64>,
+    end_ns: i64,
+}
+
+impl WebmMuxer<BufWriter<File>> {
+    pub fn create(path: &Path, doc_type: &str, tracks: &[TrackInfo]) -> RecordingResult<Self> {
+        let output = BufWriter::new(File::create(path)?);
+        Self::new(output, path, doc_type, tracks)
+    }
+}
+
+impl<W: Write + Seek> WebmMuxer<W> {
+    pub fn new(
+        mut output: W,
+        path: &Path,
+        doc_type: &str,
+        tracks: &[TrackInfo],
+    ) -> RecordingResult<Self> {
+        let mut header = uint_element(0x4286, 1); // EBMLVersion
+        header.extend(uint_element(0x42F7, 1)); // EBMLReadVersion
+        header.extend(uint_element(0x42F2, 4)); // EBMLMaxIDLength
+        header.extend(uint_element(0x42F3, 8)); // EBMLMaxSizeLength
+        header.extend(string_element(DOC_TYPE_ID, doc_type));
+        header.extend(uint_element(0x4287, 4)); // DocTypeVersion
+        header.extend(uint_element(0x4285, 2)); // DocTypeReadVersion
+        output.write_all(&element(EBML_HEADER_ID, &header))?;
+
+        output.write_all(&id_bytes(SEGMENT_ID))?;
+        let segment_size_offset = output.stream_position()?;
+        output.write_all(&[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF])?;
+        let segment_data_offset = output.stream_position()?;
+
+        let mut info = uint_element(TIMECODE_SCALE_ID, DEFAULT_TIMECODE_SCALE);
+        info.extend(string_element(MUXING_APP_ID, MUXING_APP));
+        info.extend(string_element(WRITING_APP_ID, MUXING_APP));
+        // Position of the Duration value: Info header, then the element header
+        let duration_element = float_element(DURATION_ID, 0.0);
+        let duration_position = info.len() + duration_element.len() - 8;
+        info.extend(duration_element);
+        let info_header_len = id_bytes(INFO_ID).len() + size_vint(info.len() as u64).len();
+        let duration_offset = segment_data_offset + (info_header_len + duration_position) as u64;
+        output.write_all(&element(INFO_ID, &info))?;
+
+        let entries: Vec<u8> = tracks.iter().flat_map(track_entry).collect();
+        output.write_all(&element(TRACKS_ID, &entries))?;
+
+        Ok(Self {
+            output,
+            path: path.to_path_buf(),
+            segment_size_offset,
+            segment_data_offset,
+            duration_offset,
+            cluster: Vec::new(),
+            cluster_timestamp_ms: None,
+            end_ns: 0,
+        })
+    }
+
+    /// Append one frame as a SimpleBlock
+    pub fn write_frame(
+        &mut self,
+        track_number: u64,
+        timestamp_ns: i64,
+        duration_ns: u64,
+        keyframe: bool,
+        data: &[u8],
+    ) -> RecordingResult<()> {
+        let timestamp_ms = timestamp_ns.div_euclid(DEFAULT_TIMECODE_SCALE as i64);
+        let new_cluster = match self.cluster_timestamp_ms {
+            None => true,
+            Some(start) => {
+                let age = timestamp_ms - start;
+                !(0..=CLUSTER_MAX_MS).contains(&age) || (keyframe && age >= CLUSTER_TARGET_MS)
+            }
+        };
+        if new_cluster {
+            self.flush_cluster()?;
+            self.cluster.extend(uint_element(
+                CLUSTER_TIMESTAMP_ID,
+                timestamp_ms.max(0) as u64,
+            ));
+            self.cluster_timestamp_ms = Some(timestamp_ms.max(0));
+        }
+
+        let relative = (timestamp_ms - self.cluster_timestamp_ms.unwrap_or(0)) as i16;
+        let mut block = size_vint(track_number);
+        block.extend_from_slice(&relative.to_be_bytes());
+        block.push(if keyframe { 0x80 } else { 0x00 });
+        block.extend_from_slice(data);
+        self.cluster.extend(element(SIMPLE_BLOCK_ID, &block));
+
+        self.end_ns = self.end_ns.max(timestamp_ns + duration_ns as i64);
+        Ok(())
+    }
+
+    fn flush_cluster(&mut self) -> RecordingResult<()> {
+        if !self.cluster.is_empty() {
+            self.output.write_all(&element(CLUSTER_ID, &self.cluster))?;
+            self.cluster.clear();
+        }
+        Ok(())
+    }
+
+    /// Write the last cluster and patch the sizes, returning the file path
+    pub fn finish(mut self) -> RecordingResult<PathBuf> {
+        self.finalize()?;
+        Ok(self.path)
+    }
+
+    /// Like `finish`, handing back the finished output
+    pub fn into_inner(mut self) -> RecordingResult<W> {
+        self.finalize()?;
+        Ok(self.output)
+    }
+
+    fn finalize(&mut self) -> RecordingResult<()> {
+        self.flush_cluster()?;
+        let end = self.output.stream_position()?;
+
+        let segment_size = end - self.segment_data_offset;
+        self.output
+            .seek(SeekFrom::Start(self.segment_size_offset))?;
+        self.output
+            .write_all(&size_vint_fixed(segment_size, SEGMENT_SIZE_LEN))?;
+
+        let duration_ms = self.end_ns as f64 / DEFAULT_TIMECODE_SCALE as f64;
+        self.output.seek(SeekFrom::Start(self.duration_offset))?;
+        self.output.write_all(&duration_ms.to_be_bytes())?;
+
+        self.output.seek(SeekFrom::Start(end))?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_size_vint() {
+        assert_eq!(size_vint(0), vec![0x80]);
+        assert_eq!(size_vint(126), vec![0xFE]);
+        assert_eq!(size_vint(127), vec![0x40, 0x7F]);
+        assert_eq!(size_vint_fixed(5, 8), vec![0x01, 0, 0, 0, 0, 0, 0, 5]);
+    }
+
+    #[test]
+    fn test_muxed_file_reads_back() {
+        let track = TrackInfo {
+            number: 1,
+            uid: Some(42),
+            kind: TrackKind::Audio,
+            codec_id: "A_OPUS".to_string(),
+            codec_private: Some(vec![1, 2, 3]),
+            codec_delay_ns: 0,
+            seek_pre_roll_ns: 0,
+            default_duration_ns: None,
+            audio: Some(AudioTrackInfo {
+                sampling_frequency: 48000.0,
+                channels: 2,
+                bit_depth: None,
+            }),
+            video: None,
+        };
+
+        let mut muxer = WebmMuxer::new(
+            Cursor::new(Vec::new()),
+            Path::new("test.webm"),
+            "webm",
+            std::slice::from_ref(&track),
+        )
+        .unwrap();
+        for index in 0..150i64 {
+            let timestamp_ns = index * 20_000_000;
+            muxer
+                .write_frame(1, timestamp_ns, 20_000_000, true, &[index as u8])
+                .unwrap();
+        }
+        let bytes = muxer.into_inner().unwrap().into_inner();
+
+        let mut reader = WebmReader::new(Cursor::new(bytes));
+        let mut info = None;
+        let mut tracks = Vec::new();
+        let mut blocks = Vec::new();
+        while let Some(event) = reader.next_event().unwrap() {
+            match event {
+                WebmEvent::Info(i) => info = Some(i),
+                WebmEvent::Tracks(t) => tracks = t,
+                WebmEvent::Block(block) => blocks.push(block),
+                WebmEvent::SegmentStart { size, .. } => assert!(size.is_some()),
+                _ => {}
+            }
+        }
+
+        let info = info.unwrap();
+        assert_eq!(info.muxing_app.as_deref(), Some(MUXING_APP));
+        assert_eq!(info.duration_seconds(), Some(3.0));
+        assert_eq!(tracks, vec![track]);
+        assert_eq!(blocks.len(), 150);
+        assert_eq!(blocks[149].timestamp_ns, 149 * 20_000_000);
+        assert_eq!(blocks[149].frames, vec![vec![149u8]]);
+    }
+}
@@ -0,0 +1,138 @@
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::dsp::{db_to_linear, Limiter};
+use super::export::FormatWavWriter;
+use super::loudness;
+use super::storage::derived_file_path;
+use super::types::*;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::path::Path;
+
+const NORMALIZE_BLOCK_FRAMES: usize = 4800;
+
+/// Write a loudness-normalized WAV copy of one recorded audio file
+///
+/// `measured_lufs` skips the measuring pass when the integrated loudness is
+/// already known (e.g. from the post-stop loudness report). The copy is
+/// written in the `target` format.
+pub fn normalize_file(
+    participant_id: &str,
+    source: &Path,
+    output: &Path,
+    measured_lufs: Option<f64>,
+    options: &NormalizationOptions,
+    target: &MediaFormat,
+) -> RecordingResult<NormalizedTrack> {
+    let input_lufs = match measured_lufs {
+        Some(lufs) => Some(lufs),
+        None => loudness::analyze_file(source)?.integrated_lufs,
+    };
+
+    // Silent tracks cannot be measured, they are copied at unity gain
+    let gain_db = input_lufs.map_or(0.0, |lufs| options.target_lufs - lufs);
+    let gain = db_to_linear(gain_db) as f32;
+
+    let decoder = OpusTrackDecoder::open(source)?;
+    let channels = decoder.channels();
+    let mut reader = TimelineReader::new(decoder, 0.0);
+    let mut limiter = Limiter::new(channels, OPUS_SAMPLE_RATE, options.true_peak_ceiling_dbtp);
+    let mut writer = FormatWavWriter::create(output, channels, target)?;
+    let mut limited = Vec::with_capacity(NORMALIZE_BLOCK_FRAMES * channels as usize);
+
+    while let Some(mut block) = reader.read(NORMALIZE_BLOCK_FRAMES)? {
+        block.iter_mut().for_each(|s| *s *= gain);
+        limited.clear();
+        limiter.process(&block, &mut limited);
+        writer.write(&limited)?;
+    }
+    limited.clear();
+    limiter.flush(&mut limited);
+    writer.write(&limited)?;
+
+    Ok(NormalizedTrack {
+        participant_id: participant_id.to_string(),
+        output_file: writer.finalize()?,
+        input_integrated_lufs: input_lufs,
+        gain_db,
+        limiter_max_reduction_db: limiter.max_reduction_db(),
+    })
+}
+
+/// Export loudness-normalized copies of every participant's audio
+pub fn normalize_recording(
+    metadata: &RecordingMetadata,
+    options: &NormalizationOptions,
+) -> RecordingResult<NormalizationExport> {
+    let target = metadata.export_format();
+    let mut tracks = HashMap::new();
+
+    for (participant_id, participant) in &metadata.participants {
+        let source = match &participant.audio_file {
+            Some(path) => path,
+            None => continue,
+        };
+
+        let measured = metadata
+            .loudness
+            .as_ref()
+            .and_then(|report| report.tracks.get(participant_id))
+            .and_then(|m| m.integrated_lufs);
+        let output = derived_file_path(source, "normalized", "wav");
+
+        log::info!(
+            "Normalizing audio for participant {} to {} LUFS",
+            participant_id,
+            options.target_lufs
+        );
+        let track = normalize_file(participant_id, source, &output, measured, options, &target)?;
+        tracks.insert(participant_id.clone(), track);
+    }
+
+    Ok(NormalizationExport {
+        exported_at: Utc::now(),
+        options: options.clone(),
+        tracks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::loudness::LoudnessMeter;
+    use super::*;
+    use crate::test_support::{fixture, temp_dir};
+
+    #[test]
+    fn test_normalize_fixture_to_target() {
+        let dir = temp_dir("normalize");
+        let output = dir.join("normalized.wav");
+        let options = NormalizationOptions::default();
+        let track = normalize_file(
+            "p1",
+            &fixture(),
+            &output,
+            None,
+            &options,
+            &MediaFormat::default(),
+        )
+        .unwrap();
+        assert!(track.gain_db < 0.0);
+
+        let mut reader = hound::WavReader::open(&output).unwrap();
+        let samples: Vec<f32> = reader
+            .samples::<i32>()
+            .map(|s| s.unwrap() as f32 / 8_388_607.0)
+            .collect();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let mut meter = LoudnessMeter::new(1, 48000);
+        meter.push(&samples);
+        let result = meter.finish();
+        let integrated = result.integrated_lufs.unwrap();
+        assert!(
+            (integrated - options.target_lufs).abs() < 0.5,
+            "integrated: {}",
+            integrated
+        );
+        assert!(result.true_peak_dbtp.unwrap() <= options.true_peak_ceiling_dbtp + 0.1);
+    }
+}
@@ -0,0 +1,424 @@
+use super::decoder::{OpusHead, OPUS_SAMPLE_RATE};
+use super::storage::derived_file_path;
+use super::types::*;
+use super::webm::{TrackKind, WebmReader};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const OGG_CAPTURE_PATTERN: &[u8] = b"OggS";
+const FLAG_CONTINUED: u8 = 0x01;
+const FLAG_BOS: u8 = 0x02;
+const FLAG_EOS: u8 = 0x04;
+const MAX_SEGMENTS: usize = 255;
+/// Audio per page, about a second as recommended by RFC 7845
+const PAGE_TARGET_SAMPLES: u64 = OPUS_SAMPLE_RATE as u64;
+const VENDOR: &str = "okarin";
+
+/// CRC-32 of Ogg pages: polynomial 0x04C11DB7, no reflection, no final xor
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |crc, &byte| {
+        let mut crc = crc ^ ((byte as u32) << 24);
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Writes packets of a single logical stream into Ogg pages
+pub struct OggWriter<W: Write> {
+    output: W,
+    serial: u32,
+    sequence: u32,
+    lacing: Vec<u8>,
+    body: Vec<u8>,
+    /// Granule position of the last packet completed on the current page
+    granule: Option<u64>,
+    continued: bool,
+}
+
+impl<W: Write> OggWriter<W> {
+    pub fn new(output: W, serial: u32) -> Self {
+        Self {
+            output,
+            serial,
+            sequence: 0,
+            lacing: Vec::new(),
+            body: Vec::new(),
+            granule: None,
+            continued: false,
+        }
+    }
+
+    /// Append a packet ending at `granule_position`, spilling onto new pages
+    /// when it does not fit in the current one
+    pub fn write_packet(&mut self, packet: &[u8], granule_position: u64) -> RecordingResult<()> {
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+
+        let mut offset = 0;
+        for (index, &lace) in lacing.iter().enumerate() {
+            if self.lacing.len() == MAX_SEGMENTS {
+                self.write_page(false)?;
+                self.continued = index > 0;
+            }
+            self.lacing.push(lace);
+            self.body
+                .extend_from_slice(&packet[offset..offset + lace as usize]);
+            offset += lace as usize;
+        }
+        self.granule = Some(granule_position);
+        Ok(())
+    }
+
+    /// Close the current page, so that the next packet starts a new one
+    pub fn flush_page(&mut self) -> RecordingResult<()> {
+        if self.lacing.is_empty() {
+            return Ok(());
+        }
+        self.write_page(false)
+    }
+
+    /// Write the last page with the end-of-stream flag
+    pub fn finish(mut self) -> RecordingResult<W> {
+        self.write_page(true)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+
+    fn write_page(&mut self, eos: bool) -> RecordingResult<()> {
+        let mut flags = 0;
+        if self.continued {
+            flags |= FLAG_CONTINUED;
+        }
+        if self.sequence == 0 {
+            flags |= FLAG_BOS;
+        }
+        if eos {
+            flags |= FLAG_EOS;
+        }
+        // -1 marks a page on which no packet ends
+        let granule = self.granule.map_or(-1, |g| g as i64);
+
+        let mut page = OGG_CAPTURE_PATTERN.to_vec();
+        page.push(0); // version
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]); // CRC, patched below
+        page.push(self.lacing.len() as u8);
+        page.extend_from_slice(&self.lacing);
+        page.extend_from_slice(&self.body);
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        self.output.write_all(&page)?;
+
+        self.sequence += 1;
+        self.lacing.clear();
+        self.body.clear();
+        self.granule = None;
+        self.continued = false;
+        Ok(())
+    }
+}
+
+/// Samples per channel at 48kHz of an Opus packet, read from its TOC byte
+/// (RFC 6716 section 3.1), without decoding it
+pub fn opus_packet_samples(packet: &[u8]) -> RecordingResult<u64> {
+    let toc = *packet
+        .first()
+        .ok_or_else(|| RecordingError::InvalidMedia("empty Opus packet".to_string()))?;
+    let config = toc >> 3;
+    // Frame length in 1/400s (2.5ms) units
+    let frame_units: u64 = match config {
+        0..=11 => [4, 8, 16, 24][config as usize % 4],
+        12..=15 => [4, 8][config as usize % 2],
+        _ => [1, 2, 4, 8][config as usize % 4],
+    };
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => {
+            let count = packet
+                .get(1)
+                .ok_or_else(|| RecordingError::InvalidMedia("truncated Opus packet".to_string()))?;
+            (count & 0x3F) as u64
+        }
+    };
+    Ok(frames * frame_units * OPUS_SAMPLE_RATE as u64 / 400)
+}
+
+/// Serialize an OpusTags header with `KEY=value` user comments
+pub fn opus_tags(comments: &[(&str, &str)]) -> Vec<u8> {
+    let mut data = b"OpusTags".to_vec();
+    data.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    data.extend_from_slice(VENDOR.as_bytes());
+    data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for (key, value) in comments {
+        let comment = format!("{}={}", key, value);
+        data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        data.extend_from_slice(comment.as_bytes());
+    }
+    data
+}
+
+/// Copy the Opus packets of a recorded WebM into an Ogg Opus file
+///
+/// Nothing is decoded or re-encoded: the OpusHead comes from the Matroska
+/// CodecPrivate and granule positions are counted from the packets' TOC.
+/// Where the block timestamps jump ahead of the packets, the gap is filled
+/// with empty packets the decoder conceals, keeping the rest of the track in
+/// place.
+pub fn remux_to_ogg(
+    source: &Path,
+    output: &Path,
+    comments: &[(&str, &str)],
+) -> RecordingResult<PathBuf> {
+    let mut reader = WebmReader::open(source)?;
+    let tracks = reader.read_tracks()?;
+    let track = tracks
+        .iter()
+        .find(|t| t.kind == TrackKind::Audio && t.codec_id == "A_OPUS")
+        .ok_or_else(|| RecordingError::InvalidMedia(format!("no Opus track in {:?}", source)))?;
+    let head = track.codec_private.as_deref().unwrap_or_default();
+    // Validated, then copied verbatim to keep any channel mapping table
+    OpusHead::parse(head)?;
+
+    // Any value works as long as it is unique within the file
+    let serial = ogg_crc(output.to_string_lossy().as_bytes());
+    let mut writer = OggWriter::new(BufWriter::new(File::create(output)?), serial);
+    writer.write_packet(head, 0)?;
+    writer.flush_page()?;
+    writer.write_packet(&opus_tags(comments), 0)?;
+    writer.flush_page()?;
+
+    let mut granule = 0;
+    let mut page_start = 0;
+    let mut first_ns = None;
+    while let Some(block) = reader.next_block()? {
+        if block.track_number != track.number {
+            continue;
+        }
+
+        // Code 0 packet with the TOC of the next one and no frame data
+        let filler = block
+            .frames
+            .first()
+            .and_then(|f| f.first())
+            .map(|toc| [toc & !0x03]);
+        let start_ns = *first_ns.get_or_insert(block.timestamp_ns);
+        let position =
+            (block.timestamp_ns - start_ns).max(0) as u64 * OPUS_SAMPLE_RATE as u64 / 1_000_000_000;
+        let missing = match &filler {
+            Some(filler) => {
+                let samples = opus_packet_samples(filler)?;
+                (position.saturating_sub(granule) + samples / 2) / samples
+            }
+            None => 0,
+        };
+        if missing > 0 {
+            log::warn!(
+                "{:?} has no audio for {} packets at {:.3}s, filling the gap",
+                source,
+                missing,
+                granule as f64 / OPUS_SAMPLE_RATE as f64
+            );
+        }
+
+        let fillers = filler
+            .iter()
+            .flat_map(|f| std::iter::repeat(f.as_slice()).take(missing as usize));
+        for packet in fillers.chain(block.frames.iter().map(Vec::as_slice)) {
+            // Start new pages before a packet, so the last page is never empty
+            if granule - page_start >= PAGE_TARGET_SAMPLES {
+                writer.flush_page()?;
+                page_start = granule;
+            }
+            granule += opus_packet_samples(packet)?;
+            writer.write_packet(packet, granule)?;
+        }
+    }
+    if reader.is_truncated() {
+        log::warn!("Ignoring incomplete data at the end of {:?}", source);
+    }
+
+    writer.finish()?;
+    Ok(output.to_path_buf())
+}
+
+/// Remux every participant's audio to `<participant>-audio.opus`, tagged
+/// with their name
+pub fn export_ogg_tracks(
+    metadata: &RecordingMetadata,
+) -> RecordingResult<HashMap<String, PathBuf>> {
+    let mut tracks = HashMap::new();
+
+    for (participant_id, participant) in &metadata.participants {
+        let source = match &participant.audio_file {
+            Some(path) => path,
+            None => continue,
+        };
+
+        log::info!("Remuxing Ogg Opus for participant {}", participant_id);
+        let comments = [
+            ("TITLE", participant.name.as_str()),
+            ("ARTIST", participant.name.as_str()),
+            ("ALBUM", metadata.room_id.as_str()),
+        ];
+        let output = remux_to_ogg(
+            source,
+            &derived_file_path(source, "audio", "opus"),
+            &comments,
+        )?;
+        tracks.insert(participant_id.clone(), output);
+    }
+
+    Ok(tracks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::muxer::WebmMuxer;
+    use crate::test_support::{fixture, temp_dir};
+
+    /// Split an Ogg stream into (header type, granule, packets ending on the page)
+    fn read_pages(data: &[u8]) -> Vec<(u8, i64, Vec<Vec<u8>>)> {
+        let mut pages = Vec::new();
+        let mut pos = 0;
+        let mut partial = Vec::new();
+        while pos < data.len() {
+            assert_eq!(&data[pos..pos + 4], OGG_CAPTURE_PATTERN);
+            let flags = data[pos + 5];
+            let granule = i64::from_le_bytes(data[pos + 6..pos + 14].try_into().unwrap());
+            let segments = data[pos + 26] as usize;
+            let lacing = &data[pos + 27..pos + 27 + segments];
+            let body_len: usize = lacing.iter().map(|&l| l as usize).sum();
+            let end = pos + 27 + segments + body_len;
+
+            let mut page = data[pos..end].to_vec();
+            let crc = u32::from_le_bytes(page[22..26].try_into().unwrap());
+            page[22..26].copy_from_slice(&[0; 4]);
+            assert_eq!(ogg_crc(&page), crc);
+
+            let mut body = pos + 27 + segments;
+            let mut packets = Vec::new();
+            for &lace in lacing {
+                partial.extend_from_slice(&data[body..body + lace as usize]);
+                body += lace as usize;
+                if lace < 255 {
+                    packets.push(std::mem::take(&mut partial));
+                }
+            }
+            pages.push((flags, granule, packets));
+            pos = end;
+        }
+        pages
+    }
+
+    #[test]
+    fn test_ogg_crc() {
+        // Reference value of the Ogg (non-reflected) CRC-32
+        assert_eq!(ogg_crc(b"123456789"), 0x89A1_897F);
+    }
+
+    #[test]
+    fn test_opus_packet_samples() {
+        // CELT fullband 20ms, one frame
+        assert_eq!(opus_packet_samples(&[0xF8, 0xFF]).unwrap(), 960);
+        // SILK wideband 60ms, two frames
+        assert_eq!(opus_packet_samples(&[(11 << 3) | 1]).unwrap(), 5760);
+        // CELT 2.5ms, code 3 with 4 frames
+        assert_eq!(opus_packet_samples(&[16 << 3 | 3, 4]).unwrap(), 480);
+        assert!(opus_packet_samples(&[]).is_err());
+    }
+
+    #[test]
+    fn test_long_packet_spans_pages() {
+        let mut writer = OggWriter::new(Vec::new(), 7);
+        writer.write_packet(&[1; 300 * 255], 960).unwrap();
+        let pages = read_pages(&writer.finish().unwrap());
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].0, FLAG_BOS);
+        assert_eq!(pages[0].1, -1);
+        assert_eq!(pages[1].0, FLAG_CONTINUED | FLAG_EOS);
+        assert_eq!(pages[1].1, 960);
+        assert_eq!(pages[1].2[0].len(), 300 * 255);
+    }
+
+    #[test]
+    fn test_remux_keeps_packets() {
+        let dir = temp_dir("remux");
+        let output = dir.join("remux.opus");
+        remux_to_ogg(&fixture(), &output, &[("TITLE", "Guest")]).unwrap();
+        let pages = read_pages(&std::fs::read(&output).unwrap());
+        std::fs::remove_dir_all(&dir).ok();
+
+        let mut reader = WebmReader::open(&fixture()).unwrap();
+        let tracks = reader.read_tracks().unwrap();
+        let mut frames = Vec::new();
+        while let Some(block) = reader.next_block().unwrap() {
+            frames.extend(block.frames);
+        }
+
+        // OpusHead alone on the first page, then OpusTags
+        assert_eq!(pages[0].0, FLAG_BOS);
+        assert_eq!(pages[0].2, vec![tracks[0].codec_private.clone().unwrap()]);
+        let tags = &pages[1].2[0];
+        assert!(tags.starts_with(b"OpusTags"));
+        assert!(tags.ends_with(b"TITLE=Guest"));
+
+        let packets: Vec<Vec<u8>> = pages[2..].iter().flat_map(|p| p.2.clone()).collect();
+        assert_eq!(packets, frames);
+        let last = pages.last().unwrap();
+        assert_eq!(last.0 & FLAG_EOS, FLAG_EOS);
+        // 2 seconds of audio plus the encoder pre-skip
+        let head = OpusHead::parse(&pages[0].2[0]).unwrap();
+        let duration = (last.1 as u64 - head.pre_skip as u64) as f64 / 48000.0;
+        assert!((duration - 2.0).abs() < 0.03, "{}", duration);
+    }
+    #[test]
+    fn test_remux_fills_timestamp_gaps() {
+        // The fixture with 200ms missing after its 10th packet
+        let dir = temp_dir("remux-gap");
+        let source = dir.join("gap.webm");
+        let mut reader = WebmReader::open(&fixture()).unwrap();
+        let tracks = reader.read_tracks().unwrap();
+        let mut muxer = WebmMuxer::create(&source, "webm", &tracks).unwrap();
+        let mut frames = Vec::new();
+        while let Some(block) = reader.next_block().unwrap() {
+            let shift_ns = if frames.len() < 10 { 0 } else { 200_000_000 };
+            for frame in block.frames {
+                let timestamp_ns = block.timestamp_ns + shift_ns;
+                muxer
+                    .write_frame(block.track_number, timestamp_ns, 0, true, &frame)
+                    .unwrap();
+                frames.push(frame);
+            }
+        }
+        muxer.finish().unwrap();
+
+        let output = dir.join("gap.opus");
+        remux_to_ogg(&source, &output, &[]).unwrap();
+        let pages = read_pages(&std::fs::read(&output).unwrap());
+        std::fs::remove_dir_all(&dir).ok();
+
+        let filler = vec![frames[10][0] & !0x03];
+        let missing = 9600 / opus_packet_samples(&filler).unwrap() as usize;
+        let packets: Vec<Vec<u8>> = pages[2..].iter().flat_map(|p| p.2.clone()).collect();
+        assert_eq!(packets[..10], frames[..10]);
+        assert_eq!(packets[10..10 + missing], vec![filler; missing]);
+        assert_eq!(packets[10 + missing..], frames[10..]);
+
+        let samples: u64 = frames.iter().map(|f| opus_packet_samples(f).unwrap()).sum();
+        assert_eq!(pages.last().unwrap().1, (samples + 9600) as i64);
+    }
+}
@@ -0,0 +1,334 @@
+use super::decoder::OpusHead;
+use super::types::*;
+use super::webm::{TrackInfo, TrackKind, WebmEvent, WebmReader};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Block statistics of one track, gathered while walking the clusters
+#[derive(Default)]
+struct BlockStats {
+    count: u64,
+    keyframes: u64,
+    first_ns: Option<i64>,
+    last_ns: i64,
+    end_ns: i64,
+    backwards: u64,
+}
+
+impl BlockStats {
+    /// Mean spacing between blocks, used when the track declares no duration
+    fn mean_spacing_ns(&self) -> Option<f64> {
+        let first = self.first_ns?;
+        if self.count < 2 {
+            return None;
+        }
+        Some((self.last_ns - first) as f64 / (self.count - 1) as f64)
+    }
+
+    fn duration_seconds(&self) -> f64 {
+        let Some(first) = self.first_ns else {
+            return 0.0;
+        };
+        let end = if self.end_ns > self.last_ns {
+            self.end_ns as f64
+        } else {
+            self.last_ns as f64 + self.mean_spacing_ns().unwrap_or(0.0)
+        };
+        (end - first as f64) / 1e9
+    }
+}
+
+fn probed_track(track: &TrackInfo, stats: &BlockStats, errors: &mut Vec<String>) -> ProbedTrack {
+    let kind = match track.kind {
+        TrackKind::Audio => ProbedTrackKind::Audio,
+        TrackKind::Video => ProbedTrackKind::Video,
+        TrackKind::Other(_) => ProbedTrackKind::Other,
+    };
+
+    let input_sample_rate = if track.codec_id == "A_OPUS" {
+        match OpusHead::parse(track.codec_private.as_deref().unwrap_or_default()) {
+            Ok(head) => Some(head.input_sample_rate),
+            Err(e) => {
+                errors.push(format!("track {}: {}", track.number, e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if stats.count == 0 {
+        errors.push(format!("track {} has no blocks", track.number));
+    }
+    if stats.backwards > 0 {
+        errors.push(format!(
+            "track {}: {} blocks go back in time",
+            track.number, stats.backwards
+        ));
+    }
+    if kind == ProbedTrackKind::Video && stats.count > 0 && stats.keyframes == 0 {
+        errors.push(format!("track {} has no keyframe", track.number));
+    }
+
+    ProbedTrack {
+        number: track.number,
+        kind,
+        codec_id: track.codec_id.clone(),
+        sample_rate: track.audio.as_ref().map(|a| a.sampling_frequency),
+        input_sample_rate,
+        channels: track.audio.as_ref().map(|a| a.channels),
+        width: track.video.as_ref().map(|v| v.pixel_width),
+        height: track.video.as_ref().map(|v| v.pixel_height),
+        frame_rate: match kind {
+            ProbedTrackKind::Video => stats
+                .mean_spacing_ns()
+                .filter(|&spacing| spacing > 0.0)
+                .map(|spacing| 1e9 / spacing),
+            _ => None,
+        },
+        block_count: stats.count,
+        keyframe_count: stats.keyframes,
+        duration_seconds: stats.duration_seconds(),
+    }
+}
+
+/// Walk every element of a WebM and report its structure
+///
+/// Parsing never fails on damaged data: problems are collected in
+/// `errors` and the walk stops at the first unreadable element.
+pub fn probe<R: Read>(
+    mut reader: WebmReader<R>,
+    path: &Path,
+    file_bytes: u64,
+) -> RecordingResult<ProbeReport> {
+    let mut report = ProbeReport {
+        path: path.to_path_buf(),
+        file_bytes,
+        container: String::new(),
+        writing_app: None,
+        declared_duration_seconds: None,
+        duration_seconds: 0.0,
+        live_segment: false,
+        cluster_count: 0,
+        tracks: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut tracks: Option<Vec<TrackInfo>> = None;
+    let mut stats: BTreeMap<u64, BlockStats> = BTreeMap::new();
+    let mut undeclared = BTreeMap::new();
+    let mut blocks_outside_cluster = 0;
+
+    loop {
+        let event = match reader.next_event() {
+            Ok(Some(event)) => event,
+            Ok(None) => break,
+            Err(e) => {
+                report.errors.push(format!(
+                    "unreadable data at byte {}: {}",
+                    reader.offset(),
+                    e
+                ));
+                break;
+            }
+        };
+
+        match event {
+            WebmEvent::EbmlHeader { doc_type } => report.container = doc_type,
+            WebmEvent::SegmentStart { size, .. } => report.live_segment = size.is_none(),
+            WebmEvent::Info(info) => {
+                report.declared_duration_seconds = info.duration_seconds();
+                report.writing_app = info.writing_app;
+            }
+            WebmEvent::Tracks(entries) => tracks = Some(entries),
+            WebmEvent::ClusterStart { .. } => report.cluster_count += 1,
+            WebmEvent::Block(block) => {
+                if report.cluster_count == 0 {
+                    blocks_outside_cluster += 1;
+                }
+                let declared = tracks
+                    .as_ref()
+                    .and_then(|tracks| tracks.iter().find(|t| t.number == block.track_number));
+                let Some(track) = declared else {
+                    *undeclared.entry(block.track_number).or_insert(0u64) += 1;
+                    continue;
+                };
+
+                let frame_count = block.frames.len().max(1) as u64;
+                let duration_ns = block
+                    .duration_ns
+                    .or_else(|| track.default_duration_ns.map(|d| d * frame_count))
+                    .unwrap_or(0);
+                let track_stats = stats.entry(block.track_number).or_default();
+                if track_stats.count > 0 && block.timestamp_ns < track_stats.last_ns {
+                    track_stats.backwards += 1;
+                }
+                track_stats.count += 1;
+                track_stats.keyframes += block.keyframe as u64;
+                track_stats.first_ns = Some(
+                    track_stats
+                        .first_ns
+                        .map_or(block.timestamp_ns, |first| first.min(block.timestamp_ns)),
+                );
+                track_stats.last_ns = track_stats.last_ns.max(block.timestamp_ns);
+                track_stats.end_ns = track_stats
+                    .end_ns
+                    .max(block.timestamp_ns + duration_ns as i64);
+            }
+            _ => {}
+        }
+    }
+
+    if reader.is_truncated() {
+        report.errors.push(format!(
+            "file ends inside an element at byte {} of {}",
+            reader.offset(),
+            file_bytes
+        ));
+    }
+    if report.container.is_empty() {
+        report.errors.push("no EBML header".to_string());
+    } else if report.container != "webm" && report.container != "matroska" {
+        report
+            .errors
+            .push(format!("unexpected DocType {}", report.container));
+    }
+    if blocks_outside_cluster > 0 {
+        report.errors.push(format!(
+            "{} blocks outside any cluster",
+            blocks_outside_cluster
+        ));
+    }
+    for (number, count) in undeclared {
+        report
+            .errors
+            .push(format!("{} blocks for undeclared track {}", count, number));
+    }
+
+    match tracks {
+        Some(tracks) => {
+            for track in &tracks {
+                let track_stats = stats.remove(&track.number).unwrap_or_default();
+                let probed = probed_track(track, &track_stats, &mut report.errors);
+                report.tracks.push(probed);
+            }
+        }
+        None => report.errors.push("no Tracks element".to_string()),
+    }
+
+    report.duration_seconds = report
+        .tracks
+        .iter()
+        .map(|t| t.duration_seconds)
+        .fold(0.0, f64::max);
+    if let Some(declared) = report.declared_duration_seconds {
+        // 100ms of slack, a few frames: muxers round the Duration differently and
+        // some end it at the start of the last frame rather than its end
+        if (declared - report.duration_seconds).abs() > 0.1 {
+            report.errors.push(format!(
+                "declared duration {:.3}s but blocks cover {:.3}s",
+                declared, report.duration_seconds
+            ));
+        }
+    }
+
+    Ok(report)
+}
+
+/// ffprobe-style report of a recorded WebM, built on our own demuxer
+pub fn probe_recording_file(path: &Path) -> RecordingResult<ProbeReport> {
+    let file_bytes = fs::metadata(path)?.len();
+    probe(WebmReader::open(path)?, path, file_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::muxer::WebmMuxer;
+    use crate::test_support::fixture;
+    use crate::webm::VideoTrackInfo;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_probe_fixture() {
+        let report = probe_recording_file(&fixture()).unwrap();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.container, "webm");
+        assert_eq!(report.cluster_count, 2);
+        assert!((report.duration_seconds - 2.0).abs() < 0.05);
+
+        let track = &report.tracks[0];
+        assert_eq!(track.kind, ProbedTrackKind::Audio);
+        assert_eq!(track.codec_id, "A_OPUS");
+        assert_eq!(track.channels, Some(1));
+        assert_eq!(track.sample_rate, Some(48000.0));
+        assert_eq!(track.block_count, 100);
+        assert_eq!(track.frame_rate, None);
+    }
+
+    #[test]
+    fn test_probe_truncated_file() {
+        let bytes = std::fs::read(fixture()).unwrap();
+        let cut = &bytes[..bytes.len() * 3 / 5 + 7];
+        let report = probe(
+            WebmReader::new(cut),
+            Path::new("cut.webm"),
+            cut.len() as u64,
+        )
+        .unwrap();
+
+        assert!(report
+            .errors
+            .iter()
+            .any(|e| e.starts_with("file ends inside")));
+        assert!(report.tracks[0].block_count < 100);
+        assert!(report.duration_seconds < 1.5);
+    }
+
+    #[test]
+    fn test_probe_video_frame_rate() {
+        let track = TrackInfo {
+            number: 1,
+            uid: Some(1),
+            kind: TrackKind::Video,
+            codec_id: "V_VP8".to_string(),
+            codec_private: None,
+            codec_delay_ns: 0,
+            seek_pre_roll_ns: 0,
+            default_duration_ns: None,
+            audio: None,
+            video: Some(VideoTrackInfo {
+                pixel_width: 1280,
+                pixel_height: 720,
+            }),
+        };
+        let mut muxer = WebmMuxer::new(
+            Cursor::new(Vec::new()),
+            Path::new("video.webm"),
+            "webm",
+            std::slice::from_ref(&track),
+        )
+        .unwrap();
+        // 3 seconds at 25 fps, a keyframe every second
+        for index in 0..75i64 {
+            muxer
+                .write_frame(1, index * 40_000_000, 40_000_000, index % 25 == 0, &[0])
+                .unwrap();
+        }
+        let bytes = muxer.into_inner().unwrap().into_inner();
+
+        let report = probe(
+            WebmReader::new(Cursor::new(&bytes)),
+            Path::new("video.webm"),
+            bytes.len() as u64,
+        )
+        .unwrap();
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        let track = &report.tracks[0];
+        assert_eq!((track.width, track.height), (Some(1280), Some(720)));
+        assert_eq!(track.keyframe_count, 3);
+        assert!((track.frame_rate.unwrap() - 25.0).abs() < 0.01);
+        assert!((report.duration_seconds - 3.0).abs() < 0.05);
+    }
+}
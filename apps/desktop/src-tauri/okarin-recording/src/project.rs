@@ -0,0 +1,290 @@
+use super::decoder::OPUS_SAMPLE_RATE;
+use super::types::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const REAPER_PROJECT_FILENAME: &str = "session.rpp";
+pub const AUDACITY_LABELS_FILENAME: &str = "labels.txt";
+pub const AUDACITY_FILE_LIST_FILENAME: &str = "session.lof";
+
+/// Editor projects written next to the metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectFiles {
+    pub reaper: PathBuf,
+    /// Audacity label track, imported with File > Import > Labels
+    pub audacity_labels: PathBuf,
+    /// Audacity list of files, opening it imports every track at its offset
+    pub audacity_file_list: PathBuf,
+}
+
+/// Participant audio tracks in joining order, with their placement
+struct PlacedTrack<'a> {
+    name: &'a str,
+    file: &'a Path,
+    offset_seconds: f64,
+    length_seconds: f64,
+}
+
+fn placed_tracks(metadata: &RecordingMetadata) -> Vec<PlacedTrack<'_>> {
+    let mut participants: Vec<&ParticipantMetadata> = metadata.participants.values().collect();
+    participants.sort_by_key(|p| p.joined_at);
+
+    participants
+        .into_iter()
+        .filter_map(|participant| {
+            let file = participant.audio_file.as_deref()?;
+            Some(PlacedTrack {
+                name: &participant.name,
+                file,
+                offset_seconds: metadata.participant_offset_seconds(participant),
+                length_seconds: metadata.participant_length_seconds(participant),
+            })
+        })
+        .collect()
+}
+
+/// Path as written in a project, relative when inside the recording directory
+fn project_path(metadata: &RecordingMetadata, file: &Path) -> String {
+    file.strip_prefix(&metadata.output_directory)
+        .unwrap_or(file)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reaper has no escaping, it switches quote characters instead
+fn rpp_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "'"))
+}
+
+fn reaper_source_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "wav" => "WAVE",
+        "flac" => "FLAC",
+        "mp3" => "MP3",
+        "ogg" => "VORBIS",
+        "opus" => "OPUS",
+        // webm, mkv: decoded through Reaper's video/FFmpeg source
+        _ => "VIDEO",
+    }
+}
+
+/// Reaper project: one named track per participant, chapters as regions,
+/// notes as markers
+pub fn reaper_project(metadata: &RecordingMetadata) -> String {
+    let mut rpp = String::new();
+    rpp.push_str("<REAPER_PROJECT 0.1 \"6.0\" 0\n");
+    rpp.push_str(&format!("  SAMPLERATE {} 0 0\n", OPUS_SAMPLE_RATE));
+
+    let chapters = super::chapters::chapters(metadata);
+    for (index, marker) in metadata.markers.iter().enumerate() {
+        let number = index + 1;
+        let name = rpp_string(&marker.label);
+        match marker.kind {
+            MarkerKind::Note => rpp.push_str(&format!(
+                "  MARKER {} {:.6} {} 0\n",
+                number, marker.position_seconds, name
+            )),
+            MarkerKind::Chapter => {
+                let end = chapters
+                    .iter()
+                    .find(|c| c.start_seconds == marker.position_seconds)
+                    .map_or(marker.position_seconds, |c| c.end_seconds);
+                rpp.push_str(&format!(
+                    "  MARKER {} {:.6} {} 1\n  MARKER {} {:.6} \"\" 1\n",
+                    number, marker.position_seconds, name, number, end
+                ));
+            }
+        }
+    }
+
+    for track in placed_tracks(metadata) {
+        let file = project_path(metadata, track.file);
+        rpp.push_str("  <TRACK\n");
+        rpp.push_str(&format!("    NAME {}\n", rpp_string(track.name)));
+        rpp.push_str("    <ITEM\n");
+        rpp.push_str(&format!("      POSITION {:.6}\n", track.offset_seconds));
+        rpp.push_str(&format!("      LENGTH {:.6}\n", track.length_seconds));
+        rpp.push_str("      LOOP 0\n");
+        rpp.push_str(&format!("      NAME {}\n", rpp_string(&file)));
+        rpp.push_str(&format!(
+            "      <SOURCE {}\n        FILE {}\n      >\n",
+            reaper_source_type(track.file),
+            rpp_string(&file)
+        ));
+        rpp.push_str("    >\n  >\n");
+    }
+
+    rpp.push_str(">\n");
+    rpp
+}
+
+/// Audacity label track: chapters as regions, notes as point labels
+pub fn audacity_labels(metadata: &RecordingMetadata) -> String {
+    let chapters = super::chapters::chapters(metadata);
+    let mut labels: Vec<(f64, f64, &str)> = metadata
+        .markers
+        .iter()
+        .map(|marker| {
+            let end = match marker.kind {
+                MarkerKind::Note => marker.position_seconds,
+                MarkerKind::Chapter => chapters
+                    .iter()
+                    .find(|c| c.start_seconds == marker.position_seconds)
+                    .map_or(marker.position_seconds, |c| c.end_seconds),
+            };
+            (marker.position_seconds, end, marker.label.as_str())
+        })
+        .collect();
+    labels.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    labels
+        .into_iter()
+        .map(|(start, end, label)| format!("{:.6}\t{:.6}\t{}\n", start, end, label))
+        .collect()
+}
+
+/// Audacity list of files, every track imported at its offset
+pub fn audacity_file_list(metadata: &RecordingMetadata) -> String {
+    placed_tracks(metadata)
+        .into_iter()
+        .map(|track| {
+            format!(
+                "file \"{}\" offset {:.6}\n",
+                project_path(metadata, track.file),
+                track.offset_seconds
+            )
+        })
+        .collect()
+}
+
+/// Write the Reaper and Audacity projects into the recording directory
+pub fn export_projects(metadata: &RecordingMetadata) -> RecordingResult<ProjectFiles> {
+    let dir = &metadata.output_directory;
+    log::info!("Exporting editor projects to {:?}", dir);
+
+    let files = ProjectFiles {
+        reaper: dir.join(REAPER_PROJECT_FILENAME),
+        audacity_labels: dir.join(AUDACITY_LABELS_FILENAME),
+        audacity_file_list: dir.join(AUDACITY_FILE_LIST_FILENAME),
+    };
+    fs::write(&files.reaper, reaper_project(metadata))?;
+    fs::write(&files.audacity_labels, audacity_labels(metadata))?;
+    fs::write(&files.audacity_file_list, audacity_file_list(metadata))?;
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{participants, recording};
+    use chrono::{Duration, Utc};
+
+    fn session() -> RecordingMetadata {
+        let started_at = Utc::now();
+        let dir = PathBuf::from("/recordings/recording-room-1");
+        let participant = |id: &str, name: &str, joined: i64| ParticipantMetadata {
+            audio_file: Some(dir.join(format!("{}-{}-audio.webm", id, name))),
+            left_at: Some(started_at + Duration::seconds(600)),
+            ..ParticipantMetadata::new(
+                id.to_string(),
+                name.to_string(),
+                started_at + Duration::milliseconds(joined),
+            )
+        };
+        let marker = |label: &str, kind, position_seconds| Marker {
+            id: label.to_string(),
+            label: label.to_string(),
+            kind,
+            position_seconds,
+            created_at: started_at,
+        };
+
+        RecordingMetadata {
+            id: "recording-room-1".to_string(),
+            participants: participants([
+                participant("p2", "Guest", 12_500),
+                participant("p1", "Host", 0),
+            ]),
+            output_directory: dir.clone(),
+            markers: vec![
+                marker("Intro", MarkerKind::Chapter, 0.0),
+                marker("good quote", MarkerKind::Note, 42.25),
+                marker("Segment 2", MarkerKind::Chapter, 300.0),
+            ],
+            ..recording(started_at, 600)
+        }
+    }
+
+    #[test]
+    fn test_reaper_project() {
+        let rpp = reaper_project(&session());
+
+        assert!(rpp.starts_with("<REAPER_PROJECT 0.1 \"6.0\" 0\n  SAMPLERATE 48000 0 0\n"));
+        assert!(rpp.contains("  MARKER 1 0.000000 \"Intro\" 1\n  MARKER 1 300.000000 \"\" 1\n"));
+        assert!(rpp.contains("  MARKER 2 42.250000 \"good quote\" 0\n"));
+        assert!(
+            rpp.contains("  MARKER 3 300.000000 \"Segment 2\" 1\n  MARKER 3 600.000000 \"\" 1\n")
+        );
+
+        let host = rpp.find("NAME \"Host\"").unwrap();
+        let guest = rpp.find("NAME \"Guest\"").unwrap();
+        assert!(host < guest);
+        assert!(rpp[guest..].starts_with(
+            "NAME \"Guest\"\n    <ITEM\n      POSITION 12.500000\n      LENGTH 587.500000\n"
+        ));
+        assert!(rpp.contains("<SOURCE VIDEO\n        FILE \"p2-Guest-audio.webm\"\n      >"));
+        assert!(rpp.ends_with("  >\n>\n"));
+    }
+
+    #[test]
+    fn test_audacity_files() {
+        let metadata = session();
+        assert_eq!(
+            audacity_labels(&metadata),
+            "0.000000\t300.000000\tIntro\n\
+             42.250000\t42.250000\tgood quote\n\
+             300.000000\t600.000000\tSegment 2\n"
+        );
+        assert_eq!(
+            audacity_file_list(&metadata),
+            "file \"p1-Host-audio.webm\" offset 0.000000\n\
+             file \"p2-Guest-audio.webm\" offset 12.500000\n"
+        );
+    }
+
+    #[test]
+    fn test_marker_after_pause_matches_track_position() {
+        // The guest joins after a 20s pause and the host marks the moment,
+        // 130s into the tracks
+        let mut metadata = session();
+        let started_at = metadata.started_at;
+        let at = |seconds| started_at + Duration::seconds(seconds);
+        metadata.pauses = vec![Pause {
+            paused_at: at(100),
+            resumed_at: Some(at(120)),
+            position_seconds: 100.0,
+        }];
+        let guest = metadata.participants.get_mut("p2").unwrap();
+        guest.joined_at = at(150);
+        metadata.markers.push(Marker {
+            id: "marker-4".to_string(),
+            label: "Guest joins".to_string(),
+            kind: MarkerKind::Note,
+            position_seconds: metadata.session_position_seconds(at(150)),
+            created_at: at(150),
+        });
+
+        let rpp = reaper_project(&metadata);
+        assert!(rpp.contains("  MARKER 4 130.000000 \"Guest joins\" 0\n"));
+        assert!(rpp.contains("NAME \"Guest\"\n    <ITEM\n      POSITION 130.000000\n"));
+        assert!(audacity_labels(&metadata).contains("130.000000\t130.000000\tGuest joins\n"));
+        assert!(audacity_file_list(&metadata)
+            .contains("file \"p2-Guest-audio.webm\" offset 130.000000\n"));
+    }
+}
@@ -0,0 +1,605 @@
+// Built without any encoder, only the error path of `EpisodeSink` is left
+#![cfg_attr(not(any(feature = "mp3", feature = "aac")), allow(unused))]
+
+use super::chapters::{self, Chapter};
+use super::decoder::OPUS_SAMPLE_RATE;
+use super::dsp::Limiter;
+use super::format::FormatConverter;
+#[cfg(feature = "mp3")]
+use super::id3;
+use super::mixer::{TrackMixer, MIX_CHANNELS};
+use super::mp4::CoverFormat;
+#[cfg(feature = "aac")]
+use super::mp4::{self, Mp4AudioTrack, Mp4Tags};
+use super::types::*;
+use super::wav::WavFileReader;
+use chrono::Utc;
+#[cfg(feature = "aac")]
+use fdk_aac::enc::{AudioObjectType, BitRate, ChannelMode, EncoderParams, Transport};
+#[cfg(feature = "mp3")]
+use mp3lame_encoder::{Bitrate, FlushNoGap, InterleavedPcm, Quality};
+use std::fs::{self, File};
+#[cfg(feature = "aac")]
+use std::io::BufReader;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// File name (without extension) of the published episode
+pub const EPISODE_FILE_STEM: &str = "episode";
+pub const DEFAULT_BITRATE_KBPS: u32 = 128;
+/// Ceiling of the limiter when the participants are summed
+const PUBLISH_CEILING_DB: f64 = -1.0;
+const PUBLISH_BLOCK_FRAMES: usize = 4800;
+/// Largest AAC access unit for two channels
+#[cfg(feature = "aac")]
+const AAC_MAX_FRAME_BYTES: usize = 8192;
+/// Zero frames fed past the end before giving up on the encoder's tail
+#[cfg(feature = "aac")]
+const AAC_MAX_FLUSH_FRAMES: usize = 16;
+
+/// Stereo PCM to encode, either the summed participants or a final mix
+enum EpisodeSource {
+    Mix {
+        mixer: TrackMixer,
+        limiter: Limiter,
+        finished: bool,
+    },
+    Wav {
+        reader: WavFileReader,
+        converter: FormatConverter,
+        finished: bool,
+    },
+}
+
+impl EpisodeSource {
+    /// The source and the sample rate it delivers
+    fn open(
+        metadata: &RecordingMetadata,
+        options: &PublishOptions,
+    ) -> RecordingResult<(Self, u32)> {
+        if let Some(path) = &options.source {
+            let reader = WavFileReader::open(path)?;
+            // Both encoders take 44.1 and 48kHz, anything else is converted
+            let rate = match reader.sample_rate() {
+                44100 => 44100,
+                _ => OPUS_SAMPLE_RATE,
+            };
+            let converter =
+                FormatConverter::new(reader.channels(), reader.sample_rate(), MIX_CHANNELS, rate);
+            let source = EpisodeSource::Wav {
+                reader,
+                converter,
+                finished: false,
+            };
+            return Ok((source, rate));
+        }
+
+        let mixer = TrackMixer::open(metadata)?;
+        if mixer.track_count() == 0 {
+            return Err(RecordingError::InvalidConfig(
+                "recording has no audio to publish".to_string(),
+            ));
+        }
+        let source = EpisodeSource::Mix {
+            mixer,
+            limiter: Limiter::new(MIX_CHANNELS, OPUS_SAMPLE_RATE, PUBLISH_CEILING_DB),
+            finished: false,
+        };
+        Ok((source, OPUS_SAMPLE_RATE))
+    }
+
+    /// Append the next block to `output`, false once exhausted
+    fn read(&mut self, output: &mut Vec<f32>) -> RecordingResult<bool> {
+        match self {
+            EpisodeSource::Mix {
+                mixer,
+                limiter,
+                finished,
+            } => match mixer.read(PUBLISH_BLOCK_FRAMES)? {
+                Some(block) => limiter.process(&block, output),
+                None if !*finished => {
+                    limiter.flush(output);
+                    *finished = true;
+                }
+                None => return Ok(false),
+            },
+            EpisodeSource::Wav {
+                reader,
+                converter,
+                finished,
+            } => match reader.read(PUBLISH_BLOCK_FRAMES)? {
+                Some(block) => converter.process(&block, output),
+                None if !*finished => {
+                    converter.flush(output);
+                    *finished = true;
+                }
+                None => return Ok(false),
+            },
+        }
+        Ok(true)
+    }
+}
+
+fn to_i16(samples: &[f32]) -> Vec<i16> {
+    samples
+        .iter()
+        .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16)
+        .collect()
+}
+
+fn encode_error<E: std::fmt::Debug>(e: E) -> RecordingError {
+    RecordingError::TrackError(format!("{:?}", e))
+}
+
+#[cfg(feature = "mp3")]
+fn lame_bitrate(kbps: u32) -> RecordingResult<Bitrate> {
+    Ok(match kbps {
+        64 => Bitrate::Kbps64,
+        96 => Bitrate::Kbps96,
+        112 => Bitrate::Kbps112,
+        128 => Bitrate::Kbps128,
+        160 => Bitrate::Kbps160,
+        192 => Bitrate::Kbps192,
+        256 => Bitrate::Kbps256,
+        320 => Bitrate::Kbps320,
+        other => {
+            return Err(RecordingError::InvalidConfig(format!(
+                "MP3 bitrate must be one of 64, 96, 112, 128, 160, 192, 256 or 320 kbps, not {}",
+                other
+            )))
+        }
+    })
+}
+
+/// Where the encoded episode goes, one variant per encoder compiled in
+enum EpisodeSink {
+    #[cfg(feature = "mp3")]
+    Mp3 {
+        encoder: mp3lame_encoder::Encoder,
+        writer: BufWriter<File>,
+        encoded: Vec<u8>,
+        path: PathBuf,
+    },
+    #[cfg(feature = "aac")]
+    M4a {
+        encoder: fdk_aac::enc::Encoder,
+        track: Box<Mp4AudioTrack>,
+        tags: Box<Mp4Tags>,
+        /// Access units, moved behind the `moov` once their sizes are known
+        units: BufWriter<File>,
+        units_path: PathBuf,
+        pending: Vec<i16>,
+        encoded: Vec<u8>,
+        path: PathBuf,
+    },
+    /// Never created, keeps the matches exhaustive when built without encoders
+    #[cfg(not(any(feature = "mp3", feature = "aac")))]
+    Unavailable(std::convert::Infallible),
+}
+
+impl EpisodeSink {
+    fn create(
+        path: &Path,
+        options: &PublishOptions,
+        sample_rate: u32,
+        episode: &EpisodeMetadata,
+        chapters: &[Chapter],
+    ) -> RecordingResult<Self> {
+        let kbps = options.bitrate_kbps.unwrap_or(DEFAULT_BITRATE_KBPS);
+        let cover = episode
+            .artwork_path
+            .as_deref()
+            .map(read_artwork)
+            .transpose()?;
+
+        match options.format {
+            #[cfg(feature = "mp3")]
+            PublishFormat::Mp3 => {
+                let mut builder = mp3lame_encoder::Builder::new().ok_or_else(|| {
+                    RecordingError::TrackError("LAME could not be initialized".to_string())
+                })?;
+                builder
+                    .set_num_channels(MIX_CHANNELS as u8)
+                    .map_err(encode_error)?;
+                builder.set_sample_rate(sample_rate).map_err(encode_error)?;
+                builder
+                    .set_brate(lame_bitrate(kbps)?)
+                    .map_err(encode_error)?;
+                builder.set_quality(Quality::Good).map_err(encode_error)?;
+                let encoder = builder.build().map_err(encode_error)?;
+
+                // The tag goes first, ahead of any audio
+                let mut writer = BufWriter::new(File::create(path)?);
+                writer.write_all(&id3::tag(&id3_frames(episode, cover, chapters)))?;
+
+                Ok(EpisodeSink::Mp3 {
+                    encoder,
+                    writer,
+                    encoded: Vec::new(),
+                    path: path.to_path_buf(),
+                })
+            }
+            #[cfg(feature = "aac")]
+            PublishFormat::M4a => {
+                let encoder = fdk_aac::enc::Encoder::new(EncoderParams {
+                    bit_rate: BitRate::Cbr(kbps * 1000),
+                    sample_rate,
+                    transport: Transport::Raw,
+                    channels: ChannelMode::Stereo,
+                    audio_object_type: AudioObjectType::Mpeg4LowComplexity,
+                })
+                .map_err(encode_error)?;
+                let info = encoder.info().map_err(encode_error)?;
+
+                let track = Box::new(Mp4AudioTrack {
+                    sample_rate,
+                    channels: MIX_CHANNELS,
+                    decoder_config: info.confBuf[..info.confSize as usize].to_vec(),
+                    frame_samples: info.frameLength,
+                    delay_samples: info.nDelay,
+                    duration_samples: 0,
+                    bitrate: kbps * 1000,
+                    sample_sizes: Vec::new(),
+                });
+                let tags = Box::new(Mp4Tags {
+                    title: episode.title.clone(),
+                    album: episode.show.clone(),
+                    track_number: episode.episode_number,
+                    cover,
+                    chapters: chapters.to_vec(),
+                });
+                let units_path = path.with_extension("aac.part");
+
+                Ok(EpisodeSink::M4a {
+                    encoder,
+                    track,
+                    tags,
+                    units: BufWriter::new(File::create(&units_path)?),
+                    units_path,
+                    pending: Vec::new(),
+                    encoded: vec![0; AAC_MAX_FRAME_BYTES],
+                    path: path.to_path_buf(),
+                })
+            }
+            #[allow(unreachable_patterns)]
+            format => {
+                let feature = match format {
+                    PublishFormat::Mp3 => "mp3",
+                    PublishFormat::M4a => "aac",
+                };
+                Err(RecordingError::InvalidConfig(format!(
+                    "{} publishing is not available, okarin-recording was built without the `{}` feature",
+                    format.extension().to_uppercase(),
+                    feature
+                )))
+            }
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) -> RecordingResult<()> {
+        match self {
+            #[cfg(feature = "mp3")]
+            EpisodeSink::Mp3 {
+                encoder,
+                writer,
+                encoded,
+                ..
+            } => {
+                encoded.clear();
+                encoder
+                    .encode_to_vec(InterleavedPcm(&to_i16(samples)), encoded)
+                    .map_err(encode_error)?;
+                writer.write_all(encoded)?;
+            }
+            #[cfg(feature = "aac")]
+            EpisodeSink::M4a {
+                encoder,
+                track,
+                units,
+                pending,
+                encoded,
+                ..
+            } => {
+                track.duration_samples += (samples.len() / MIX_CHANNELS as usize) as u64;
+                pending.extend(to_i16(samples));
+                let frame_len = track.frame_samples as usize * MIX_CHANNELS as usize;
+                while pending.len() >= frame_len {
+                    let frame: Vec<i16> = pending.drain(..frame_len).collect();
+                    encode_aac_frame(encoder, &frame, encoded, track, units)?;
+                }
+            }
+            #[cfg(not(any(feature = "mp3", feature = "aac")))]
+            EpisodeSink::Unavailable(never) => match *never {},
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> RecordingResult<PathBuf> {
+        match self {
+            #[cfg(feature = "mp3")]
+            EpisodeSink::Mp3 {
+                mut encoder,
+                mut writer,
+                mut encoded,
+                path,
+            } => {
+                encoded.clear();
+                encoder
+                    .flush_to_vec::<FlushNoGap>(&mut encoded)
+                    .map_err(encode_error)?;
+                writer.write_all(&encoded)?;
+                writer.flush()?;
+                Ok(path)
+            }
+            #[cfg(feature = "aac")]
+            EpisodeSink::M4a {
+                encoder,
+                mut track,
+                tags,
+                mut units,
+                units_path,
+                mut pending,
+                mut encoded,
+                path,
+            } => {
+                // Feed silence until the encoder's delay has pushed out the last sample
+                let frame_len = track.frame_samples as usize * MIX_CHANNELS as usize;
+                let needed = track.duration_samples + track.delay_samples as u64;
+                let mut flushed = 0;
+                while (track.sample_sizes.len() as u64 * track.frame_samples as u64) < needed {
+                    if flushed == AAC_MAX_FLUSH_FRAMES {
+                        return Err(RecordingError::TrackError(
+                            "AAC encoder did not flush its last frames".to_string(),
+                        ));
+                    }
+                    pending.resize(frame_len, 0);
+                    let frame = std::mem::take(&mut pending);
+                    encode_aac_frame(&encoder, &frame, &mut encoded, &mut track, &mut units)?;
+                    flushed += 1;
+                }
+                units.flush()?;
+                drop(units);
+
+                let result = File::open(&units_path)
+                    .map_err(RecordingError::from)
+                    .and_then(|file| {
+                        mp4::write_m4a(&path, &track, &tags, &mut BufReader::new(file))
+                    });
+                fs::remove_file(&units_path).ok();
+                result
+            }
+            #[cfg(not(any(feature = "mp3", feature = "aac")))]
+            EpisodeSink::Unavailable(never) => match never {},
+        }
+    }
+}
+
+#[cfg(feature = "aac")]
+fn encode_aac_frame(
+    encoder: &fdk_aac::enc::Encoder,
+    frame: &[i16],
+    encoded: &mut [u8],
+    track: &mut Mp4AudioTrack,
+    units: &mut BufWriter<File>,
+) -> RecordingResult<()> {
+    let info = encoder.encode(frame, encoded).map_err(encode_error)?;
+    // Nothing comes out while the encoder fills its lookahead
+    if info.output_size > 0 {
+        units.write_all(&encoded[..info.output_size])?;
+        track.sample_sizes.push(info.output_size as u32);
+    }
+    Ok(())
+}
+
+/// Load the cover art, which must be a JPEG or a PNG
+fn read_artwork(path: &Path) -> RecordingResult<(CoverFormat, Vec<u8>)> {
+    let image = fs::read(path)?;
+    let format = if image.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        CoverFormat::Jpeg
+    } else if image.starts_with(b"\x89PNG") {
+        CoverFormat::Png
+    } else {
+        return Err(RecordingError::InvalidMedia(format!(
+            "{:?} is neither a JPEG nor a PNG",
+            path
+        )));
+    };
+    Ok((format, image))
+}
+
+/// ID3 frames of the MP3: titles, episode number, cover and chapters
+#[cfg(feature = "mp3")]
+fn id3_frames(
+    episode: &EpisodeMetadata,
+    cover: Option<(CoverFormat, Vec<u8>)>,
+    chapters: &[Chapter],
+) -> Vec<u8> {
+    let mut frames = id3::text_frame(b"TIT2", &episode.title);
+    frames.extend(id3::text_frame(b"TALB", &episode.show));
+    if let Some(number) = episode.episode_number {
+        frames.extend(id3::text_frame(b"TRCK", &number.to_string()));
+    }
+    if let Some((format, image)) = cover {
+        let mime_type = match format {
+            CoverFormat::Jpeg => "image/jpeg",
+            CoverFormat::Png => "image/png",
+        };
+        frames.extend(id3::picture_frame(mime_type, &image));
+    }
+    if !chapters.is_empty() {
+        frames.extend(chapters::id3_frames(chapters));
+    }
+    frames
+}
+
+/// Path of the published episode for a given format
+pub fn episode_file_path(recording_dir: &Path, format: PublishFormat) -> PathBuf {
+    recording_dir.join(format!("{}.{}", EPISODE_FILE_STEM, format.extension()))
+}
+
+/// Encode the episode for publishing, tagged from `episode` and the chapter markers
+///
+/// Chapters follow the session timeline, which a supplied final mix is
+/// expected to keep.
+pub fn publish_episode(
+    metadata: &RecordingMetadata,
+    episode: &EpisodeMetadata,
+    options: &PublishOptions,
+) -> RecordingResult<PublishExport> {
+    let (mut source, sample_rate) = EpisodeSource::open(metadata, options)?;
+    let chapters = chapters::chapters(metadata);
+    let path = episode_file_path(&metadata.output_directory, options.format);
+    log::info!(
+        "Publishing {:?} with {} chapters to {:?}",
+        episode.title,
+        chapters.len(),
+        path
+    );
+
+    let mut sink = EpisodeSink::create(&path, options, sample_rate, episode, &chapters)?;
+    let mut block = Vec::with_capacity(PUBLISH_BLOCK_FRAMES * MIX_CHANNELS as usize);
+    let mut frames = 0u64;
+    while source.read(&mut block)? {
+        frames += (block.len() / MIX_CHANNELS as usize) as u64;
+        sink.write(&block)?;
+        block.clear();
+    }
+    let file = sink.finish()?;
+
+    Ok(PublishExport {
+        exported_at: Utc::now(),
+        format: options.format,
+        size_bytes: fs::metadata(&file)?.len(),
+        file,
+        duration_seconds: frames as f64 / sample_rate as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, temp_dir};
+
+    fn fixture_metadata(output_directory: PathBuf) -> RecordingMetadata {
+        let metadata = test_support::fixture_metadata(output_directory);
+        RecordingMetadata {
+            markers: vec![Marker {
+                id: "marker-1".to_string(),
+                label: "Intro".to_string(),
+                kind: MarkerKind::Chapter,
+                position_seconds: 0.0,
+                created_at: metadata.started_at,
+            }],
+            ..metadata
+        }
+    }
+
+    fn episode(artwork_path: Option<PathBuf>) -> EpisodeMetadata {
+        EpisodeMetadata {
+            title: "Pilot".to_string(),
+            show: "Okarin Radio".to_string(),
+            description: String::new(),
+            episode_number: Some(1),
+            artwork_path,
+            export: None,
+        }
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    #[cfg(feature = "mp3")]
+    fn test_publish_mp3_with_id3_tag() {
+        let dir = temp_dir("publish-mp3");
+        let artwork = dir.join("cover.png");
+        std::fs::write(&artwork, b"\x89PNG\r\n\x1a\nfake").unwrap();
+
+        let options = PublishOptions::default();
+        let export = publish_episode(
+            &fixture_metadata(dir.clone()),
+            &episode(Some(artwork)),
+            &options,
+        )
+        .unwrap();
+        let data = std::fs::read(&export.file).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(export.file, dir.join("episode.mp3"));
+        assert_eq!(export.size_bytes, data.len() as u64);
+        assert!((export.duration_seconds - 2.0).abs() < 0.05);
+
+        assert_eq!(&data[..5], b"ID3\x04\x00");
+        let tag_size = data[6..10]
+            .iter()
+            .fold(0usize, |size, &b| size << 7 | b as usize);
+        let tag = &data[10..10 + tag_size];
+        assert!(contains(tag, b"TIT2\0\0\0\x06\0\0\x03Pilot"));
+        assert!(contains(tag, b"\x03Okarin Radio"));
+        assert!(contains(tag, b"APIC"));
+        assert!(contains(tag, b"image/png\0\x03\0\x89PNG"));
+        assert!(contains(tag, b"CHAP"));
+
+        // MPEG audio frames follow the tag right away
+        let audio = &data[10 + tag_size..];
+        assert_eq!(audio[0], 0xFF);
+        assert_eq!(audio[1] & 0xE0, 0xE0);
+    }
+
+    #[test]
+    #[cfg(feature = "aac")]
+    fn test_publish_m4a_puts_moov_first() {
+        let dir = temp_dir("publish-m4a");
+        let options = PublishOptions {
+            format: PublishFormat::M4a,
+            source: None,
+            bitrate_kbps: Some(96),
+        };
+
+        let export =
+            publish_episode(&fixture_metadata(dir.clone()), &episode(None), &options).unwrap();
+        let data = std::fs::read(&export.file).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(export.file, dir.join("episode.m4a"));
+        let mut offset = 0;
+        let mut boxes = Vec::new();
+        while offset < data.len() {
+            let size = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+            boxes.push((&data[offset + 4..offset + 8], offset));
+            offset += size;
+        }
+        assert_eq!(offset, data.len());
+        let kinds: Vec<&[u8]> = boxes.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, vec![&b"ftyp"[..], b"moov", b"mdat"]);
+
+        let moov = &data[boxes[1].1..boxes[2].1];
+        assert!(contains(moov, b"data\0\0\0\x01\0\0\0\0Pilot"));
+        assert!(contains(moov, b"chpl"));
+        let stco = moov.windows(4).position(|w| w == b"stco").unwrap();
+        let chunk_offset = u32::from_be_bytes(moov[stco + 12..stco + 16].try_into().unwrap());
+        assert_eq!(chunk_offset as usize, boxes[2].1 + 8);
+    }
+
+    #[test]
+    #[cfg(not(feature = "aac"))]
+    fn test_publish_m4a_needs_aac_feature() {
+        let dir = temp_dir("publish-no-aac");
+        let options = PublishOptions {
+            format: PublishFormat::M4a,
+            source: None,
+            bitrate_kbps: None,
+        };
+
+        let result = publish_episode(&fixture_metadata(dir.clone()), &episode(None), &options);
+        std::fs::remove_dir_all(&dir).ok();
+
+        match result {
+            Err(RecordingError::InvalidConfig(message)) => {
+                assert!(message.contains("`aac` feature"), "{}", message)
+            }
+            other => panic!("expected a missing feature error, got {:?}", other),
+        }
+    }
+}
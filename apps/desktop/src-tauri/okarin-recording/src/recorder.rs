@@ -1,3 +1,4 @@
+use super::meter::MeterListener;
 use super::storage::StorageManager;
 use super::track::TrackRecorder;
 use super::types::*;
@@ -18,6 +19,7 @@ struct RecordingState {
     tracks: HashMap<String, TrackRecorder>,
     metadata: Option<RecordingMetadata>,
     recording_id: Option<String>,
+    meter_listener: Option<MeterListener>,
 }
 
 impl RecordingManager {
@@ -30,10 +32,16 @@ impl RecordingManager {
                 tracks: HashMap::new(),
                 metadata: None,
                 recording_id: None,
+                meter_listener: None,
             })),
         }
     }
 
+    /// Receive live input levels and warnings of the participants' audio
+    pub fn set_meter_listener(&self, listener: MeterListener) {
+        self.state.write().meter_listener = Some(listener);
+    }
+
     /// Start a new recording session
     pub fn start_recording(&self, config: RecordingConfig) -> RecordingResult<String> {
         let mut state = self.state.write();
@@ -51,13 +59,13 @@ impl RecordingManager {
 
         // Initialize metadata
         let metadata = RecordingMetadata {
-            id: recording_id.clone(),
-            room_id: config.room_id.clone(),
-            started_at: Utc::now(),
-            stopped_at: None,
-            duration_seconds: 0,
-            participants: HashMap::new(),
-            output_directory: storage.get_output_dir().to_path_buf(),
+            configured_format: Some(config.media_format()),
+            ..RecordingMetadata::new(
+                recording_id.clone(),
+                config.room_id.clone(),
+                Utc::now(),
+                storage.get_output_dir().to_path_buf(),
+            )
         };
 
         state.status = RecordingStatus::Recording {
@@ -104,7 +112,7 @@ impl RecordingManager {
 
         // Create file writers
         let audio_writer = if record_audio {
-            Some(storage.create_audio_file(&participant_id, &participant_name, config)?)
+            Some(storage.create_audio_file(&participant_id, &participant_name)?)
         } else {
             None
         };
@@ -122,6 +130,7 @@ impl RecordingManager {
             config,
             audio_writer,
             video_writer,
+            state.meter_listener.clone(),
         )?;
 
         log::info!(
@@ -134,14 +143,12 @@ impl RecordingManager {
         if let Some(metadata) = &mut state.metadata {
             metadata.participants.insert(
                 participant_id.clone(),
-                ParticipantMetadata {
-                    id: participant_id.clone(),
-                    name: participant_name.clone(),
-                    audio_file: None, // Will be set when stopping
-                    video_file: None, // Will be set when stopping
-                    joined_at: Utc::now(),
-                    left_at: None,
-                },
+                // Track files are set when stopping
+                ParticipantMetadata::new(
+                    participant_id.clone(),
+                    participant_name.clone(),
+                    Utc::now(),
+                ),
             );
         }
 
@@ -151,9 +158,17 @@ impl RecordingManager {
     }
 
     /// Add audio chunk for a participant
+    ///
+    /// Chunks arriving while the session is paused are dropped, paused time
+    /// is left out of the tracks like it is left out of the timeline.
     pub fn add_audio_chunk(&self, participant_id: &str, chunk: Vec<u8>) -> RecordingResult<()> {
         let state = self.state.read();
 
+        if matches!(state.status, RecordingStatus::Paused { .. }) {
+            log::debug!("Dropping audio chunk of {} while paused", participant_id);
+            return Ok(());
+        }
+
         let track = state
             .tracks
             .get(participant_id)
@@ -163,10 +178,15 @@ impl RecordingManager {
         Ok(())
     }
 
-    /// Add video chunk for a participant
+    /// Add video chunk for a participant, dropped while paused like audio
     pub fn add_video_chunk(&self, participant_id: &str, chunk: Vec<u8>) -> RecordingResult<()> {
         let state = self.state.read();
 
+        if matches!(state.status, RecordingStatus::Paused { .. }) {
+            log::debug!("Dropping video chunk of {} while paused", participant_id);
+            return Ok(());
+        }
+
         let track = state
             .tracks
             .get(participant_id)
@@ -187,14 +207,7 @@ impl RecordingManager {
 
         log::info!("Stopping recording...");
 
-        let started_at = match state.status {
-            RecordingStatus::Recording { started_at } => started_at,
-            RecordingStatus::Paused { started_at, .. } => started_at,
-            _ => Utc::now(),
-        };
-
         let stopped_at = Utc::now();
-        let duration = (stopped_at - started_at).num_seconds().max(0) as u64;
 
         // Stop all track recorders and collect results
         let tracks = std::mem::take(&mut state.tracks);
@@ -217,7 +230,10 @@ impl RecordingManager {
             .ok_or(RecordingError::NoActiveRecording)?;
 
         metadata.stopped_at = Some(stopped_at);
-        metadata.duration_seconds = duration;
+        if let Some(pause) = metadata.pauses.last_mut() {
+            pause.resumed_at.get_or_insert(stopped_at);
+        }
+        metadata.duration_seconds = metadata.session_length_seconds() as u64;
 
         // Update participant metadata with file paths
         for result in track_results {
@@ -225,6 +241,17 @@ impl RecordingManager {
                 participant_meta.audio_file = result.audio_file;
                 participant_meta.video_file = result.video_file;
                 participant_meta.left_at = Some(stopped_at);
+                participant_meta.format = result.stats.format;
+                participant_meta.format_mismatches = result.stats.format_mismatches;
+                participant_meta.video_offset_seconds = match (
+                    result.stats.first_audio_chunk_at,
+                    result.stats.first_video_chunk_at,
+                ) {
+                    (Some(audio), Some(video)) => {
+                        Some((video - audio).num_milliseconds() as f64 / 1000.0)
+                    }
+                    _ => None,
+                };
 
                 log::info!(
                     "Participant {} recording stats: audio chunks: {}, video chunks: {}, errors: {}",
@@ -233,6 +260,13 @@ impl RecordingManager {
                     result.stats.video_chunks_received,
                     result.stats.errors.len()
                 );
+                if let Some(reason) = &result.stats.rejected {
+                    log::warn!(
+                        "Participant {} was refused: {}",
+                        result.participant_id,
+                        reason
+                    );
+                }
             }
         }
 
@@ -269,16 +303,58 @@ impl RecordingManager {
         self.state.read().metadata.clone()
     }
 
+    /// Drop a marker at the current position of the session timeline
+    pub fn add_marker(&self, label: String, kind: MarkerKind) -> RecordingResult<Marker> {
+        let mut state = self.state.write();
+
+        if !matches!(
+            state.status,
+            RecordingStatus::Recording { .. } | RecordingStatus::Paused { .. }
+        ) {
+            return Err(RecordingError::NoActiveRecording);
+        }
+        let metadata = state
+            .metadata
+            .as_mut()
+            .ok_or(RecordingError::NoActiveRecording)?;
+
+        let created_at = Utc::now();
+        let marker = Marker {
+            id: format!("marker-{}", metadata.markers.len() + 1),
+            label,
+            kind,
+            position_seconds: metadata.session_position_seconds(created_at),
+            created_at,
+        };
+        log::info!(
+            "Marker \"{}\" added at {:.2}s",
+            marker.label,
+            marker.position_seconds
+        );
+        metadata.markers.push(marker.clone());
+
+        Ok(marker)
+    }
+
     /// Pause recording (marks status but doesn't stop threads)
     pub fn pause_recording(&self) -> RecordingResult<()> {
         let mut state = self.state.write();
 
         match state.status {
             RecordingStatus::Recording { started_at } => {
+                let paused_at = Utc::now();
                 state.status = RecordingStatus::Paused {
                     started_at,
-                    paused_at: Utc::now(),
+                    paused_at,
                 };
+                if let Some(metadata) = &mut state.metadata {
+                    let position_seconds = metadata.session_position_seconds(paused_at);
+                    metadata.pauses.push(Pause {
+                        paused_at,
+                        resumed_at: None,
+                        position_seconds,
+                    });
+                }
                 log::info!("Recording paused");
                 Ok(())
             }
@@ -293,6 +369,9 @@ impl RecordingManager {
         match state.status {
             RecordingStatus::Paused { started_at, .. } => {
                 state.status = RecordingStatus::Recording { started_at };
+                if let Some(pause) = state.metadata.as_mut().and_then(|m| m.pauses.last_mut()) {
+                    pause.resumed_at = Some(Utc::now());
+                }
                 log::info!("Recording resumed");
                 Ok(())
             }
@@ -310,6 +389,7 @@ impl Default for RecordingManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::temp_dir;
     use std::path::PathBuf;
 
     #[test]
@@ -329,4 +409,73 @@ mod tests {
         // This will fail in test without proper filesystem setup, but tests the flow
         let _ = manager.start_recording(config);
     }
+
+    #[test]
+    fn test_markers_skip_paused_time() {
+        let manager = RecordingManager::new();
+        assert!(matches!(
+            manager.add_marker("intro".to_string(), MarkerKind::Chapter),
+            Err(RecordingError::NoActiveRecording)
+        ));
+
+        let output_dir = temp_dir("markers");
+        let config = RecordingConfig {
+            room_id: "marker-room".to_string(),
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+        manager.start_recording(config).unwrap();
+        manager.pause_recording().unwrap();
+        let paused = manager.add_marker("during pause".to_string(), MarkerKind::Note);
+        manager.resume_recording().unwrap();
+
+        let mut metadata = manager.get_metadata().unwrap();
+        std::fs::remove_dir_all(&output_dir).ok();
+        assert_eq!(metadata.markers, vec![paused.unwrap()]);
+        assert!(metadata.pauses[0].resumed_at.is_some());
+
+        // 10s recorded, 20s paused, 5s recorded: the tracks are 15s long
+        // and a marker dropped at the end sits at 15s, like their last sample
+        let started_at = metadata.started_at;
+        let at = |seconds| started_at + chrono::Duration::seconds(seconds);
+        metadata.pauses = vec![Pause {
+            paused_at: at(10),
+            resumed_at: Some(at(30)),
+            position_seconds: 10.0,
+        }];
+        metadata.stopped_at = Some(at(35));
+        let host = ParticipantMetadata::new("p1".to_string(), "Host".to_string(), started_at);
+        assert_eq!(metadata.session_position_seconds(at(5)), 5.0);
+        assert_eq!(metadata.session_position_seconds(at(20)), 10.0);
+        assert_eq!(metadata.session_position_seconds(at(35)), 15.0);
+        assert_eq!(metadata.participant_length_seconds(&host), 15.0);
+        assert_eq!(metadata.session_length_seconds(), 15.0);
+    }
+
+    #[test]
+    fn test_chunks_are_dropped_while_paused() {
+        let manager = RecordingManager::new();
+        let output_dir = temp_dir("paused-chunks");
+        let config = RecordingConfig {
+            room_id: "pause-room".to_string(),
+            output_dir: output_dir.clone(),
+            ..Default::default()
+        };
+        manager.start_recording(config).unwrap();
+        manager
+            .add_participant("p1".to_string(), "Host".to_string(), true, false)
+            .unwrap();
+
+        manager.add_audio_chunk("p1", vec![1; 8]).unwrap();
+        manager.pause_recording().unwrap();
+        manager.add_audio_chunk("p1", vec![2; 8]).unwrap();
+        manager.resume_recording().unwrap();
+        manager.add_audio_chunk("p1", vec![3; 8]).unwrap();
+        let metadata = manager.stop_recording().unwrap();
+
+        let audio_file = metadata.participants["p1"].audio_file.clone().unwrap();
+        let written = std::fs::read(audio_file).unwrap();
+        std::fs::remove_dir_all(&output_dir).ok();
+        assert_eq!(written, [[1; 8], [3; 8]].concat());
+    }
 }
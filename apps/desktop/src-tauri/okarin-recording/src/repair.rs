@@ -0,0 +1,214 @@
+use super::muxer::WebmMuxer;
+use super::types::*;
+use super::webm::{WebmEvent, WebmReader};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path of the repaired copy, `p1-John-audio.webm` becomes
+/// `p1-John-audio-repaired.webm`
+pub fn repaired_file_path(source: &Path) -> PathBuf {
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    source.with_file_name(format!("{}-repaired.webm", stem))
+}
+
+/// Rewrite a recorded WebM with known element sizes and a Duration
+///
+/// MediaRecorder output has unknown-size Segment and Clusters and no
+/// Duration, and a file whose writer was killed ends mid-cluster; players
+/// refuse or cannot seek in either. Every complete block is copied
+/// untouched, the first incomplete or unreadable element ends the copy.
+pub fn repair_file(source: &Path, output: &Path) -> RecordingResult<RepairReport> {
+    let source_bytes = fs::metadata(source)?.len();
+    let mut reader = WebmReader::open(source)?;
+    let mut doc_type = "webm".to_string();
+    let tracks = loop {
+        match reader.next_event()? {
+            Some(WebmEvent::EbmlHeader { doc_type: found }) => doc_type = found,
+            Some(WebmEvent::Tracks(tracks)) => break tracks,
+            Some(WebmEvent::Block(_)) | None => {
+                return Err(RecordingError::InvalidMedia(format!(
+                    "no Tracks element in {:?}, nothing to recover",
+                    source
+                )))
+            }
+            Some(_) => {}
+        }
+    };
+
+    let default_durations: HashMap<u64, u64> = tracks
+        .iter()
+        .filter_map(|t| t.default_duration_ns.map(|d| (t.number, d)))
+        .collect();
+    let mut muxer = WebmMuxer::create(output, &doc_type, &tracks)?;
+    let mut recovered_blocks = 0;
+    let mut recovered_bytes = reader.offset();
+    let mut start_ns = None;
+    let mut end_ns = 0;
+    let mut unreadable = false;
+
+    loop {
+        let block = match reader.next_block() {
+            Ok(Some(block)) => block,
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!("Stopping at unreadable data in {:?}: {}", source, e);
+                unreadable = true;
+                break;
+            }
+        };
+
+        let frame_count = block.frames.len().max(1) as u64;
+        let frame_duration = block
+            .duration_ns
+            .map(|d| d / frame_count)
+            .or_else(|| default_durations.get(&block.track_number).copied())
+            .unwrap_or(0);
+        for (index, frame) in block.frames.iter().enumerate() {
+            muxer.write_frame(
+                block.track_number,
+                block.timestamp_ns + (index as u64 * frame_duration) as i64,
+                frame_duration,
+                block.keyframe,
+                frame,
+            )?;
+        }
+
+        recovered_blocks += 1;
+        recovered_bytes = reader.offset();
+        let start = start_ns.get_or_insert(block.timestamp_ns);
+        *start = (*start).min(block.timestamp_ns);
+        end_ns = end_ns.max(block.timestamp_ns + (frame_count * frame_duration) as i64);
+    }
+
+    let truncated = unreadable || reader.is_truncated();
+    let dropped_bytes = source_bytes.saturating_sub(recovered_bytes);
+    if truncated {
+        log::warn!(
+            "Dropped {} trailing bytes of {:?} after byte {}",
+            dropped_bytes,
+            source,
+            recovered_bytes
+        );
+    }
+
+    Ok(RepairReport {
+        source_file: source.to_path_buf(),
+        output_file: muxer.finish()?,
+        repaired_at: Utc::now(),
+        truncated,
+        source_bytes,
+        dropped_bytes: if truncated { dropped_bytes } else { 0 },
+        recovered_blocks,
+        recovered_seconds: start_ns.map_or(0.0, |start| (end_ns - start) as f64 / 1e9),
+    })
+}
+
+/// Recorded tracks of a recording directory, found by name so that a session
+/// that crashed before writing its metadata can still be repaired
+pub fn recorded_tracks(recording_dir: &Path) -> RecordingResult<Vec<PathBuf>> {
+    let mut tracks: Vec<PathBuf> = fs::read_dir(recording_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| name.ends_with("-audio.webm") || name.ends_with("-video.webm"))
+        })
+        .collect();
+    tracks.sort();
+    Ok(tracks)
+}
+
+/// Write repaired copies of every recorded track of a directory
+pub fn repair_recording(recording_dir: &Path) -> RecordingResult<Vec<RepairReport>> {
+    recorded_tracks(recording_dir)?
+        .iter()
+        .map(|source| {
+            log::info!("Repairing {:?}", source);
+            repair_file(source, &repaired_file_path(source))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::OpusTrackDecoder;
+    use crate::test_support::{fixture, temp_dir};
+
+    fn file_duration(path: &Path) -> Option<f64> {
+        let mut reader = WebmReader::open(path).unwrap();
+        let mut duration = None;
+        while let Some(event) = reader.next_event().unwrap() {
+            if let WebmEvent::Info(info) = event {
+                duration = info.duration_seconds();
+            }
+        }
+        assert!(!reader.is_truncated());
+        duration
+    }
+
+    #[test]
+    fn test_repair_complete_file() {
+        let dir = temp_dir("repair-complete");
+        let output = dir.join("repaired.webm");
+        let report = repair_file(&fixture(), &output).unwrap();
+
+        assert!(!report.truncated);
+        assert_eq!(report.dropped_bytes, 0);
+        assert!((report.recovered_seconds - 2.0).abs() < 0.05);
+        assert!((file_duration(&output).unwrap() - 2.0).abs() < 0.05);
+
+        let original = OpusTrackDecoder::open(&fixture())
+            .unwrap()
+            .decode_all()
+            .unwrap();
+        let repaired = OpusTrackDecoder::open(&output)
+            .unwrap()
+            .decode_all()
+            .unwrap();
+        assert_eq!(repaired.frame_count(), original.frame_count());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_repair_truncated_file() {
+        let dir = temp_dir("repair-truncated");
+        let bytes = fs::read(fixture()).unwrap();
+        // App killed partway through writing a cluster
+        let source = dir.join("p1-Host-audio.webm");
+        fs::write(&source, &bytes[..bytes.len() * 3 / 5 + 7]).unwrap();
+        fs::write(dir.join("notes.txt"), "not a track").unwrap();
+
+        let reports = repair_recording(&dir).unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert!(report.truncated);
+        assert!(report.dropped_bytes > 0 && report.dropped_bytes < 400);
+        assert!(report.recovered_seconds > 1.0 && report.recovered_seconds < 1.5);
+        assert_eq!(report.output_file, dir.join("p1-Host-audio-repaired.webm"));
+
+        let duration = file_duration(&report.output_file).unwrap();
+        assert!((duration - report.recovered_seconds).abs() < 0.03);
+        let decoded = OpusTrackDecoder::open(&report.output_file)
+            .unwrap()
+            .decode_all()
+            .unwrap();
+        assert!(decoded.duration_seconds() > 1.0);
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_repaired_file_path() {
+        assert_eq!(
+            repaired_file_path(Path::new("/rec/p1-John-audio.webm")),
+            PathBuf::from("/rec/p1-John-audio-repaired.webm")
+        );
+    }
+}
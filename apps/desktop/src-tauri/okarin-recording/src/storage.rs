@@ -0,0 +1,275 @@
+use super::types::*;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const METADATA_FILENAME: &str = "metadata.json";
+pub const ACTIVITY_FILENAME: &str = "activity.json";
+pub const EPISODE_FILENAME: &str = "episode.json";
+/// Feed settings, in the recordings root rather than in a recording
+pub const FEED_SETTINGS_FILENAME: &str = "feed.json";
+/// Library index, in the recordings root as well
+pub const LIBRARY_INDEX_FILENAME: &str = "library.json";
+
+/// Manages file storage for multitrack recordings
+pub struct StorageManager {
+    output_dir: PathBuf,
+    recording_id: String,
+}
+
+impl StorageManager {
+    pub fn new(base_dir: PathBuf, room_id: &str) -> RecordingResult<Self> {
+        let timestamp = Utc::now().format("%Y-%m-%d_%H-%M-%S");
+        let recording_id = format!("recording-{}-{}", room_id, timestamp);
+        let output_dir = base_dir.join(&recording_id);
+
+        // Create recording directory
+        fs::create_dir_all(&output_dir)?;
+
+        Ok(Self {
+            output_dir,
+            recording_id,
+        })
+    }
+
+    pub fn get_output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    pub fn get_recording_id(&self) -> &str {
+        &self.recording_id
+    }
+
+    /// Create WebM file for audio track (Opus codec)
+    pub fn create_audio_file(
+        &self,
+        participant_id: &str,
+        participant_name: &str,
+    ) -> RecordingResult<AudioFileWriter> {
+        let filename = format!("{}-{}-audio.webm", participant_id, sanitize_filename(participant_name));
+        let path = self.output_dir.join(&filename);
+
+        let file = File::create(&path)?;
+
+        Ok(AudioFileWriter {
+            file,
+            path,
+            chunk_count: 0,
+        })
+    }
+
+    /// Create WebM video file (will contain VP9/H264 encoded video)
+    pub fn create_video_file(
+        &self,
+        participant_id: &str,
+        participant_name: &str,
+    ) -> RecordingResult<VideoFileWriter> {
+        let filename = format!("{}-{}-video.webm", participant_id, sanitize_filename(participant_name));
+        let path = self.output_dir.join(&filename);
+
+        let file = File::create(&path)?;
+
+        Ok(VideoFileWriter {
+            file,
+            path,
+            chunk_count: 0,
+        })
+    }
+
+    /// Save recording metadata to JSON
+    pub fn save_metadata(&self, metadata: &RecordingMetadata) -> RecordingResult<()> {
+        write_metadata(&self.output_dir, metadata)
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> RecordingResult<()> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| RecordingError::IoError(std::io::Error::other(e)))?;
+
+    fs::write(path, json)?;
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> RecordingResult<T> {
+    let json = fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(|e| RecordingError::IoError(std::io::Error::other(e)))
+}
+
+/// Write `metadata.json` into a recording directory
+pub fn write_metadata(recording_dir: &Path, metadata: &RecordingMetadata) -> RecordingResult<()> {
+    write_json(&recording_dir.join(METADATA_FILENAME), metadata)
+}
+
+/// Read `metadata.json` from a recording directory
+///
+/// The paths in it are moved under `recording_dir`, which need not be where
+/// the session was recorded.
+pub fn load_metadata(recording_dir: &Path) -> RecordingResult<RecordingMetadata> {
+    let mut metadata: RecordingMetadata = read_json(&recording_dir.join(METADATA_FILENAME))?;
+    metadata.relocate(recording_dir);
+    Ok(metadata)
+}
+
+/// Write `activity.json` next to the metadata
+pub fn write_activity(recording_dir: &Path, activity: &ActivityTimeline) -> RecordingResult<()> {
+    write_json(&recording_dir.join(ACTIVITY_FILENAME), activity)
+}
+
+/// Read `activity.json`, None if the recording has not been analyzed yet
+pub fn load_activity(recording_dir: &Path) -> RecordingResult<Option<ActivityTimeline>> {
+    let path = recording_dir.join(ACTIVITY_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    read_json(&path).map(Some)
+}
+
+/// Write `episode.json` next to the metadata
+pub fn write_episode(recording_dir: &Path, episode: &EpisodeMetadata) -> RecordingResult<()> {
+    write_json(&recording_dir.join(EPISODE_FILENAME), episode)
+}
+
+/// Read `episode.json`, None if the episode has not been described yet
+///
+/// The exported episode is always written into the recording directory and is
+/// looked up there, wherever the recording was moved.
+pub fn load_episode(recording_dir: &Path) -> RecordingResult<Option<EpisodeMetadata>> {
+    let path = recording_dir.join(EPISODE_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut episode: EpisodeMetadata = read_json(&path)?;
+    if let Some(export) = &mut episode.export {
+        if let Some(name) = export.file.file_name() {
+            export.file = recording_dir.join(name);
+        }
+    }
+    Ok(Some(episode))
+}
+
+/// Write `feed.json` into the recordings root
+pub fn write_feed_settings(recordings_root: &Path, settings: &FeedSettings) -> RecordingResult<()> {
+    write_json(&recordings_root.join(FEED_SETTINGS_FILENAME), settings)
+}
+
+/// Read `feed.json`, None if no feed was ever published
+pub fn load_feed_settings(recordings_root: &Path) -> RecordingResult<Option<FeedSettings>> {
+    let path = recordings_root.join(FEED_SETTINGS_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    read_json(&path).map(Some)
+}
+
+/// Write `library.json` into the recordings root
+pub fn write_library_index(recordings_root: &Path, index: &LibraryIndex) -> RecordingResult<()> {
+    write_json(&recordings_root.join(LIBRARY_INDEX_FILENAME), index)
+}
+
+/// Read `library.json`, empty if the library was never indexed
+pub fn load_library_index(recordings_root: &Path) -> RecordingResult<LibraryIndex> {
+    let path = recordings_root.join(LIBRARY_INDEX_FILENAME);
+    if !path.exists() {
+        return Ok(LibraryIndex::default());
+    }
+    read_json(&path)
+}
+
+/// Path of a file derived from a recorded track, next to it
+///
+/// `p1-John-audio.webm` with suffix `normalized` and extension `wav` becomes
+/// `p1-John-normalized.wav`.
+pub fn derived_file_path(source: &Path, suffix: &str, extension: &str) -> PathBuf {
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let base = stem
+        .strip_suffix("-audio")
+        .or_else(|| stem.strip_suffix("-video"))
+        .unwrap_or(&stem);
+
+    source.with_file_name(format!("{}-{}.{}", base, suffix, extension))
+}
+
+/// Helper to sanitize filenames
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Writer for audio files (WebM format with Opus codec)
+pub struct AudioFileWriter {
+    file: File,
+    path: PathBuf,
+    chunk_count: u64,
+}
+
+impl AudioFileWriter {
+    /// Write WebM audio chunk from MediaRecorder
+    pub fn write_chunk(&mut self, chunk: &[u8]) -> RecordingResult<()> {
+        // Write WebM chunks directly as they come from the browser
+        self.file.write_all(chunk)?;
+        self.chunk_count += 1;
+        Ok(())
+    }
+
+    pub fn finalize(self) -> RecordingResult<PathBuf> {
+        // File is automatically closed when dropped
+        Ok(self.path)
+    }
+}
+
+/// Writer for video files (WebM format)
+pub struct VideoFileWriter {
+    file: File,
+    path: PathBuf,
+    chunk_count: u64,
+}
+
+impl VideoFileWriter {
+    /// Write WebM video chunk from MediaRecorder
+    pub fn write_chunk(&mut self, chunk_data: &[u8]) -> RecordingResult<()> {
+        // Write WebM chunks directly as they come from the browser
+        // MediaRecorder already produces valid WebM segments
+        self.file.write_all(chunk_data)?;
+        self.chunk_count += 1;
+        Ok(())
+    }
+
+    pub fn finalize(self) -> RecordingResult<PathBuf> {
+        // File is automatically closed when dropped
+        Ok(self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(sanitize_filename("John Doe"), "John_Doe");
+        assert_eq!(sanitize_filename("user@example.com"), "user_example_com");
+        assert_eq!(sanitize_filename("test-user_123"), "test-user_123");
+    }
+
+    #[test]
+    fn test_derived_file_path() {
+        assert_eq!(
+            derived_file_path(Path::new("/rec/p1-John-audio.webm"), "normalized", "wav"),
+            PathBuf::from("/rec/p1-John-normalized.wav")
+        );
+        assert_eq!(
+            derived_file_path(Path::new("/rec/p1-John-video.webm"), "av", "mkv"),
+            PathBuf::from("/rec/p1-John-av.mkv")
+        );
+    }
+}
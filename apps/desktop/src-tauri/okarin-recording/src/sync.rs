@@ -0,0 +1,327 @@
+use super::correlation::downmix_decimate;
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::dsp::linear_to_db;
+use super::types::*;
+use super::wav::WavFileReader;
+use chrono::Utc;
+use std::collections::VecDeque;
+use std::path::Path;
+
+/// Transients are located to the millisecond
+const TRANSIENT_HOP_SECONDS: f64 = 0.001;
+/// Window the level jump is measured against
+const TRANSIENT_BACKGROUND_SECONDS: f64 = 0.1;
+/// Jump over the background needed to call it a clap
+const MIN_TRANSIENT_RISE_DB: f64 = 20.0;
+const MIN_TRANSIENT_LEVEL_DB: f64 = -30.0;
+/// Minimum spacing between two transients
+const TRANSIENT_REFRACTORY_SECONDS: f64 = 0.2;
+/// Strongest transients of each file considered for the match
+const MAX_SYNC_CANDIDATES: usize = 20;
+/// Two transients this close once shifted are the same event
+const SYNC_MATCH_TOLERANCE_SECONDS: f64 = 0.01;
+/// Any single pair of transients lines up with some shift, so one match
+/// proves nothing
+const MIN_SYNC_MATCHES: usize = 2;
+const SYNC_BLOCK_FRAMES: usize = 4800;
+
+/// A sharp level jump, typically a clap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transient {
+    pub time_seconds: f64,
+    /// Jump over the preceding background
+    pub rise_db: f64,
+}
+
+/// Finds sharp transients in a mono stream of any sample rate
+pub struct TransientDetector {
+    hop: usize,
+    sample_rate: f64,
+    background_hops: usize,
+    pending: Vec<f32>,
+    hop_index: u64,
+    /// Mean square of the previous hops
+    background: VecDeque<f64>,
+    background_sum: f64,
+    current: Option<Transient>,
+    last_start: f64,
+    transients: Vec<Transient>,
+}
+
+impl TransientDetector {
+    pub fn new(sample_rate: u32) -> Self {
+        let hop = ((sample_rate as f64 * TRANSIENT_HOP_SECONDS).round() as usize).max(1);
+        Self {
+            hop,
+            sample_rate: sample_rate as f64,
+            background_hops: (TRANSIENT_BACKGROUND_SECONDS / TRANSIENT_HOP_SECONDS) as usize,
+            pending: Vec::new(),
+            hop_index: 0,
+            background: VecDeque::new(),
+            background_sum: 0.0,
+            current: None,
+            last_start: f64::NEG_INFINITY,
+            transients: Vec::new(),
+        }
+    }
+
+    /// Feed mono samples
+    pub fn push(&mut self, samples: &[f32]) {
+        self.pending.extend_from_slice(samples);
+
+        let mut start = 0;
+        while self.pending.len() - start >= self.hop {
+            let energy = self.pending[start..start + self.hop]
+                .iter()
+                .map(|&s| (s as f64) * (s as f64))
+                .sum::<f64>()
+                / self.hop as f64;
+            self.process_hop(energy);
+            start += self.hop;
+        }
+        self.pending.drain(..start);
+    }
+
+    fn process_hop(&mut self, energy: f64) {
+        let time_seconds = (self.hop_index * self.hop as u64) as f64 / self.sample_rate;
+        self.hop_index += 1;
+
+        if !self.background.is_empty() {
+            let background = self.background_sum / self.background.len() as f64;
+            let level_db = linear_to_db(energy.sqrt().max(1e-10));
+            let rise_db = level_db - linear_to_db(background.sqrt().max(1e-10));
+            let onset = level_db >= MIN_TRANSIENT_LEVEL_DB && rise_db >= MIN_TRANSIENT_RISE_DB;
+
+            if onset {
+                match &mut self.current {
+                    Some(transient) => transient.rise_db = transient.rise_db.max(rise_db),
+                    None if time_seconds - self.last_start >= TRANSIENT_REFRACTORY_SECONDS => {
+                        self.current = Some(Transient {
+                            time_seconds,
+                            rise_db,
+                        });
+                        self.last_start = time_seconds;
+                    }
+                    None => {}
+                }
+            } else if let Some(transient) = self.current.take() {
+                self.transients.push(transient);
+            }
+        }
+
+        self.background.push_back(energy);
+        self.background_sum += energy;
+        if self.background.len() > self.background_hops {
+            self.background_sum -= self.background.pop_front().unwrap_or(0.0);
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Transient> {
+        self.transients.extend(self.current.take());
+        self.transients
+    }
+}
+
+/// Offset found between two sets of transients
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncMatch {
+    /// Add to an external time to get the reference time
+    pub offset_seconds: f64,
+    /// Reference times of the transients found in both
+    pub sync_points: Vec<f64>,
+}
+
+fn strongest(transients: &[Transient]) -> Vec<Transient> {
+    let mut sorted = transients.to_vec();
+    sorted.sort_by(|a, b| b.rise_db.total_cmp(&a.rise_db));
+    sorted.truncate(MAX_SYNC_CANDIDATES);
+    sorted
+}
+
+/// Find the shift that lines up the most transients of both files
+///
+/// Every reference/external pair is tried as an anchor; the shift matching
+/// the most transients wins, the strongest ones breaking ties. None unless at
+/// least two transients line up.
+pub fn match_transients(reference: &[Transient], external: &[Transient]) -> Option<SyncMatch> {
+    let reference = strongest(reference);
+    let external = strongest(external);
+    let mut best: Option<(f64, Vec<(f64, f64)>)> = None;
+
+    for anchor in &reference {
+        for candidate in &external {
+            let shift = anchor.time_seconds - candidate.time_seconds;
+            let mut matched = Vec::new();
+            let mut strength = 0.0;
+
+            for r in &reference {
+                let found = external.iter().find(|e| {
+                    (e.time_seconds + shift - r.time_seconds).abs() <= SYNC_MATCH_TOLERANCE_SECONDS
+                });
+                if let Some(e) = found {
+                    matched.push((r.time_seconds, r.time_seconds - e.time_seconds));
+                    strength += r.rise_db + e.rise_db;
+                }
+            }
+
+            let better = best.as_ref().is_none_or(|(best_strength, best_matched)| {
+                let count = best_matched.len();
+                matched.len() > count || (matched.len() == count && strength > *best_strength)
+            });
+            if better {
+                best = Some((strength, matched));
+            }
+        }
+    }
+
+    let (_, mut matched) = best.filter(|(_, matched)| matched.len() >= MIN_SYNC_MATCHES)?;
+    matched.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Some(SyncMatch {
+        offset_seconds: matched.iter().map(|(_, d)| d).sum::<f64>() / matched.len() as f64,
+        sync_points: matched.into_iter().map(|(t, _)| t).collect(),
+    })
+}
+
+/// Transients of a participant's track, on the session timeline
+pub fn track_transients(
+    metadata: &RecordingMetadata,
+    participant: &ParticipantMetadata,
+) -> RecordingResult<Vec<Transient>> {
+    let path = participant.audio_file.as_ref().ok_or_else(|| {
+        RecordingError::InvalidConfig(format!("participant {} has no audio track", participant.id))
+    })?;
+    let decoder = OpusTrackDecoder::open(path)?;
+    let channels = decoder.channels();
+    let mut reader = TimelineReader::new(decoder, metadata.participant_offset_seconds(participant));
+    let mut detector = TransientDetector::new(OPUS_SAMPLE_RATE);
+
+    while let Some(block) = reader.read(SYNC_BLOCK_FRAMES)? {
+        detector.push(&downmix_decimate(&block, channels, 1));
+    }
+    Ok(detector.finish())
+}
+
+/// Transients of an external WAV file, relative to its first sample
+pub fn wav_transients(path: &Path) -> RecordingResult<Vec<Transient>> {
+    let mut reader = WavFileReader::open(path)?;
+    let mut detector = TransientDetector::new(reader.sample_rate());
+
+    while let Some(block) = reader.read(SYNC_BLOCK_FRAMES)? {
+        detector.push(&downmix_decimate(&block, reader.channels(), 1));
+    }
+    Ok(detector.finish())
+}
+
+/// Line an external recording up with a participant's track using claps
+pub fn sync_external_file(
+    metadata: &RecordingMetadata,
+    participant_id: &str,
+    external_file: &Path,
+) -> RecordingResult<ExternalAlignment> {
+    let participant = metadata
+        .participants
+        .get(participant_id)
+        .ok_or_else(|| RecordingError::ParticipantNotFound(participant_id.to_string()))?;
+
+    log::info!(
+        "Looking for claps shared by {:?} and participant {}",
+        external_file,
+        participant_id
+    );
+    let reference = track_transients(metadata, participant)?;
+    let external = wav_transients(external_file)?;
+
+    let found = match_transients(&reference, &external).ok_or_else(|| {
+        RecordingError::InvalidMedia(format!(
+            "no two claps found in both {:?} and the track of participant {}",
+            external_file, participant_id
+        ))
+    })?;
+
+    Ok(ExternalAlignment {
+        participant_id: participant_id.to_string(),
+        source_file: external_file.to_path_buf(),
+        method: SyncMethod::Clap,
+        offset_seconds: found.offset_seconds,
+        drift_ppm: 0.0,
+        sync_points: found.sync_points,
+        aligned_file: None,
+        aligned_at: Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Quiet noise floor with decaying noise bursts at the given times
+    fn claps(sample_rate: u32, seconds: f64, at: &[f64]) -> Vec<f32> {
+        let rate = sample_rate as f64;
+        let mut state = sample_rate;
+        let mut samples: Vec<f32> = (0..(seconds * rate) as usize)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect();
+        let noise = samples.clone();
+        samples.iter_mut().for_each(|s| *s *= 0.002);
+
+        for &time in at {
+            let start = (time * rate) as usize;
+            for n in 0..(0.03 * rate) as usize {
+                let decay = (-(n as f64) / (0.005 * rate)).exp() as f32;
+                samples[start + n] += 1.6 * noise[n] * decay;
+            }
+        }
+        samples
+    }
+
+    fn detect(sample_rate: u32, samples: &[f32]) -> Vec<Transient> {
+        let mut detector = TransientDetector::new(sample_rate);
+        for block in samples.chunks(1000) {
+            detector.push(block);
+        }
+        detector.finish()
+    }
+
+    #[test]
+    fn test_detects_claps() {
+        let transients = detect(48000, &claps(48000, 3.0, &[0.5, 2.0]));
+        assert_eq!(transients.len(), 2);
+        assert!((transients[0].time_seconds - 0.5).abs() < 0.002);
+        assert!((transients[1].time_seconds - 2.0).abs() < 0.002);
+    }
+
+    #[test]
+    fn test_matches_claps_across_sample_rates() {
+        // External recorder started 0.75s before the session and caught
+        // an extra clap after it ended
+        let reference = detect(48000, &claps(48000, 6.0, &[1.0, 3.5, 4.2]));
+        let external = detect(44100, &claps(44100, 8.0, &[1.75, 4.25, 4.95, 7.0]));
+
+        let found = match_transients(&reference, &external).unwrap();
+        assert!(
+            (found.offset_seconds - -0.75).abs() < 0.002,
+            "offset: {}",
+            found.offset_seconds
+        );
+        assert_eq!(found.sync_points.len(), 3);
+        assert!((found.sync_points[0] - 1.0).abs() < 0.002);
+
+        assert_eq!(match_transients(&reference, &[]), None);
+    }
+
+    #[test]
+    fn test_single_or_unrelated_claps_do_not_match() {
+        // One transient on each side always lines up with some shift
+        let reference = detect(48000, &claps(48000, 4.0, &[1.0]));
+        let external = detect(48000, &claps(48000, 4.0, &[2.5]));
+        assert_eq!(match_transients(&reference, &external), None);
+
+        // Two claps each, but never the same spacing
+        let reference = detect(48000, &claps(48000, 4.0, &[1.0, 2.0]));
+        let external = detect(48000, &claps(48000, 4.0, &[0.5, 3.0]));
+        assert_eq!(match_transients(&reference, &external), None);
+    }
+}
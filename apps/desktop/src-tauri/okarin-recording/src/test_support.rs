@@ -0,0 +1,94 @@
+//! Fixtures and signal generators shared by the unit tests
+
+use super::types::*;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 2 seconds of a 440Hz sine, mono Opus in WebM
+pub fn fixture() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sine-440hz-mono.webm")
+}
+
+/// A new empty directory under the system temp dir
+///
+/// Unique per process and call, so parallel tests and concurrent test runs
+/// never share files.
+pub fn temp_dir(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "okarin-{}-{}-{}",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Mono sine at 48kHz
+pub fn sine(frequency: f64, amplitude: f64, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|n| (amplitude * (2.0 * PI * frequency * n as f64 / 48000.0).sin()) as f32)
+        .collect()
+}
+
+/// Deterministic white noise in [-0.5, 0.5)
+pub fn noise(len: usize, seed: u32) -> Vec<f32> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+        })
+        .collect()
+}
+
+/// Copy a mono signal to every channel of an interleaved one
+pub fn interleave(mono: &[f32], channels: usize) -> Vec<f32> {
+    mono.iter()
+        .flat_map(|&s| std::iter::repeat(s).take(channels))
+        .collect()
+}
+
+/// A finished recording of `duration_seconds`, no pauses, participants or markers
+pub fn recording(started_at: DateTime<Utc>, duration_seconds: u64) -> RecordingMetadata {
+    RecordingMetadata {
+        stopped_at: Some(started_at + Duration::seconds(duration_seconds as i64)),
+        duration_seconds,
+        ..RecordingMetadata::new(
+            "test".to_string(),
+            "room".to_string(),
+            started_at,
+            PathBuf::new(),
+        )
+    }
+}
+
+pub fn participant(id: &str, name: &str, joined_at: DateTime<Utc>) -> ParticipantMetadata {
+    ParticipantMetadata::new(id.to_string(), name.to_string(), joined_at)
+}
+
+/// Participants keyed by their ID, as in `RecordingMetadata::participants`
+pub fn participants(
+    list: impl IntoIterator<Item = ParticipantMetadata>,
+) -> HashMap<String, ParticipantMetadata> {
+    list.into_iter().map(|p| (p.id.clone(), p)).collect()
+}
+
+/// A 2 second session with the fixture as its only participant's audio
+pub fn fixture_metadata(output_directory: PathBuf) -> RecordingMetadata {
+    let started_at = Utc::now();
+    RecordingMetadata {
+        participants: participants([ParticipantMetadata {
+            audio_file: Some(fixture()),
+            ..participant("p1", "Guest", started_at)
+        }]),
+        output_directory,
+        ..recording(started_at, 2)
+    }
+}
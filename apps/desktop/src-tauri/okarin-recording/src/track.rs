@@ -1,5 +1,8 @@
+use super::format::LiveFormatCheck;
+use super::meter::{LiveAudioMeter, MeterEvent, MeterListener};
 use super::storage::{AudioFileWriter, VideoFileWriter};
 use super::types::*;
+use chrono::{DateTime, Utc};
 use crossbeam::channel::{bounded, Receiver, Sender};
 use parking_lot::Mutex;
 use std::path::PathBuf;
@@ -33,15 +36,91 @@ pub struct TrackStats {
     pub audio_bytes_written: u64,
     pub video_bytes_written: u64,
     pub errors: Vec<String>,
+    /// Arrival of the first chunk, MediaRecorder timestamps start there
+    pub first_audio_chunk_at: Option<DateTime<Utc>>,
+    pub first_video_chunk_at: Option<DateTime<Utc>>,
+    /// Format found in the incoming audio and video
+    pub format: MediaFormat,
+    pub format_mismatches: Vec<FormatMismatch>,
+    /// Set when chunks are refused because of a format mismatch
+    pub rejected: Option<String>,
+}
+
+/// Compares the head of an incoming stream with the `RecordingConfig`
+struct FormatGuard {
+    participant_id: String,
+    check: LiveFormatCheck,
+    reject: bool,
+    listener: Option<MeterListener>,
+}
+
+impl FormatGuard {
+    fn new(
+        participant_id: String,
+        config: &RecordingConfig,
+        listener: Option<MeterListener>,
+    ) -> Self {
+        Self {
+            participant_id,
+            check: LiveFormatCheck::new(config.media_format()),
+            reject: config.reject_format_mismatch,
+            listener,
+        }
+    }
+
+    /// Check a chunk, returning false once the stream must be refused
+    fn accept(&mut self, chunk: &[u8], stats: &Mutex<TrackStats>) -> bool {
+        let mismatches = self.check.push_chunk(chunk);
+        let mut stats = stats.lock();
+        if stats.rejected.is_some() {
+            return false;
+        }
+
+        let found = self.check.format();
+        let format = &mut stats.format;
+        format.audio_sample_rate = found.audio_sample_rate.or(format.audio_sample_rate);
+        format.audio_channels = found.audio_channels.or(format.audio_channels);
+        format.video_width = found.video_width.or(format.video_width);
+        format.video_height = found.video_height.or(format.video_height);
+        format.video_fps = found.video_fps.or(format.video_fps);
+
+        for mismatch in mismatches {
+            log::warn!(
+                "Participant {} sends {} {} instead of the configured {}",
+                self.participant_id,
+                mismatch.field,
+                mismatch.actual,
+                mismatch.configured
+            );
+            if let Some(listener) = &self.listener {
+                listener(MeterEvent::Warning(TrackWarning::FormatMismatch {
+                    participant_id: self.participant_id.clone(),
+                    field: mismatch.field.clone(),
+                    configured: mismatch.configured,
+                    actual: mismatch.actual,
+                }));
+            }
+            if self.reject && stats.rejected.is_none() {
+                stats.rejected = Some(format!(
+                    "{} is {} but the recording is configured for {}",
+                    mismatch.field, mismatch.actual, mismatch.configured
+                ));
+            }
+            stats.format_mismatches.push(mismatch);
+        }
+
+        stats.rejected.is_none()
+    }
 }
 
 impl TrackRecorder {
     pub fn new(
         participant_id: String,
         _participant_name: String,
-        _config: &RecordingConfig,
+        config: &RecordingConfig,
         mut audio_writer: Option<AudioFileWriter>,
         mut video_writer: Option<VideoFileWriter>,
+        meter_listener: Option<MeterListener>,
     ) -> RecordingResult<Self> {
         let stats = Arc::new(Mutex::new(TrackStats::default()));
 
@@ -50,9 +129,24 @@ impl TrackRecorder {
             let (sender, receiver) = bounded::<TrackMessage>(CHANNEL_BUFFER_SIZE);
             let participant_id_clone = participant_id.clone();
             let stats_clone = Arc::clone(&stats);
+            let guard = FormatGuard::new(participant_id.clone(), config, meter_listener.clone());
+            let meter = meter_listener.clone().map(|listener| {
+                LiveAudioMeter::new(
+                    participant_id.clone(),
+                    config.silence_warning_seconds,
+                    listener,
+                )
+            });
 
             let handle = thread::spawn(move || {
-                Self::audio_recording_loop(participant_id_clone, receiver, writer, stats_clone)
+                Self::audio_recording_loop(
+                    participant_id_clone,
+                    receiver,
+                    writer,
+                    guard,
+                    meter,
+                    stats_clone,
+                )
             });
 
             (Some(sender), Some(handle))
@@ -65,9 +159,16 @@ impl TrackRecorder {
             let (sender, receiver) = bounded::<TrackMessage>(CHANNEL_BUFFER_SIZE);
             let participant_id_clone = participant_id.clone();
             let stats_clone = Arc::clone(&stats);
+            let guard = FormatGuard::new(participant_id.clone(), config, meter_listener);
 
             let handle = thread::spawn(move || {
-                Self::video_recording_loop(participant_id_clone, receiver, writer, stats_clone)
+                Self::video_recording_loop(
+                    participant_id_clone,
+                    receiver,
+                    writer,
+                    guard,
+                    stats_clone,
+                )
             });
 
             (Some(sender), Some(handle))
@@ -86,27 +187,39 @@ impl TrackRecorder {
     }
 
     /// Send audio chunk to the recording thread
+    ///
+    /// Fails once the stream was refused for not matching the configuration.
     pub fn add_audio_chunk(&self, chunk: Vec<u8>) -> RecordingResult<()> {
         if let Some(sender) = &self.audio_sender {
+            if let Some(reason) = &self.stats.lock().rejected {
+                return Err(RecordingError::InvalidMedia(reason.clone()));
+            }
             sender
                 .send(TrackMessage::AudioChunk(chunk))
                 .map_err(|_| RecordingError::TrackError("Failed to send audio chunk".to_string()))?;
 
             let mut stats = self.stats.lock();
             stats.audio_chunks_received += 1;
+            stats.first_audio_chunk_at.get_or_insert_with(Utc::now);
         }
         Ok(())
     }
 
     /// Send video chunk to the recording thread
+    ///
+    /// Fails once the stream was refused for not matching the configuration.
     pub fn add_video_chunk(&self, chunk: Vec<u8>) -> RecordingResult<()> {
         if let Some(sender) = &self.video_sender {
+            if let Some(reason) = &self.stats.lock().rejected {
+                return Err(RecordingError::InvalidMedia(reason.clone()));
+            }
             sender
                 .send(TrackMessage::VideoChunk(chunk))
                 .map_err(|_| RecordingError::TrackError("Failed to send video chunk".to_string()))?;
 
             let mut stats = self.stats.lock();
             stats.video_chunks_received += 1;
+            stats.first_video_chunk_at.get_or_insert_with(Utc::now);
         }
         Ok(())
     }
@@ -161,6 +274,8 @@ impl TrackRecorder {
         participant_id: String,
         receiver: Receiver<TrackMessage>,
         mut writer: AudioFileWriter,
+        mut guard: FormatGuard,
+        mut meter: Option<LiveAudioMeter>,
         stats: Arc<Mutex<TrackStats>>,
     ) -> RecordingResult<PathBuf> {
         log::info!(
@@ -171,6 +286,10 @@ impl TrackRecorder {
         loop {
             match receiver.recv() {
                 Ok(TrackMessage::AudioChunk(chunk)) => {
+                    if !guard.accept(&chunk, &stats) {
+                        continue;
+                    }
+
                     // Write WebM chunks directly (already encoded by browser)
                     let chunk_len = chunk.len() as u64;
                     if let Err(e) = writer.write_chunk(&chunk) {
@@ -181,6 +300,11 @@ impl TrackRecorder {
                         let mut stats = stats.lock();
                         stats.audio_bytes_written += chunk_len;
                     }
+
+                    // Decode on the fly to report input levels to the frontend
+                    if let Some(meter) = &mut meter {
+                        meter.push_chunk(&chunk);
+                    }
                 }
                 Ok(TrackMessage::Stop) | Err(_) => {
                     log::info!(
@@ -201,6 +325,7 @@ impl TrackRecorder {
         participant_id: String,
         receiver: Receiver<TrackMessage>,
         mut writer: VideoFileWriter,
+        mut guard: FormatGuard,
         stats: Arc<Mutex<TrackStats>>,
     ) -> RecordingResult<PathBuf> {
         log::info!(
@@ -211,6 +336,10 @@ impl TrackRecorder {
         loop {
             match receiver.recv() {
                 Ok(TrackMessage::VideoChunk(chunk)) => {
+                    if !guard.accept(&chunk, &stats) {
+                        continue;
+                    }
+
                     // Write WebM chunks directly (already encoded by browser)
                     let chunk_len = chunk.len() as u64;
                     if let Err(e) = writer.write_chunk(&chunk) {
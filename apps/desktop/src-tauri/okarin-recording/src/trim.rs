@@ -0,0 +1,173 @@
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::export::FormatWavWriter;
+use super::storage::derived_file_path;
+use super::types::*;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+/// Room left before the first and after the last detected speech
+pub const DEFAULT_TRIM_PADDING_SECONDS: f64 = 0.5;
+const TRIM_BLOCK_FRAMES: usize = 4800;
+
+/// Session in/out points: first to last speech of anyone, padded
+pub fn trim_points(timeline: &ActivityTimeline, padding_seconds: f64) -> Option<TimeRange> {
+    let speech = timeline.participants.values().flat_map(|p| p.speech.iter());
+    let first = speech.clone().map(|r| r.start_seconds).reduce(f64::min)?;
+    let last = speech.map(|r| r.end_seconds).reduce(f64::max)?;
+
+    Some(TimeRange {
+        start_seconds: (first - padding_seconds).max(0.0),
+        end_seconds: (last + padding_seconds).min(timeline.duration_seconds.max(last)),
+    })
+}
+
+/// Copy `range` of a track placed on the session timeline into a WAV file
+///
+/// Every track gets exactly the same number of frames, padded with silence
+/// where the participant had already left.
+fn write_range<R: Read>(
+    reader: &mut TimelineReader<R>,
+    range: &TimeRange,
+    writer: &mut FormatWavWriter,
+) -> RecordingResult<()> {
+    let channels = reader.channels().max(1) as usize;
+    let start = (range.start_seconds * OPUS_SAMPLE_RATE as f64).round() as usize;
+    let end = (range.end_seconds * OPUS_SAMPLE_RATE as f64).round() as usize;
+
+    let mut skipped = 0;
+    while skipped < start {
+        match reader.read(TRIM_BLOCK_FRAMES.min(start - skipped))? {
+            Some(block) => skipped += block.len() / channels,
+            None => break,
+        }
+    }
+
+    let mut remaining = end.saturating_sub(start);
+    while remaining > 0 {
+        let wanted = TRIM_BLOCK_FRAMES.min(remaining);
+        let mut block = reader.read(wanted)?.unwrap_or_default();
+        block.resize(wanted * channels, 0.0);
+        writer.write(&block)?;
+        remaining -= wanted;
+    }
+
+    Ok(())
+}
+
+/// Export every participant trimmed to the same in/out points
+pub fn trim_recording(
+    metadata: &RecordingMetadata,
+    timeline: &ActivityTimeline,
+    padding_seconds: f64,
+) -> RecordingResult<TrimExport> {
+    let range = trim_points(timeline, padding_seconds).ok_or_else(|| {
+        RecordingError::InvalidConfig("no speech detected, nothing to trim to".to_string())
+    })?;
+
+    let target = metadata.export_format();
+    let mut tracks = HashMap::new();
+    for (participant_id, participant) in &metadata.participants {
+        let source = match &participant.audio_file {
+            Some(path) => path,
+            None => continue,
+        };
+        log::info!(
+            "Trimming audio for participant {} to {:.2}s - {:.2}s",
+            participant_id,
+            range.start_seconds,
+            range.end_seconds
+        );
+
+        let output = derived_file_path(source, "trimmed", "wav");
+        trim_file(
+            source,
+            metadata.participant_offset_seconds(participant),
+            &range,
+            &target,
+            &output,
+        )?;
+        tracks.insert(participant_id.clone(), output);
+    }
+
+    Ok(TrimExport {
+        exported_at: Utc::now(),
+        in_seconds: range.start_seconds,
+        out_seconds: range.end_seconds,
+        padding_seconds,
+        tracks,
+    })
+}
+
+/// Write `range` of the session timeline of one track in the `target` format
+pub fn trim_file(
+    source: &Path,
+    offset_seconds: f64,
+    range: &TimeRange,
+    target: &MediaFormat,
+    output: &Path,
+) -> RecordingResult<()> {
+    let decoder = OpusTrackDecoder::open(source)?;
+    let mut writer = FormatWavWriter::create(output, decoder.channels(), target)?;
+    let mut reader = TimelineReader::new(decoder, offset_seconds);
+    write_range(&mut reader, range, &mut writer)?;
+    writer.finalize()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixture, temp_dir};
+
+    #[test]
+    fn test_trim_points_span_all_participants() {
+        let range = |start_seconds, end_seconds| TimeRange {
+            start_seconds,
+            end_seconds,
+        };
+        let participant = |speech: Vec<TimeRange>| ParticipantActivity {
+            speech,
+            talk_seconds: 0.0,
+            talk_time_percent: 0.0,
+            threshold_db: -40.0,
+        };
+        let mut timeline = ActivityTimeline {
+            analyzed_at: Utc::now(),
+            duration_seconds: 300.0,
+            participants: HashMap::from([
+                ("p1".to_string(), participant(vec![range(62.0, 70.0)])),
+                ("p2".to_string(), participant(vec![range(65.0, 240.0)])),
+                ("p3".to_string(), participant(Vec::new())),
+            ]),
+        };
+
+        assert_eq!(trim_points(&timeline, 0.5), Some(range(61.5, 240.5)));
+        assert_eq!(trim_points(&timeline, 100.0), Some(range(0.0, 300.0)));
+
+        timeline.participants.clear();
+        assert_eq!(trim_points(&timeline, 0.5), None);
+    }
+
+    #[test]
+    fn test_trim_file_keeps_tracks_aligned() {
+        let source = fixture();
+        let range = TimeRange {
+            start_seconds: 0.5,
+            end_seconds: 3.0,
+        };
+
+        // Same range, track joined 1s late: output lengths must match
+        let dir = temp_dir("trim");
+        let mut lengths = Vec::new();
+        for (index, offset) in [0.0, 1.0].into_iter().enumerate() {
+            let output = dir.join(format!("trim-{}.wav", index));
+            trim_file(&source, offset, &range, &MediaFormat::default(), &output).unwrap();
+            let reader = hound::WavReader::open(&output).unwrap();
+            lengths.push(reader.duration());
+            std::fs::remove_file(&output).ok();
+        }
+        assert_eq!(lengths, vec![120000, 120000]);
+    }
+}
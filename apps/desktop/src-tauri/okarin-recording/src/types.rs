@@ -0,0 +1,873 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingConfig {
+    pub room_id: String,
+    pub output_dir: PathBuf,
+    pub audio_sample_rate: u32,
+    pub audio_channels: u16,
+    pub video_width: u32,
+    pub video_height: u32,
+    pub video_fps: u32,
+    /// Seconds of flat-line input before a participant is reported as muted
+    pub silence_warning_seconds: u32,
+    /// Refuse a participant's chunks once their stream contradicts the
+    /// values above, instead of only warning
+    pub reject_format_mismatch: bool,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            room_id: String::new(),
+            output_dir: PathBuf::new(),
+            audio_sample_rate: 48000, // 48kHz for best quality
+            audio_channels: 2,         // Stereo
+            video_width: 1920,
+            video_height: 1080,
+            video_fps: 30,
+            silence_warning_seconds: 10,
+            reject_format_mismatch: false,
+        }
+    }
+}
+
+impl RecordingConfig {
+    /// Stream parameters every participant's tracks are expected to have
+    pub fn media_format(&self) -> MediaFormat {
+        MediaFormat {
+            audio_sample_rate: Some(self.audio_sample_rate),
+            audio_channels: Some(self.audio_channels),
+            video_width: Some(self.video_width),
+            video_height: Some(self.video_height),
+            video_fps: Some(self.video_fps as f64),
+        }
+    }
+}
+
+/// Stream parameters, as configured or as found in a recorded track
+///
+/// Fields are None when unknown, e.g. the video ones of an audio-only track.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MediaFormat {
+    pub audio_sample_rate: Option<u32>,
+    pub audio_channels: Option<u16>,
+    pub video_width: Option<u32>,
+    pub video_height: Option<u32>,
+    pub video_fps: Option<f64>,
+}
+
+/// A stream parameter that differs from the recording configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormatMismatch {
+    /// `RecordingConfig` field name, e.g. `audio_channels`
+    pub field: String,
+    pub configured: f64,
+    pub actual: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingMetadata {
+    pub id: String,
+    pub room_id: String,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub duration_seconds: u64,
+    pub participants: HashMap<String, ParticipantMetadata>,
+    pub output_directory: PathBuf,
+    #[serde(default)]
+    pub loudness: Option<LoudnessReport>,
+    #[serde(default)]
+    pub normalization: Option<NormalizationExport>,
+    #[serde(default)]
+    pub bleed: Option<BleedReport>,
+    /// Rough mix of every participant, once exported
+    #[serde(default)]
+    pub mix_file: Option<PathBuf>,
+    #[serde(default)]
+    pub trim: Option<TrimExport>,
+    #[serde(default)]
+    pub cleanup: Option<CleanupExport>,
+    /// Backup recordings lined up with the session
+    #[serde(default)]
+    pub external_files: Vec<ExternalAlignment>,
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub pauses: Vec<Pause>,
+    /// Format requested by the `RecordingConfig`, None for older recordings
+    #[serde(default)]
+    pub configured_format: Option<MediaFormat>,
+}
+
+impl RecordingMetadata {
+    /// Metadata of a session that just started, nothing analyzed or exported yet
+    pub fn new(
+        id: String,
+        room_id: String,
+        started_at: DateTime<Utc>,
+        output_directory: PathBuf,
+    ) -> Self {
+        Self {
+            id,
+            room_id,
+            started_at,
+            stopped_at: None,
+            duration_seconds: 0,
+            participants: HashMap::new(),
+            output_directory,
+            loudness: None,
+            normalization: None,
+            bleed: None,
+            mix_file: None,
+            trim: None,
+            cleanup: None,
+            external_files: Vec::new(),
+            markers: Vec::new(),
+            pauses: Vec::new(),
+            configured_format: None,
+        }
+    }
+
+    /// Position of a participant's tracks on the session timeline
+    pub fn participant_offset_seconds(&self, participant: &ParticipantMetadata) -> f64 {
+        self.session_position_seconds(participant.joined_at)
+    }
+
+    /// Time from a participant joining until they left or the session ended
+    pub fn participant_length_seconds(&self, participant: &ParticipantMetadata) -> f64 {
+        let end = participant
+            .left_at
+            .or(self.stopped_at)
+            .map_or(self.duration_seconds as f64, |left_at| {
+                self.session_position_seconds(left_at)
+            });
+        (end - self.participant_offset_seconds(participant)).max(0.0)
+    }
+
+    /// Position of a wall-clock instant on the session timeline, pauses excluded
+    ///
+    /// Nothing is recorded while the session is paused, so markers, pauses
+    /// and track offsets all leave paused time out.
+    pub fn session_position_seconds(&self, at: DateTime<Utc>) -> f64 {
+        let paused: i64 = self
+            .pauses
+            .iter()
+            .filter(|pause| pause.paused_at < at)
+            .map(|pause| {
+                let end = pause.resumed_at.map_or(at, |resumed| resumed.min(at));
+                (end - pause.paused_at).num_milliseconds()
+            })
+            .sum();
+        let elapsed = (at - self.started_at).num_milliseconds() - paused;
+        (elapsed as f64 / 1000.0).max(0.0)
+    }
+
+    /// Length of the session timeline, pauses excluded
+    pub fn session_length_seconds(&self) -> f64 {
+        match self.stopped_at {
+            Some(stopped_at) => self.session_position_seconds(stopped_at),
+            None => self.duration_seconds as f64,
+        }
+    }
+
+    /// Format exported files are written in, the recorded one where the
+    /// configuration left it open or for older recordings
+    pub fn export_format(&self) -> MediaFormat {
+        self.configured_format.clone().unwrap_or_default()
+    }
+
+    /// Point the files of the recording at the directory it now lives in
+    ///
+    /// Paths are saved absolute, as they were on the machine that recorded.
+    /// The ones under the old `output_directory` follow the recording when it
+    /// is moved or copied; files outside of it, like the sources of external
+    /// alignments, are left alone.
+    pub fn relocate(&mut self, directory: &Path) {
+        let old = std::mem::replace(&mut self.output_directory, directory.to_path_buf());
+        if old == directory {
+            return;
+        }
+        let rebase = |path: &mut PathBuf| {
+            if let Ok(relative) = path.strip_prefix(&old) {
+                *path = directory.join(relative);
+            }
+        };
+
+        self.mix_file.iter_mut().for_each(rebase);
+        for participant in self.participants.values_mut() {
+            participant
+                .audio_file
+                .iter_mut()
+                .chain(&mut participant.video_file)
+                .chain(&mut participant.waveform_files)
+                .for_each(rebase);
+        }
+        if let Some(normalization) = &mut self.normalization {
+            for track in normalization.tracks.values_mut() {
+                rebase(&mut track.output_file);
+            }
+        }
+        if let Some(cleanup) = &mut self.cleanup {
+            for track in cleanup.tracks.values_mut() {
+                rebase(&mut track.output_file);
+            }
+        }
+        if let Some(trim) = &mut self.trim {
+            trim.tracks.values_mut().for_each(rebase);
+        }
+        for alignment in &mut self.external_files {
+            rebase(&mut alignment.source_file);
+            alignment.aligned_file.iter_mut().for_each(rebase);
+        }
+    }
+
+    /// Record an alignment, replacing any previous one of the same file
+    pub fn set_external_alignment(&mut self, alignment: ExternalAlignment) {
+        self.external_files
+            .retain(|a| a.source_file != alignment.source_file);
+        self.external_files.push(alignment);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticipantMetadata {
+    pub id: String,
+    pub name: String,
+    pub audio_file: Option<PathBuf>,
+    pub video_file: Option<PathBuf>,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>,
+    /// Waveform `.dat` files, one per zoom level
+    #[serde(default)]
+    pub waveform_files: Vec<PathBuf>,
+    /// Format found in the participant's tracks while recording
+    #[serde(default)]
+    pub format: MediaFormat,
+    #[serde(default)]
+    pub format_mismatches: Vec<FormatMismatch>,
+    /// Start of the video track relative to the audio track, when both exist
+    #[serde(default)]
+    pub video_offset_seconds: Option<f64>,
+}
+
+impl ParticipantMetadata {
+    /// A participant who just joined, before any of their tracks is written
+    pub fn new(id: String, name: String, joined_at: DateTime<Utc>) -> Self {
+        Self {
+            id,
+            name,
+            audio_file: None,
+            video_file: None,
+            joined_at,
+            left_at: None,
+            waveform_files: Vec::new(),
+            format: MediaFormat::default(),
+            format_mismatches: Vec::new(),
+            video_offset_seconds: None,
+        }
+    }
+}
+
+/// EBU R128 measurements of one track or of the mix
+///
+/// Loudness values are None when the audio is entirely below the -70 LUFS
+/// absolute gate (or too short for a 3s short-term window).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessMeasurement {
+    pub integrated_lufs: Option<f64>,
+    pub loudness_range_lu: f64,
+    pub short_term_max_lufs: Option<f64>,
+    pub true_peak_dbtp: Option<f64>,
+    pub duration_seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoudnessReport {
+    pub analyzed_at: DateTime<Utc>,
+    /// Per-participant measurements, keyed by participant ID
+    pub tracks: HashMap<String, LoudnessMeasurement>,
+    pub mix: Option<LoudnessMeasurement>,
+}
+
+/// Targets for the loudness-normalized export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizationOptions {
+    pub target_lufs: f64,
+    pub true_peak_ceiling_dbtp: f64,
+}
+
+impl Default for NormalizationOptions {
+    fn default() -> Self {
+        Self {
+            target_lufs: -16.0, // Stereo podcast target
+            true_peak_ceiling_dbtp: -1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizedTrack {
+    pub participant_id: String,
+    pub output_file: PathBuf,
+    pub input_integrated_lufs: Option<f64>,
+    pub gain_db: f64,
+    pub limiter_max_reduction_db: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizationExport {
+    pub exported_at: DateTime<Utc>,
+    pub options: NormalizationOptions,
+    /// Normalized copies, keyed by participant ID
+    pub tracks: HashMap<String, NormalizedTrack>,
+}
+
+/// Cleanup chain applied to one participant: high-pass, de-click, expander
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CleanupSettings {
+    /// Rumble filter cutoff, 0 disables it
+    pub high_pass_hz: f64,
+    pub de_click: bool,
+    /// Level under which the expander starts attenuating
+    pub gate_threshold_db: f64,
+    /// Expansion ratio below the threshold, large values make it a gate
+    pub gate_ratio: f64,
+    /// Maximum attenuation of the expander
+    pub gate_range_db: f64,
+}
+
+impl Default for CleanupSettings {
+    fn default() -> Self {
+        Self {
+            high_pass_hz: 80.0,
+            de_click: true,
+            gate_threshold_db: -50.0,
+            gate_ratio: 4.0,
+            gate_range_db: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupOptions {
+    /// Keyed by participant ID, missing participants get the default chain
+    #[serde(default)]
+    pub tracks: HashMap<String, CleanupSettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanedTrack {
+    pub output_file: PathBuf,
+    /// Settings the file was produced with
+    pub settings: CleanupSettings,
+    pub clicks_repaired: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupExport {
+    pub exported_at: DateTime<Utc>,
+    /// Cleaned copies, keyed by participant ID
+    pub tracks: HashMap<String, CleanedTrack>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MarkerKind {
+    /// Free-form note for the editor ("good quote here")
+    #[default]
+    Note,
+    /// Start of a chapter, exported to the chapter lists
+    Chapter,
+}
+
+/// Marker dropped by the host while recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub id: String,
+    pub label: String,
+    pub kind: MarkerKind,
+    /// Position on the session timeline, pauses excluded
+    pub position_seconds: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pause {
+    pub paused_at: DateTime<Utc>,
+    /// None while the recording is still paused
+    pub resumed_at: Option<DateTime<Utc>>,
+    /// Session timeline position the pause happened at
+    pub position_seconds: f64,
+}
+
+/// How an external file was lined up with the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncMethod {
+    Clap,
+    CrossCorrelation,
+}
+
+/// Where an external backup recording sits on the session timeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalAlignment {
+    /// Participant whose track the file was aligned against
+    pub participant_id: String,
+    pub source_file: PathBuf,
+    pub method: SyncMethod,
+    /// Session time of the external file's first sample, negative if it
+    /// started before the session
+    pub offset_seconds: f64,
+    /// Clock drift of the external recorder, positive when it runs fast
+    #[serde(default)]
+    pub drift_ppm: f64,
+    /// Session times of the shared transients the offset was computed from
+    #[serde(default)]
+    pub sync_points: Vec<f64>,
+    /// Copy of the external file placed on the session timeline
+    #[serde(default)]
+    pub aligned_file: Option<PathBuf>,
+    pub aligned_at: DateTime<Utc>,
+}
+
+/// Level and position of one participant in the mixdown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackMixSettings {
+    #[serde(default)]
+    pub gain_db: f64,
+    /// -1.0 is hard left, 1.0 hard right
+    #[serde(default)]
+    pub pan: f64,
+}
+
+impl TrackMixSettings {
+    /// Left/right gains with a balance pan law, unity on both sides at center
+    pub fn stereo_gains(&self) -> (f32, f32) {
+        let gain = 10f64.powf(self.gain_db / 20.0);
+        let pan = self.pan.clamp(-1.0, 1.0);
+        (
+            (gain * (1.0 - pan).min(1.0)) as f32,
+            (gain * (1.0 + pan).min(1.0)) as f32,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MixFormat {
+    #[default]
+    Wav,
+    Webm,
+}
+
+impl MixFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MixFormat::Wav => "wav",
+            MixFormat::Webm => "webm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MixdownOptions {
+    #[serde(default)]
+    pub format: MixFormat,
+    /// Keyed by participant ID, missing participants are mixed at unity, centered
+    #[serde(default)]
+    pub tracks: HashMap<String, TrackMixSettings>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishFormat {
+    /// MP3 with an ID3v2.4 tag
+    #[default]
+    Mp3,
+    /// AAC-LC in an MP4 container
+    M4a,
+}
+
+impl PublishFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PublishFormat::Mp3 => "mp3",
+            PublishFormat::M4a => "m4a",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            PublishFormat::Mp3 => "audio/mpeg",
+            PublishFormat::M4a => "audio/mp4",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PublishOptions {
+    pub format: PublishFormat,
+    /// Final mix to encode, the participants are summed when absent
+    #[serde(default)]
+    pub source: Option<PathBuf>,
+    /// Constant bitrate, 128 kbps when absent
+    #[serde(default)]
+    pub bitrate_kbps: Option<u32>,
+}
+
+/// Encoded episode, ready for the feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishExport {
+    pub exported_at: DateTime<Utc>,
+    pub format: PublishFormat,
+    pub file: PathBuf,
+    pub size_bytes: u64,
+    pub duration_seconds: f64,
+}
+
+/// What the published episode is called, stored as `episode.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EpisodeMetadata {
+    pub title: String,
+    /// Name of the podcast
+    pub show: String,
+    /// Show notes, plain text
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub episode_number: Option<u32>,
+    /// Cover image (JPEG or PNG) embedded in the exported file
+    #[serde(default)]
+    pub artwork_path: Option<PathBuf>,
+    /// Last encoded file, None until published
+    #[serde(default)]
+    pub export: Option<PublishExport>,
+}
+
+/// Podcast-level details of the RSS feed, stored as `feed.json` in the recordings root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedSettings {
+    pub title: String,
+    pub description: String,
+    /// Website of the show
+    pub link: String,
+    pub author: String,
+    #[serde(default)]
+    pub owner_email: Option<String>,
+    /// RFC 5646 code, e.g. `en` or `fr`
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Show artwork, already hosted
+    #[serde(default)]
+    pub image_url: Option<String>,
+    /// Apple Podcasts category, e.g. `Technology`
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub explicit: bool,
+    /// Public URL `output_dir` is served from
+    pub base_url: String,
+    /// Directory the static host syncs from
+    pub output_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedExport {
+    pub generated_at: DateTime<Utc>,
+    pub feed_file: PathBuf,
+    /// Recording IDs of the episodes in the feed, newest first
+    pub episodes: Vec<String>,
+}
+
+/// A span of the session timeline
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+}
+
+impl TimeRange {
+    pub fn duration_seconds(&self) -> f64 {
+        (self.end_seconds - self.start_seconds).max(0.0)
+    }
+}
+
+/// When one participant is talking, from the energy-based VAD
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParticipantActivity {
+    /// Speech intervals on the session timeline, sorted and non-overlapping
+    pub speech: Vec<TimeRange>,
+    pub talk_seconds: f64,
+    /// Share of the session duration spent talking
+    pub talk_time_percent: f64,
+    /// Frame RMS level above which the track counted as speech
+    pub threshold_db: f64,
+}
+
+/// Tracks cut to the span between the first and last speech of the session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrimExport {
+    pub exported_at: DateTime<Utc>,
+    /// In point on the session timeline, the same for every track
+    pub in_seconds: f64,
+    pub out_seconds: f64,
+    pub padding_seconds: f64,
+    /// Trimmed WAV file, keyed by participant ID
+    pub tracks: HashMap<String, PathBuf>,
+}
+
+/// Speech activity of every participant, stored as `activity.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityTimeline {
+    pub analyzed_at: DateTime<Utc>,
+    pub duration_seconds: f64,
+    /// Keyed by participant ID
+    pub participants: HashMap<String, ParticipantActivity>,
+}
+
+impl ActivityTimeline {
+    /// Stretches of at least `min_seconds` where nobody is talking
+    pub fn silences(&self, min_seconds: f64) -> Vec<TimeRange> {
+        let mut speech: Vec<TimeRange> = self
+            .participants
+            .values()
+            .flat_map(|p| p.speech.iter().copied())
+            .collect();
+        speech.sort_by(|a, b| a.start_seconds.total_cmp(&b.start_seconds));
+
+        let mut silences = Vec::new();
+        let mut cursor = 0.0;
+        for range in speech {
+            if range.start_seconds - cursor >= min_seconds {
+                silences.push(TimeRange {
+                    start_seconds: cursor,
+                    end_seconds: range.start_seconds,
+                });
+            }
+            cursor = f64::max(cursor, range.end_seconds);
+        }
+        if self.duration_seconds - cursor >= min_seconds {
+            silences.push(TimeRange {
+                start_seconds: cursor,
+                end_seconds: self.duration_seconds,
+            });
+        }
+        silences
+    }
+}
+
+/// A stretch where one track holds a delayed, attenuated copy of another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BleedSegment {
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    /// How late the copy arrives in the target track
+    pub delay_ms: f64,
+    /// Level of the copy relative to the source track
+    pub level_db: f64,
+    /// Peak normalized cross-correlation over the segment
+    pub correlation: f64,
+}
+
+/// Bleed of one participant's voice into another participant's track
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BleedPair {
+    pub source_participant_id: String,
+    pub target_participant_id: String,
+    pub segments: Vec<BleedSegment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BleedReport {
+    pub analyzed_at: DateTime<Utc>,
+    /// Only the pairs where bleed was found
+    pub pairs: Vec<BleedPair>,
+}
+
+/// Outcome of rewriting a damaged or unfinished WebM track
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairReport {
+    pub source_file: PathBuf,
+    pub output_file: PathBuf,
+    pub repaired_at: DateTime<Utc>,
+    /// The source ended mid-element or held unreadable data
+    pub truncated: bool,
+    pub source_bytes: u64,
+    /// Trailing bytes of the source that could not be used
+    pub dropped_bytes: u64,
+    pub recovered_blocks: u64,
+    /// Media time covered by the recovered blocks
+    pub recovered_seconds: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProbedTrackKind {
+    Audio,
+    Video,
+    Other,
+}
+
+/// One track of a probed file, as declared and as measured from its blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbedTrack {
+    pub number: u64,
+    pub kind: ProbedTrackKind,
+    pub codec_id: String,
+    /// Container sample rate, always 48000 for Opus
+    pub sample_rate: Option<f64>,
+    /// Rate the audio was captured at before encoding, from the OpusHead
+    pub input_sample_rate: Option<u32>,
+    pub channels: Option<u64>,
+    pub width: Option<u64>,
+    pub height: Option<u64>,
+    /// Measured from the block timestamps
+    pub frame_rate: Option<f64>,
+    pub block_count: u64,
+    pub keyframe_count: u64,
+    pub duration_seconds: f64,
+}
+
+/// Structure of a recorded WebM file, ffprobe style
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeReport {
+    pub path: PathBuf,
+    pub file_bytes: u64,
+    /// EBML DocType, `webm` or `matroska`
+    pub container: String,
+    pub writing_app: Option<String>,
+    /// Duration written by the muxer, live recordings have none
+    pub declared_duration_seconds: Option<f64>,
+    /// Span covered by the blocks of every track
+    pub duration_seconds: f64,
+    /// Segment of unknown size, as written by MediaRecorder
+    pub live_segment: bool,
+    pub cluster_count: u64,
+    pub tracks: Vec<ProbedTrack>,
+    /// Structural problems found, empty for a sound file
+    pub errors: Vec<String>,
+}
+
+/// Input level of a participant's audio over the last metering window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioLevel {
+    pub participant_id: String,
+    /// End of the window, in seconds of recorded audio
+    pub timestamp_seconds: f64,
+    pub rms_db: f64,
+    pub peak_db: f64,
+}
+
+/// Problems spotted on a participant's input while recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TrackWarning {
+    /// The input has been flat-line silent for `silent_seconds`
+    PossiblyMuted {
+        participant_id: String,
+        silent_seconds: f64,
+    },
+    /// Samples hit full scale during the last metering window
+    Clipping {
+        participant_id: String,
+        timestamp_seconds: f64,
+        clipped_samples: u64,
+    },
+    /// The incoming stream does not match the `RecordingConfig`
+    FormatMismatch {
+        participant_id: String,
+        field: String,
+        configured: f64,
+        actual: f64,
+    },
+}
+
+/// One past session as listed in the library
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub id: String,
+    pub room_id: String,
+    pub directory: PathBuf,
+    pub started_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub duration_seconds: f64,
+    /// Participant names, in order of arrival
+    pub participants: Vec<String>,
+    /// Title from `episode.json`, once described
+    #[serde(default)]
+    pub episode_title: Option<String>,
+    #[serde(default)]
+    pub published: bool,
+    /// Last change of the recording's JSON files, the entry is re-read when it moves
+    pub modified_at: DateTime<Utc>,
+}
+
+/// Every recording of the recordings root, stored as `library.json` there
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LibraryIndex {
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Newest first
+    pub recordings: Vec<LibraryEntry>,
+}
+
+/// Filters of a library search, all optional and combined
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LibraryQuery {
+    /// Case-insensitive part of the room, a participant name or the episode title
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Sessions started at or after this instant
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    /// Sessions started before this instant
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordingStatus {
+    Idle,
+    Recording { started_at: DateTime<Utc> },
+    Paused { started_at: DateTime<Utc>, paused_at: DateTime<Utc> },
+    Stopped,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordingError {
+    #[error("Recording already in progress")]
+    AlreadyRecording,
+
+    #[error("No active recording")]
+    NoActiveRecording,
+
+    #[error("Participant not found: {0}")]
+    ParticipantNotFound(String),
+
+    #[error("Recording not found: {0}")]
+    RecordingNotFound(String),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Track error: {0}")]
+    TrackError(String),
+
+    #[error("Invalid chunk data")]
+    InvalidChunkData,
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Invalid media: {0}")]
+    InvalidMedia(String),
+
+    #[error("Decode error: {0}")]
+    DecodeError(String),
+}
+
+pub type RecordingResult<T> = Result<T, RecordingError>;
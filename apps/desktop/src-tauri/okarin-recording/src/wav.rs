@@ -0,0 +1,157 @@
+use super::types::*;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Bit depth of every WAV export
+pub const EXPORT_BITS_PER_SAMPLE: u16 = 24;
+
+const MAX_24_BIT: f32 = 8_388_607.0;
+
+fn wav_error(e: hound::Error) -> RecordingError {
+    match e {
+        hound::Error::IoError(e) => RecordingError::IoError(e),
+        other => RecordingError::IoError(std::io::Error::other(other)),
+    }
+}
+
+/// Streaming 24-bit PCM WAV writer used by the export paths
+pub struct WavFileWriter {
+    writer: WavWriter<BufWriter<File>>,
+    path: PathBuf,
+}
+
+impl WavFileWriter {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> RecordingResult<Self> {
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: EXPORT_BITS_PER_SAMPLE,
+            sample_format: SampleFormat::Int,
+        };
+        let writer = WavWriter::create(path, spec).map_err(wav_error)?;
+
+        Ok(Self {
+            writer,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Write interleaved float samples, clipping anything outside [-1, 1]
+    pub fn write(&mut self, samples: &[f32]) -> RecordingResult<()> {
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * MAX_24_BIT).round() as i32;
+            self.writer.write_sample(value).map_err(wav_error)?;
+        }
+        Ok(())
+    }
+
+    pub fn finalize(self) -> RecordingResult<PathBuf> {
+        self.writer.finalize().map_err(wav_error)?;
+        Ok(self.path)
+    }
+}
+
+/// Streaming reader for external WAV files of any PCM or float format
+pub struct WavFileReader {
+    reader: WavReader<BufReader<File>>,
+    spec: WavSpec,
+}
+
+impl WavFileReader {
+    pub fn open(path: &Path) -> RecordingResult<Self> {
+        let reader = WavReader::open(path).map_err(|e| match e {
+            hound::Error::IoError(e) => RecordingError::IoError(e),
+            other => RecordingError::InvalidMedia(format!("{:?}: {}", path, other)),
+        })?;
+        let spec = reader.spec();
+
+        Ok(Self { reader, spec })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.spec.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.spec.sample_rate
+    }
+
+    /// Length of the file in frames
+    pub fn frame_count(&self) -> u64 {
+        self.reader.duration() as u64
+    }
+
+    /// Read up to `frames` frames of interleaved samples, None once exhausted
+    pub fn read(&mut self, frames: usize) -> RecordingResult<Option<Vec<f32>>> {
+        let wanted = frames * self.spec.channels as usize;
+        let samples: Vec<f32> = match self.spec.sample_format {
+            SampleFormat::Float => self
+                .reader
+                .samples::<f32>()
+                .take(wanted)
+                .collect::<Result<_, _>>()
+                .map_err(wav_error)?,
+            SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (self.spec.bits_per_sample - 1)) as f32;
+                self.reader
+                    .samples::<i32>()
+                    .take(wanted)
+                    .map(|s| s.map(|s| s as f32 * scale))
+                    .collect::<Result<_, _>>()
+                    .map_err(wav_error)?
+            }
+        };
+
+        Ok((!samples.is_empty()).then_some(samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn test_write_and_read_back() {
+        let dir = temp_dir("wav-roundtrip");
+        let path = dir.join("roundtrip.wav");
+        let mut writer = WavFileWriter::create(&path, 2, 48000).unwrap();
+        writer.write(&[0.0, 0.5, -0.5, 2.0]).unwrap();
+        writer.finalize().unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 24);
+        let samples: Vec<i32> = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0, 4_194_304, -4_194_304, 8_388_607]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_reader_scales_to_float() {
+        let dir = temp_dir("wav-reader");
+        let path = dir.join("reader.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for sample in [0i16, 16384, -32768] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let mut reader = WavFileReader::open(&path).unwrap();
+        assert_eq!(reader.sample_rate(), 44100);
+        assert_eq!(reader.frame_count(), 3);
+        assert_eq!(reader.read(2).unwrap(), Some(vec![0.0, 0.5]));
+        assert_eq!(reader.read(2).unwrap(), Some(vec![-1.0]));
+        assert_eq!(reader.read(2).unwrap(), None);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
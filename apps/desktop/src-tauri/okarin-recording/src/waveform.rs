@@ -0,0 +1,244 @@
+use super::decoder::{OpusTrackDecoder, TimelineReader, OPUS_SAMPLE_RATE};
+use super::storage::derived_file_path;
+use super::types::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Samples per pixel of each pre-computed zoom level, most detailed first
+pub const WAVEFORM_ZOOM_LEVELS: [u32; 4] = [256, 1024, 4096, 16384];
+
+const DAT_VERSION: i32 = 1;
+const DAT_HEADER_LEN: usize = 20;
+const WAVEFORM_BLOCK_FRAMES: usize = 4800;
+
+/// Min/max peak data, in the layout of audiowaveform's JSON output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveformData {
+    pub version: i32,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples_per_pixel: u32,
+    pub bits: u8,
+    /// Number of min/max pairs in `data`
+    pub length: usize,
+    pub data: Vec<i16>,
+}
+
+impl WaveformData {
+    /// Serialize to the audiowaveform binary `.dat` format (version 1, 16-bit)
+    pub fn to_dat(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(DAT_HEADER_LEN + self.data.len() * 2);
+        bytes.extend_from_slice(&DAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // flags: 16-bit values
+        bytes.extend_from_slice(&(self.sample_rate as i32).to_le_bytes());
+        bytes.extend_from_slice(&(self.samples_per_pixel as i32).to_le_bytes());
+        bytes.extend_from_slice(&(self.length as u32).to_le_bytes());
+        for value in &self.data {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_dat(bytes: &[u8]) -> RecordingResult<Self> {
+        let invalid =
+            |reason: &str| RecordingError::InvalidMedia(format!("waveform file: {}", reason));
+        if bytes.len() < DAT_HEADER_LEN {
+            return Err(invalid("truncated header"));
+        }
+        let word = |i: usize| [bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]];
+
+        if i32::from_le_bytes(word(0)) != DAT_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let eight_bit = u32::from_le_bytes(word(4)) & 1 == 1;
+        let sample_rate = i32::from_le_bytes(word(8)) as u32;
+        let samples_per_pixel = i32::from_le_bytes(word(12)) as u32;
+        let length = u32::from_le_bytes(word(16)) as usize;
+
+        let body = &bytes[DAT_HEADER_LEN..];
+        let data: Vec<i16> = if eight_bit {
+            body.iter().map(|&b| (b as i8 as i16) << 8).collect()
+        } else {
+            body.chunks_exact(2)
+                .map(|c| i16::from_le_bytes([c[0], c[1]]))
+                .collect()
+        };
+        if data.len() < length * 2 {
+            return Err(invalid("truncated data"));
+        }
+
+        Ok(Self {
+            version: DAT_VERSION,
+            channels: 1,
+            sample_rate,
+            samples_per_pixel,
+            bits: 16,
+            length,
+            data: data[..length * 2].to_vec(),
+        })
+    }
+
+    /// Merge every `factor` consecutive pairs into one
+    pub fn reduce(&self, factor: u32) -> Self {
+        let data: Vec<i16> = self
+            .data
+            .chunks(factor.max(1) as usize * 2)
+            .flat_map(|chunk| {
+                let min = chunk.iter().step_by(2).copied().min().unwrap_or(0);
+                let max = chunk.iter().skip(1).step_by(2).copied().max().unwrap_or(0);
+                [min, max]
+            })
+            .collect();
+
+        Self {
+            samples_per_pixel: self.samples_per_pixel * factor.max(1),
+            length: data.len() / 2,
+            data,
+            ..self.clone()
+        }
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+/// Compute the most detailed zoom level of a recorded audio file
+///
+/// Channels are averaged to mono, as audiowaveform does by default.
+pub fn compute_waveform(path: &Path) -> RecordingResult<WaveformData> {
+    let decoder = OpusTrackDecoder::open(path)?;
+    let channels = decoder.channels().max(1) as usize;
+    let mut reader = TimelineReader::new(decoder, 0.0);
+
+    let samples_per_pixel = WAVEFORM_ZOOM_LEVELS[0];
+    let mut data = Vec::new();
+    let (mut min, mut max, mut count) = (f32::MAX, f32::MIN, 0u32);
+
+    while let Some(block) = reader.read(WAVEFORM_BLOCK_FRAMES)? {
+        for frame in block.chunks_exact(channels) {
+            let sample = frame.iter().sum::<f32>() / channels as f32;
+            min = min.min(sample);
+            max = max.max(sample);
+            count += 1;
+            if count == samples_per_pixel {
+                data.extend_from_slice(&[to_i16(min), to_i16(max)]);
+                (min, max, count) = (f32::MAX, f32::MIN, 0);
+            }
+        }
+    }
+    if count > 0 {
+        data.extend_from_slice(&[to_i16(min), to_i16(max)]);
+    }
+
+    Ok(WaveformData {
+        version: DAT_VERSION,
+        channels: 1,
+        sample_rate: OPUS_SAMPLE_RATE,
+        samples_per_pixel,
+        bits: 16,
+        length: data.len() / 2,
+        data,
+    })
+}
+
+/// Path of the `.dat` file of one zoom level, next to the audio file
+pub fn waveform_path(audio_file: &Path, samples_per_pixel: u32) -> PathBuf {
+    derived_file_path(
+        audio_file,
+        &format!("waveform-{}", samples_per_pixel),
+        "dat",
+    )
+}
+
+/// Write every zoom level of a track's waveform, returning the files written
+pub fn generate_track_waveforms(audio_file: &Path) -> RecordingResult<Vec<PathBuf>> {
+    let base = compute_waveform(audio_file)?;
+    let mut files = Vec::with_capacity(WAVEFORM_ZOOM_LEVELS.len());
+
+    for &samples_per_pixel in &WAVEFORM_ZOOM_LEVELS {
+        let level = base.reduce(samples_per_pixel / base.samples_per_pixel);
+        let path = waveform_path(audio_file, samples_per_pixel);
+        fs::write(&path, level.to_dat())?;
+        files.push(path);
+    }
+
+    Ok(files)
+}
+
+/// Generate waveforms for every participant and list them in the metadata
+pub fn generate_recording_waveforms(metadata: &mut RecordingMetadata) -> RecordingResult<()> {
+    for participant in metadata.participants.values_mut() {
+        if let Some(audio_file) = &participant.audio_file {
+            participant.waveform_files = generate_track_waveforms(audio_file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load one zoom level of a participant's waveform, generating it if missing
+pub fn load_waveform(
+    metadata: &RecordingMetadata,
+    participant_id: &str,
+    zoom: usize,
+) -> RecordingResult<WaveformData> {
+    let participant = metadata
+        .participants
+        .get(participant_id)
+        .ok_or_else(|| RecordingError::ParticipantNotFound(participant_id.to_string()))?;
+    let audio_file = participant.audio_file.as_ref().ok_or_else(|| {
+        RecordingError::InvalidConfig(format!("participant {} has no audio", participant_id))
+    })?;
+    let samples_per_pixel = *WAVEFORM_ZOOM_LEVELS.get(zoom).ok_or_else(|| {
+        RecordingError::InvalidConfig(format!(
+            "zoom must be between 0 and {}",
+            WAVEFORM_ZOOM_LEVELS.len() - 1
+        ))
+    })?;
+
+    let path = waveform_path(audio_file, samples_per_pixel);
+    if !path.exists() {
+        generate_track_waveforms(audio_file)?;
+    }
+
+    WaveformData::from_dat(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    #[test]
+    fn test_dat_roundtrip_and_reduce() {
+        let waveform = WaveformData {
+            version: DAT_VERSION,
+            channels: 1,
+            sample_rate: 48000,
+            samples_per_pixel: 256,
+            bits: 16,
+            length: 3,
+            data: vec![-10, 20, -30, 5, -1, 40],
+        };
+        assert_eq!(
+            WaveformData::from_dat(&waveform.to_dat()).unwrap(),
+            waveform
+        );
+
+        let reduced = waveform.reduce(2);
+        assert_eq!(reduced.samples_per_pixel, 512);
+        assert_eq!(reduced.data, vec![-30, 20, -1, 40]);
+        assert_eq!(reduced.length, 2);
+    }
+
+    #[test]
+    fn test_compute_waveform_from_fixture() {
+        let waveform = compute_waveform(&fixture()).unwrap();
+
+        // 2 seconds minus the encoder pre-skip, at 256 samples per pixel
+        assert_eq!(waveform.length, (96000 - 312usize).div_ceil(256));
+        let max = waveform.data.iter().copied().max().unwrap();
+        assert!((14000..18000).contains(&max), "max: {}", max);
+    }
+}
@@ -0,0 +1,821 @@
+use super::types::*;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// EBML / Matroska element IDs used by the recorder
+pub const EBML_HEADER_ID: u32 = 0x1A45DFA3;
+pub const DOC_TYPE_ID: u32 = 0x4282;
+pub const SEGMENT_ID: u32 = 0x18538067;
+pub const SEEK_HEAD_ID: u32 = 0x114D9B74;
+pub const INFO_ID: u32 = 0x1549A966;
+pub const TIMECODE_SCALE_ID: u32 = 0x2AD7B1;
+pub const DURATION_ID: u32 = 0x4489;
+pub const MUXING_APP_ID: u32 = 0x4D80;
+pub const WRITING_APP_ID: u32 = 0x5741;
+pub const TRACKS_ID: u32 = 0x1654AE6B;
+pub const TRACK_ENTRY_ID: u32 = 0xAE;
+pub const TRACK_NUMBER_ID: u32 = 0xD7;
+pub const TRACK_UID_ID: u32 = 0x73C5;
+pub const TRACK_TYPE_ID: u32 = 0x83;
+pub const CODEC_ID_ID: u32 = 0x86;
+pub const CODEC_PRIVATE_ID: u32 = 0x63A2;
+pub const CODEC_DELAY_ID: u32 = 0x56AA;
+pub const SEEK_PRE_ROLL_ID: u32 = 0x56BB;
+pub const DEFAULT_DURATION_ID: u32 = 0x23E383;
+pub const AUDIO_ID: u32 = 0xE1;
+pub const SAMPLING_FREQUENCY_ID: u32 = 0xB5;
+pub const CHANNELS_ID: u32 = 0x9F;
+pub const BIT_DEPTH_ID: u32 = 0x6264;
+pub const VIDEO_ID: u32 = 0xE0;
+pub const PIXEL_WIDTH_ID: u32 = 0xB0;
+pub const PIXEL_HEIGHT_ID: u32 = 0xBA;
+pub const CLUSTER_ID: u32 = 0x1F43B675;
+pub const CLUSTER_TIMESTAMP_ID: u32 = 0xE7;
+pub const SIMPLE_BLOCK_ID: u32 = 0xA3;
+pub const BLOCK_GROUP_ID: u32 = 0xA0;
+pub const BLOCK_ID: u32 = 0xA1;
+pub const BLOCK_DURATION_ID: u32 = 0x9B;
+pub const REFERENCE_BLOCK_ID: u32 = 0xFB;
+pub const CUES_ID: u32 = 0x1C53BB6B;
+pub const VOID_ID: u32 = 0xEC;
+
+/// Default Matroska timestamp unit (1 ms)
+pub const DEFAULT_TIMECODE_SCALE: u64 = 1_000_000;
+
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Header of an EBML element: ID, data size (None = unknown size) and header length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementHeader {
+    pub id: u32,
+    pub size: Option<u64>,
+    pub header_len: usize,
+}
+
+impl ElementHeader {
+    /// Parse an element header, returning None if more data is needed
+    pub fn parse(buf: &[u8]) -> RecordingResult<Option<Self>> {
+        let (id, id_len) = match read_element_id(buf)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let (size, size_len) = match read_vint(&buf[id_len..])? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        // All bits set means "unknown size" (used by live MediaRecorder output)
+        let unknown = size == (1u64 << (7 * size_len)) - 1;
+
+        Ok(Some(Self {
+            id,
+            size: if unknown { None } else { Some(size) },
+            header_len: id_len + size_len,
+        }))
+    }
+}
+
+/// Read an element ID (marker bits kept, as in the Matroska spec)
+fn read_element_id(buf: &[u8]) -> RecordingResult<Option<(u32, usize)>> {
+    let first = match buf.first() {
+        Some(&b) => b,
+        None => return Ok(None),
+    };
+    let len = first.leading_zeros() as usize + 1;
+    if len > 4 {
+        return Err(RecordingError::InvalidMedia(format!(
+            "invalid EBML element ID byte 0x{:02X}",
+            first
+        )));
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let id = buf[..len]
+        .iter()
+        .fold(0u32, |acc, &b| (acc << 8) | b as u32);
+    Ok(Some((id, len)))
+}
+
+/// Read a variable-length integer (marker bit removed)
+pub fn read_vint(buf: &[u8]) -> RecordingResult<Option<(u64, usize)>> {
+    let first = match buf.first() {
+        Some(&b) => b,
+        None => return Ok(None),
+    };
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return Err(RecordingError::InvalidMedia(
+            "invalid EBML variable-length integer".to_string(),
+        ));
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let mut value = (first as u64) & (0xFF >> len);
+    for &b in &buf[1..len] {
+        value = (value << 8) | b as u64;
+    }
+    Ok(Some((value, len)))
+}
+
+fn read_uint(data: &[u8]) -> u64 {
+    data.iter()
+        .take(8)
+        .fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn read_float(data: &[u8]) -> RecordingResult<f64> {
+    match data.len() {
+        0 => Ok(0.0),
+        4 => Ok(f32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64),
+        8 => {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(data);
+            Ok(f64::from_be_bytes(bytes))
+        }
+        n => Err(RecordingError::InvalidMedia(format!(
+            "invalid EBML float size: {}",
+            n
+        ))),
+    }
+}
+
+fn read_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// Iterate over the children of a fully buffered master element
+fn children(mut data: &[u8]) -> impl Iterator<Item = RecordingResult<(u32, &[u8])>> {
+    std::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+        let result = match ElementHeader::parse(data) {
+            Ok(Some(ElementHeader {
+                id,
+                size: Some(size),
+                header_len,
+            })) if (header_len as u64 + size) <= data.len() as u64 => {
+                let end = header_len + size as usize;
+                let body = &data[header_len..end];
+                data = &data[end..];
+                Ok((id, body))
+            }
+            Ok(_) => Err(RecordingError::InvalidMedia(
+                "truncated or unsized child element".to_string(),
+            )),
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            data = &[];
+        }
+        Some(result)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackKind {
+    Video,
+    Audio,
+    Other(u64),
+}
+
+impl From<u64> for TrackKind {
+    fn from(value: u64) -> Self {
+        match value {
+            1 => TrackKind::Video,
+            2 => TrackKind::Audio,
+            other => TrackKind::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioTrackInfo {
+    pub sampling_frequency: f64,
+    pub channels: u64,
+    pub bit_depth: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoTrackInfo {
+    pub pixel_width: u64,
+    pub pixel_height: u64,
+}
+
+/// A TrackEntry from the Tracks element
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackInfo {
+    pub number: u64,
+    pub uid: Option<u64>,
+    pub kind: TrackKind,
+    pub codec_id: String,
+    pub codec_private: Option<Vec<u8>>,
+    pub codec_delay_ns: u64,
+    pub seek_pre_roll_ns: u64,
+    pub default_duration_ns: Option<u64>,
+    pub audio: Option<AudioTrackInfo>,
+    pub video: Option<VideoTrackInfo>,
+}
+
+impl TrackInfo {
+    fn parse(data: &[u8]) -> RecordingResult<Self> {
+        let mut track = TrackInfo {
+            number: 0,
+            uid: None,
+            kind: TrackKind::Other(0),
+            codec_id: String::new(),
+            codec_private: None,
+            codec_delay_ns: 0,
+            seek_pre_roll_ns: 0,
+            default_duration_ns: None,
+            audio: None,
+            video: None,
+        };
+
+        for child in children(data) {
+            let (id, body) = child?;
+            match id {
+                TRACK_NUMBER_ID => track.number = read_uint(body),
+                TRACK_UID_ID => track.uid = Some(read_uint(body)),
+                TRACK_TYPE_ID => track.kind = TrackKind::from(read_uint(body)),
+                CODEC_ID_ID => track.codec_id = read_string(body),
+                CODEC_PRIVATE_ID => track.codec_private = Some(body.to_vec()),
+                CODEC_DELAY_ID => track.codec_delay_ns = read_uint(body),
+                SEEK_PRE_ROLL_ID => track.seek_pre_roll_ns = read_uint(body),
+                DEFAULT_DURATION_ID => track.default_duration_ns = Some(read_uint(body)),
+                AUDIO_ID => {
+                    let mut audio = AudioTrackInfo {
+                        sampling_frequency: 8000.0,
+                        channels: 1,
+                        bit_depth: None,
+                    };
+                    for child in children(body) {
+                        let (id, body) = child?;
+                        match id {
+                            SAMPLING_FREQUENCY_ID => audio.sampling_frequency = read_float(body)?,
+                            CHANNELS_ID => audio.channels = read_uint(body),
+                            BIT_DEPTH_ID => audio.bit_depth = Some(read_uint(body)),
+                            _ => {}
+                        }
+                    }
+                    track.audio = Some(audio);
+                }
+                VIDEO_ID => {
+                    let mut video = VideoTrackInfo {
+                        pixel_width: 0,
+                        pixel_height: 0,
+                    };
+                    for child in children(body) {
+                        let (id, body) = child?;
+                        match id {
+                            PIXEL_WIDTH_ID => video.pixel_width = read_uint(body),
+                            PIXEL_HEIGHT_ID => video.pixel_height = read_uint(body),
+                            _ => {}
+                        }
+                    }
+                    track.video = Some(video);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(track)
+    }
+}
+
+/// The segment Info element
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentInfo {
+    pub timecode_scale: u64,
+    /// Duration in timecode-scale units, usually absent in live recordings
+    pub duration: Option<f64>,
+    pub muxing_app: Option<String>,
+    pub writing_app: Option<String>,
+}
+
+impl SegmentInfo {
+    fn parse(data: &[u8]) -> RecordingResult<Self> {
+        let mut info = SegmentInfo {
+            timecode_scale: DEFAULT_TIMECODE_SCALE,
+            duration: None,
+            muxing_app: None,
+            writing_app: None,
+        };
+
+        for child in children(data) {
+            let (id, body) = child?;
+            match id {
+                TIMECODE_SCALE_ID => info.timecode_scale = read_uint(body),
+                DURATION_ID => info.duration = Some(read_float(body)?),
+                MUXING_APP_ID => info.muxing_app = Some(read_string(body)),
+                WRITING_APP_ID => info.writing_app = Some(read_string(body)),
+                _ => {}
+            }
+        }
+
+        if info.timecode_scale == 0 {
+            info.timecode_scale = DEFAULT_TIMECODE_SCALE;
+        }
+
+        Ok(info)
+    }
+
+    /// Duration in seconds, if the muxer wrote one
+    pub fn duration_seconds(&self) -> Option<f64> {
+        self.duration
+            .map(|d| d * self.timecode_scale as f64 / 1_000_000_000.0)
+    }
+}
+
+/// A SimpleBlock or Block with its frames already split out of any lacing
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebmBlock {
+    pub track_number: u64,
+    /// Absolute timestamp in nanoseconds
+    pub timestamp_ns: i64,
+    pub duration_ns: Option<u64>,
+    pub keyframe: bool,
+    pub frames: Vec<Vec<u8>>,
+    /// Byte offset of the block element in the file
+    pub offset: u64,
+}
+
+impl WebmBlock {
+    pub fn timestamp_seconds(&self) -> f64 {
+        self.timestamp_ns as f64 / 1_000_000_000.0
+    }
+}
+
+/// Parse the payload of a (Simple)Block: track number, relative timestamp, flags and frames
+fn parse_block_payload(data: &[u8]) -> RecordingResult<(u64, i16, u8, Vec<Vec<u8>>)> {
+    let (track_number, len) = read_vint(data)?
+        .ok_or_else(|| RecordingError::InvalidMedia("truncated block header".to_string()))?;
+    if data.len() < len + 3 {
+        return Err(RecordingError::InvalidMedia(
+            "truncated block header".to_string(),
+        ));
+    }
+    let relative = i16::from_be_bytes([data[len], data[len + 1]]);
+    let flags = data[len + 2];
+    let payload = &data[len + 3..];
+
+    let frames = match (flags >> 1) & 0x03 {
+        0 => vec![payload.to_vec()],
+        lacing => split_laced_frames(lacing, payload)?,
+    };
+
+    Ok((track_number, relative, flags, frames))
+}
+
+fn split_laced_frames(lacing: u8, payload: &[u8]) -> RecordingResult<Vec<Vec<u8>>> {
+    let invalid = || RecordingError::InvalidMedia("invalid block lacing".to_string());
+
+    let count = *payload.first().ok_or_else(invalid)? as usize + 1;
+    let mut pos = 1;
+    let mut sizes = Vec::with_capacity(count);
+
+    match lacing {
+        // Xiph lacing
+        1 => {
+            for _ in 0..count - 1 {
+                let mut size = 0usize;
+                loop {
+                    let b = *payload.get(pos).ok_or_else(invalid)?;
+                    pos += 1;
+                    size += b as usize;
+                    if b != 0xFF {
+                        break;
+                    }
+                }
+                sizes.push(size);
+            }
+        }
+        // Fixed-size lacing
+        2 => {
+            let total = payload.len() - pos;
+            if !total.is_multiple_of(count) {
+                return Err(invalid());
+            }
+            sizes = vec![total / count; count - 1];
+        }
+        // EBML lacing
+        _ => {
+            let (first, len) = read_vint(&payload[pos..])?.ok_or_else(invalid)?;
+            pos += len;
+            let mut size = first as i64;
+            sizes.push(first as usize);
+            for _ in 1..count - 1 {
+                let rest = payload.get(pos..).unwrap_or_default();
+                let (raw, len) = read_vint(rest)?.ok_or_else(invalid)?;
+                pos += len;
+                // Signed vint: subtract the bias for the encoded length
+                let bias = (1i64 << (7 * len - 1)) - 1;
+                size += raw as i64 - bias;
+                if size < 0 {
+                    return Err(invalid());
+                }
+                sizes.push(size as usize);
+            }
+        }
+    }
+
+    let mut frames = Vec::with_capacity(count);
+    for size in sizes {
+        let end = pos
+            .checked_add(size)
+            .filter(|&e| e <= payload.len())
+            .ok_or_else(invalid)?;
+        frames.push(payload[pos..end].to_vec());
+        pos = end;
+    }
+    frames.push(payload[pos..].to_vec());
+
+    Ok(frames)
+}
+
+/// Events produced while walking a WebM file
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebmEvent {
+    EbmlHeader {
+        doc_type: String,
+    },
+    SegmentStart {
+        offset: u64,
+        size: Option<u64>,
+    },
+    Info(SegmentInfo),
+    Tracks(Vec<TrackInfo>),
+    ClusterStart {
+        offset: u64,
+        size: Option<u64>,
+    },
+    ClusterTimestamp(u64),
+    Block(WebmBlock),
+    /// Any other element, skipped without interpretation
+    Other {
+        id: u32,
+        offset: u64,
+        size: u64,
+    },
+}
+
+/// Push-based WebM demuxer
+///
+/// Bytes are fed with `push` (either from a file or from live MediaRecorder
+/// chunks) and events are pulled with `next_event`. Segment and Cluster are
+/// always entered rather than skipped, so unknown-size elements written by
+/// live recorders are handled naturally.
+pub struct WebmDemuxer {
+    buffer: Vec<u8>,
+    position: usize,
+    buffer_offset: u64,
+    timecode_scale: u64,
+    cluster_timestamp: u64,
+}
+
+impl WebmDemuxer {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            position: 0,
+            buffer_offset: 0,
+            timecode_scale: DEFAULT_TIMECODE_SCALE,
+            cluster_timestamp: 0,
+        }
+    }
+
+    /// Append bytes to the parse buffer
+    pub fn push(&mut self, data: &[u8]) {
+        if self.position > 0 && self.position >= self.buffer.len() / 2 {
+            self.buffer.drain(..self.position);
+            self.buffer_offset += self.position as u64;
+            self.position = 0;
+        }
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Absolute byte offset of the next unparsed element
+    pub fn offset(&self) -> u64 {
+        self.buffer_offset + self.position as u64
+    }
+
+    /// Number of buffered bytes that have not been parsed yet
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
+    pub fn timecode_scale(&self) -> u64 {
+        self.timecode_scale
+    }
+
+    /// Parse the next event, returning None when more data is needed
+    pub fn next_event(&mut self) -> RecordingResult<Option<WebmEvent>> {
+        let available = &self.buffer[self.position..];
+        let header = match ElementHeader::parse(available)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let offset = self.offset();
+
+        // Master elements we descend into instead of buffering whole
+        match header.id {
+            SEGMENT_ID => {
+                self.position += header.header_len;
+                return Ok(Some(WebmEvent::SegmentStart {
+                    offset,
+                    size: header.size,
+                }));
+            }
+            CLUSTER_ID => {
+                self.position += header.header_len;
+                return Ok(Some(WebmEvent::ClusterStart {
+                    offset,
+                    size: header.size,
+                }));
+            }
+            _ => {}
+        }
+
+        let size = header.size.ok_or_else(|| {
+            RecordingError::InvalidMedia(format!(
+                "unknown-size element 0x{:X} at offset {}",
+                header.id, offset
+            ))
+        })?;
+        let total = header.header_len as u64 + size;
+        if (available.len() as u64) < total {
+            return Ok(None);
+        }
+
+        let body_start = self.position + header.header_len;
+        let body_end = self.position + total as usize;
+        self.position = body_end;
+        let body = &self.buffer[body_start..body_end];
+
+        let event = match header.id {
+            EBML_HEADER_ID => {
+                let mut doc_type = String::new();
+                for child in children(body) {
+                    let (id, body) = child?;
+                    if id == DOC_TYPE_ID {
+                        doc_type = read_string(body);
+                    }
+                }
+                WebmEvent::EbmlHeader { doc_type }
+            }
+            INFO_ID => {
+                let info = SegmentInfo::parse(body)?;
+                self.timecode_scale = info.timecode_scale;
+                WebmEvent::Info(info)
+            }
+            TRACKS_ID => {
+                let mut tracks = Vec::new();
+                for child in children(body) {
+                    let (id, body) = child?;
+                    if id == TRACK_ENTRY_ID {
+                        tracks.push(TrackInfo::parse(body)?);
+                    }
+                }
+                WebmEvent::Tracks(tracks)
+            }
+            CLUSTER_TIMESTAMP_ID => {
+                self.cluster_timestamp = read_uint(body);
+                WebmEvent::ClusterTimestamp(self.cluster_timestamp)
+            }
+            SIMPLE_BLOCK_ID => {
+                let (track_number, relative, flags, frames) = parse_block_payload(body)?;
+                WebmEvent::Block(WebmBlock {
+                    track_number,
+                    timestamp_ns: self.block_timestamp_ns(relative, offset)?,
+                    duration_ns: None,
+                    keyframe: flags & 0x80 != 0,
+                    frames,
+                    offset,
+                })
+            }
+            BLOCK_GROUP_ID => {
+                let mut block = None;
+                let mut duration = None;
+                let mut has_reference = false;
+                for child in children(body) {
+                    let (id, body) = child?;
+                    match id {
+                        BLOCK_ID => block = Some(parse_block_payload(body)?),
+                        BLOCK_DURATION_ID => duration = Some(read_uint(body)),
+                        REFERENCE_BLOCK_ID => has_reference = true,
+                        _ => {}
+                    }
+                }
+                let (track_number, relative, _, frames) = block.ok_or_else(|| {
+                    RecordingError::InvalidMedia("BlockGroup without Block".to_string())
+                })?;
+                WebmEvent::Block(WebmBlock {
+                    track_number,
+                    timestamp_ns: self.block_timestamp_ns(relative, offset)?,
+                    duration_ns: duration.map(|d| d.saturating_mul(self.timecode_scale)),
+                    keyframe: !has_reference,
+                    frames,
+                    offset,
+                })
+            }
+            id => WebmEvent::Other { id, offset, size },
+        };
+
+        Ok(Some(event))
+    }
+
+    fn block_timestamp_ns(&self, relative: i16, offset: u64) -> RecordingResult<i64> {
+        i64::try_from(self.cluster_timestamp)
+            .ok()
+            .and_then(|cluster| cluster.checked_add(relative as i64))
+            .zip(i64::try_from(self.timecode_scale).ok())
+            .and_then(|(timestamp, scale)| timestamp.checked_mul(scale))
+            .ok_or_else(|| {
+                RecordingError::InvalidMedia(format!(
+                    "block timestamp out of range at offset {}",
+                    offset
+                ))
+            })
+    }
+}
+
+impl Default for WebmDemuxer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pull-based WebM reader over any byte source
+pub struct WebmReader<R> {
+    source: R,
+    demuxer: WebmDemuxer,
+    chunk: Vec<u8>,
+    eof: bool,
+}
+
+impl WebmReader<BufReader<File>> {
+    /// Open a recorded WebM file
+    pub fn open(path: &Path) -> RecordingResult<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: Read> WebmReader<R> {
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            demuxer: WebmDemuxer::new(),
+            chunk: vec![0u8; READ_CHUNK_SIZE],
+            eof: false,
+        }
+    }
+
+    /// Read the next event, returning None at end of file
+    pub fn next_event(&mut self) -> RecordingResult<Option<WebmEvent>> {
+        loop {
+            if let Some(event) = self.demuxer.next_event()? {
+                return Ok(Some(event));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            let read = self.source.read(&mut self.chunk)?;
+            if read == 0 {
+                self.eof = true;
+            } else {
+                self.demuxer.push(&self.chunk[..read]);
+            }
+        }
+    }
+
+    /// Read up to the Tracks element and return its entries
+    pub fn read_tracks(&mut self) -> RecordingResult<Vec<TrackInfo>> {
+        while let Some(event) = self.next_event()? {
+            match event {
+                WebmEvent::Tracks(tracks) => return Ok(tracks),
+                WebmEvent::Block(_) => break,
+                _ => {}
+            }
+        }
+        Err(RecordingError::InvalidMedia(
+            "no Tracks element before media data".to_string(),
+        ))
+    }
+
+    /// Read the next block, skipping every other element
+    pub fn next_block(&mut self) -> RecordingResult<Option<WebmBlock>> {
+        while let Some(event) = self.next_event()? {
+            if let WebmEvent::Block(block) = event {
+                return Ok(Some(block));
+            }
+        }
+        Ok(None)
+    }
+
+    /// True once the source is exhausted with an incomplete element left over
+    pub fn is_truncated(&self) -> bool {
+        self.eof && self.demuxer.buffered() > 0
+    }
+
+    /// Absolute byte offset of the next unparsed element
+    pub fn offset(&self) -> u64 {
+        self.demuxer.offset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixture;
+
+    #[test]
+    fn test_element_header_parse() {
+        let header = ElementHeader::parse(&[0x1A, 0x45, 0xDF, 0xA3, 0x9F])
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.id, EBML_HEADER_ID);
+        assert_eq!(header.size, Some(31));
+        assert_eq!(header.header_len, 5);
+
+        // Live MediaRecorder output uses unknown-size Segment and Cluster
+        let header = ElementHeader::parse(&[
+            0x1F, 0x43, 0xB6, 0x75, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(header.id, CLUSTER_ID);
+        assert_eq!(header.size, None);
+
+        assert_eq!(ElementHeader::parse(&[0x1A, 0x45]).unwrap(), None);
+        assert!(ElementHeader::parse(&[0x00]).is_err());
+    }
+
+    #[test]
+    fn test_xiph_lacing() {
+        // 3 frames: sizes 2 and 3 laced, last one takes the rest
+        let payload = [0x02, 0x02, 0x03, 1, 1, 2, 2, 2, 3];
+        let frames = split_laced_frames(1, &payload).unwrap();
+        assert_eq!(frames, vec![vec![1, 1], vec![2, 2, 2], vec![3]]);
+    }
+
+    #[test]
+    fn test_read_fixture() {
+        let mut reader = WebmReader::open(&fixture()).unwrap();
+        let tracks = reader.read_tracks().unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].kind, TrackKind::Audio);
+        assert_eq!(tracks[0].codec_id, "A_OPUS");
+        assert_eq!(tracks[0].audio.as_ref().unwrap().channels, 1);
+
+        let mut blocks = Vec::new();
+        while let Some(block) = reader.next_block().unwrap() {
+            blocks.push(block);
+        }
+        assert_eq!(blocks.len(), 100);
+        assert_eq!(blocks[0].timestamp_ns, 0);
+        // Second cluster starts at 1000ms
+        assert_eq!(blocks[50].timestamp_ns, 1_000_000_000);
+        assert!(!reader.is_truncated());
+    }
+
+    #[test]
+    fn test_demuxer_handles_split_chunks() {
+        let data = std::fs::read(fixture()).unwrap();
+        let mut demuxer = WebmDemuxer::new();
+        let mut blocks = 0;
+
+        // Feed the file in small, arbitrary pieces as MediaRecorder would
+        for chunk in data.chunks(37) {
+            demuxer.push(chunk);
+            while let Some(event) = demuxer.next_event().unwrap() {
+                if let WebmEvent::Block(_) = event {
+                    blocks += 1;
+                }
+            }
+        }
+        assert_eq!(blocks, 100);
+        assert_eq!(demuxer.buffered(), 0);
+    }
+
+    #[test]
+    fn test_block_timestamp_overflow_is_an_error() {
+        let mut demuxer = WebmDemuxer::new();
+        demuxer.cluster_timestamp = 1000;
+        assert_eq!(demuxer.block_timestamp_ns(-20, 0).unwrap(), 980_000_000);
+
+        demuxer.cluster_timestamp = u64::MAX / 2;
+        assert!(demuxer.block_timestamp_ns(0, 0).is_err());
+    }
+
+    #[test]
+    fn test_truncated_file_detected() {
+        let data = std::fs::read(fixture()).unwrap();
+        let mut reader = WebmReader::new(&data[..data.len() - 10]);
+        while reader.next_block().unwrap().is_some() {}
+        assert!(reader.is_truncated());
+    }
+}
@@ -0,0 +1,121 @@
+//! A session driven through the public API, the way the desktop app does it
+
+use okarin_recording::{chapters, decoder, library, mixdown, probe, repair, storage};
+use okarin_recording::{
+    MarkerKind, MixdownOptions, RecordingConfig, RecordingManager, RecordingMetadata,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 2 seconds of a 440Hz sine, mono Opus in WebM
+fn fixture() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sine-440hz-mono.webm")
+}
+
+/// A new empty recordings root, unique per process
+fn recordings_root(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("okarin-session-{}-{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Record the fixture as the audio of a single guest, streamed in small chunks
+fn record_fixture(root: &Path) -> RecordingMetadata {
+    let manager = RecordingManager::new();
+    manager
+        .start_recording(RecordingConfig {
+            room_id: "integration".to_string(),
+            output_dir: root.to_path_buf(),
+            ..Default::default()
+        })
+        .unwrap();
+    manager
+        .add_participant("p1".to_string(), "Guest".to_string(), true, false)
+        .unwrap();
+
+    for chunk in fs::read(fixture()).unwrap().chunks(4096) {
+        manager.add_audio_chunk("p1", chunk.to_vec()).unwrap();
+    }
+    manager
+        .add_marker("Intro".to_string(), MarkerKind::Chapter)
+        .unwrap();
+
+    manager.stop_recording().unwrap()
+}
+
+#[test]
+fn test_record_and_post_process_a_session() {
+    let root = recordings_root("post-process");
+    let metadata = record_fixture(&root);
+
+    let audio_file = metadata.participants["p1"].audio_file.clone().unwrap();
+    let pcm = decoder::decode_file(&audio_file).unwrap();
+    let original = decoder::decode_file(&fixture()).unwrap();
+    assert_eq!(pcm.sample_rate, 48000);
+    assert_eq!(pcm.frame_count(), original.frame_count());
+
+    let saved = storage::load_metadata(&metadata.output_directory).unwrap();
+    assert_eq!(saved.id, metadata.id);
+    assert_eq!(saved.markers.len(), 1);
+
+    let mix = mixdown::mixdown_recording(&metadata, &MixdownOptions::default()).unwrap();
+    assert!(fs::metadata(&mix).unwrap().len() > 0);
+
+    let files = chapters::export_chapters(&metadata).unwrap();
+    let vtt = fs::read_to_string(&files.webvtt).unwrap();
+    assert!(vtt.contains("Intro"));
+
+    let entries = library::list_recordings(&root).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].id, metadata.id);
+    assert_eq!(entries[0].participants, vec!["Guest".to_string()]);
+
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn test_repair_an_interrupted_session() {
+    let root = recordings_root("interrupted");
+    let metadata = record_fixture(&root);
+
+    // The app was killed halfway through writing the track
+    let audio_file = metadata.participants["p1"].audio_file.clone().unwrap();
+    let bytes = fs::read(&audio_file).unwrap();
+    fs::write(&audio_file, &bytes[..bytes.len() / 2]).unwrap();
+
+    let reports = repair::repair_recording(&metadata.output_directory).unwrap();
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].truncated);
+    assert!(reports[0].recovered_seconds > 0.5 && reports[0].recovered_seconds < 2.0);
+
+    let report = probe::probe_recording_file(&reports[0].output_file).unwrap();
+    assert_eq!(report.tracks.len(), 1);
+    assert_eq!(report.tracks[0].codec_id, "A_OPUS");
+    assert!((report.duration_seconds - reports[0].recovered_seconds).abs() < 0.05);
+
+    fs::remove_dir_all(root).ok();
+}
+
+#[test]
+fn test_process_a_moved_session() {
+    let root = recordings_root("recorded");
+    let metadata = record_fixture(&root);
+
+    // Copied to another machine, or just another folder
+    let moved_root = recordings_root("moved");
+    let moved = moved_root.join(metadata.output_directory.file_name().unwrap());
+    fs::rename(&metadata.output_directory, &moved).unwrap();
+
+    let loaded = storage::load_metadata(&moved).unwrap();
+    assert_eq!(loaded.output_directory, moved);
+    let audio_file = loaded.participants["p1"].audio_file.clone().unwrap();
+    assert!(audio_file.starts_with(&moved), "{:?}", audio_file);
+
+    let mix = mixdown::mixdown_recording(&loaded, &MixdownOptions::default()).unwrap();
+    assert!(mix.starts_with(&moved), "{:?}", mix);
+    assert!(fs::metadata(&mix).unwrap().len() > 0);
+
+    fs::remove_dir_all(root).ok();
+    fs::remove_dir_all(moved_root).ok();
+}
@@ -1,9 +1,87 @@
-use crate::recording::{
-    RecordingConfig, RecordingError, RecordingManager, RecordingMetadata, RecordingStatus,
+use okarin_recording::chapters::ChapterFiles;
+use okarin_recording::interchange::TimelineFiles;
+use okarin_recording::meter::MeterEvent;
+use okarin_recording::project::ProjectFiles;
+use okarin_recording::waveform::WaveformData;
+use okarin_recording::{
+    activity, align, analysis, avmux, bleed, chapters, cleanup, feed, interchange, library,
+    loudness, mixdown, normalize, ogg, probe, project, publish, repair, storage, sync, trim,
+    waveform, ActivityTimeline, BleedReport, CleanupExport, CleanupOptions, EpisodeMetadata,
+    ExternalAlignment, FeedExport, FeedSettings, LibraryEntry, LibraryQuery, LoudnessReport,
+    Marker, MarkerKind, MixdownOptions, NormalizationExport, NormalizationOptions, ProbeReport,
+    PublishExport, PublishOptions, RecordingConfig, RecordingError, RecordingManager,
+    RecordingMetadata, RecordingStatus, RepairReport, TrimExport,
 };
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
+
+/// Event carrying an `AudioLevel`, one per 100ms of audio per participant
+///
+/// Levels go out as the audio chunks arrive, the frontend sends them every
+/// 100ms, which makes about 10 events per second.
+pub const AUDIO_LEVEL_EVENT: &str = "recording-audio-level";
+/// Event carrying a `TrackWarning` (possibly muted guest, clipping)
+pub const TRACK_WARNING_EVENT: &str = "recording-track-warning";
+/// Event carrying the `RecordingMetadata` of a stopped recording once the
+/// post-stop analysis has been saved with it
+pub const ANALYSIS_COMPLETE_EVENT: &str = "recording-analysis-complete";
+
+/// `RecordingError` as the frontend receives it, `{ kind, message }`
+#[derive(Debug)]
+pub struct CommandError(RecordingError);
+
+impl From<RecordingError> for CommandError {
+    fn from(error: RecordingError) -> Self {
+        Self(error)
+    }
+}
+
+// Sérialisation structurée pour le frontend
+#[derive(serde::Serialize)]
+#[serde(tag = "kind", content = "message")]
+#[serde(rename_all = "camelCase")]
+enum RecordingErrorKind {
+    AlreadyRecording(String),
+    NoActiveRecording(String),
+    ParticipantNotFound(String),
+    RecordingNotFound(String),
+    IoError(String),
+    TrackError(String),
+    InvalidChunkData(String),
+    InvalidConfig(String),
+    InvalidMedia(String),
+    DecodeError(String),
+}
+
+impl serde::Serialize for CommandError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        let error_message = self.0.to_string();
+        let error_kind = match &self.0 {
+            RecordingError::AlreadyRecording => RecordingErrorKind::AlreadyRecording(error_message),
+            RecordingError::NoActiveRecording => {
+                RecordingErrorKind::NoActiveRecording(error_message)
+            }
+            RecordingError::ParticipantNotFound(_) => {
+                RecordingErrorKind::ParticipantNotFound(error_message)
+            }
+            RecordingError::RecordingNotFound(_) => {
+                RecordingErrorKind::RecordingNotFound(error_message)
+            }
+            RecordingError::IoError(_) => RecordingErrorKind::IoError(error_message),
+            RecordingError::TrackError(_) => RecordingErrorKind::TrackError(error_message),
+            RecordingError::InvalidChunkData => RecordingErrorKind::InvalidChunkData(error_message),
+            RecordingError::InvalidConfig(_) => RecordingErrorKind::InvalidConfig(error_message),
+            RecordingError::InvalidMedia(_) => RecordingErrorKind::InvalidMedia(error_message),
+            RecordingError::DecodeError(_) => RecordingErrorKind::DecodeError(error_message),
+        };
+        error_kind.serialize(serializer)
+    }
+}
 
 /// Global recording manager state
 pub struct RecordingState {
@@ -16,6 +94,54 @@ impl RecordingState {
             manager: Arc::new(RecordingManager::new()),
         }
     }
+
+    /// Forward live meter events of the recording threads to the frontend
+    pub fn forward_meter_events(&self, app: AppHandle) {
+        self.manager.set_meter_listener(Arc::new(move |event| {
+            let result = match event {
+                MeterEvent::Level(level) => app.emit(AUDIO_LEVEL_EVENT, level),
+                MeterEvent::Warning(warning) => {
+                    log::warn!("Track warning: {:?}", warning);
+                    app.emit(TRACK_WARNING_EVENT, warning)
+                }
+            };
+            if let Err(e) = result {
+                log::error!("Failed to emit meter event: {}", e);
+            }
+        }));
+    }
+}
+
+/// Folder every recording is saved in, `Okarin` under the user's audio folder
+pub fn recordings_root() -> Result<PathBuf, RecordingError> {
+    // Utiliser dirs pour une meilleure compatibilité cross-platform
+    let base_dir = dirs::audio_dir().or_else(dirs::home_dir).ok_or_else(|| {
+        RecordingError::InvalidConfig("Could not determine home or audio directory".to_string())
+    })?;
+
+    let recordings_root = base_dir.join("Okarin");
+    std::fs::create_dir_all(&recordings_root)?;
+    Ok(recordings_root)
+}
+
+/// Directory of a recording of the library, looked up by ID
+fn find_recording_dir(
+    recordings_root: &Path,
+    recording_id: &str,
+) -> Result<PathBuf, RecordingError> {
+    Ok(library::find_recording(recordings_root, recording_id)?.directory)
+}
+
+/// Run CPU-heavy post-processing off the async runtime
+async fn run_blocking<T, F>(task: F) -> Result<T, CommandError>
+where
+    F: FnOnce() -> Result<T, RecordingError> + Send + 'static,
+    T: Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| RecordingError::TrackError(format!("Background task failed: {}", e)))?;
+    Ok(result?)
 }
 
 #[tauri::command]
@@ -28,16 +154,19 @@ pub async fn start_recording(
     video_width: Option<u32>,
     video_height: Option<u32>,
     video_fps: Option<u32>,
-) -> Result<String, RecordingError> {
+    silence_warning_seconds: Option<u32>,
+    reject_format_mismatch: Option<bool>,
+) -> Result<String, CommandError> {
     // Validation des entrées
     if room_id.trim().is_empty() {
-        return Err(RecordingError::InvalidConfig("room_id cannot be empty".into()));
+        return Err(RecordingError::InvalidConfig("room_id cannot be empty".into()).into());
     }
     if !output_dir.exists() {
         return Err(RecordingError::InvalidConfig(format!(
             "output directory does not exist: {:?}",
             output_dir
-        )));
+        ))
+        .into());
     }
 
     let config = RecordingConfig {
@@ -48,26 +177,53 @@ pub async fn start_recording(
         video_width: video_width.unwrap_or(1920),
         video_height: video_height.unwrap_or(1080),
         video_fps: video_fps.unwrap_or(30),
+        silence_warning_seconds: silence_warning_seconds.unwrap_or(10),
+        reject_format_mismatch: reject_format_mismatch.unwrap_or(false),
     };
 
-    state.manager.start_recording(config)
+    Ok(state.manager.start_recording(config)?)
 }
 
 #[tauri::command]
 pub async fn stop_recording(
+    app: AppHandle,
     state: State<'_, RecordingState>,
-) -> Result<RecordingMetadata, RecordingError> {
-    state.manager.stop_recording()
+) -> Result<RecordingMetadata, CommandError> {
+    // The metadata is saved by the time the manager returns it
+    let metadata = state.manager.stop_recording()?;
+
+    // Post-stop analysis decodes every track, minutes on a long session: the
+    // stop returns right away and the results follow as an event
+    let stopped = metadata.clone();
+    tokio::task::spawn_blocking(move || {
+        let analyzed = analysis::run_post_stop_analysis(stopped);
+        if let Err(e) = app.emit(ANALYSIS_COMPLETE_EVENT, analyzed) {
+            log::error!("Failed to emit analysis results: {}", e);
+        }
+    });
+    Ok(metadata)
 }
 
 #[tauri::command]
-pub async fn pause_recording(state: State<'_, RecordingState>) -> Result<(), RecordingError> {
-    state.manager.pause_recording()
+pub async fn pause_recording(state: State<'_, RecordingState>) -> Result<(), CommandError> {
+    Ok(state.manager.pause_recording()?)
 }
 
 #[tauri::command]
-pub async fn resume_recording(state: State<'_, RecordingState>) -> Result<(), RecordingError> {
-    state.manager.resume_recording()
+pub async fn resume_recording(state: State<'_, RecordingState>) -> Result<(), CommandError> {
+    Ok(state.manager.resume_recording()?)
+}
+
+#[tauri::command]
+pub async fn add_marker(
+    state: State<'_, RecordingState>,
+    label: String,
+    kind: Option<MarkerKind>,
+) -> Result<Marker, CommandError> {
+    if label.trim().is_empty() {
+        return Err(RecordingError::InvalidConfig("marker label cannot be empty".into()).into());
+    }
+    Ok(state.manager.add_marker(label, kind.unwrap_or_default())?)
 }
 
 #[tauri::command]
@@ -77,17 +233,18 @@ pub async fn add_participant_track(
     participant_name: String,
     record_audio: bool,
     record_video: bool,
-) -> Result<(), RecordingError> {
+) -> Result<(), CommandError> {
     // Validation
     if participant_id.trim().is_empty() {
-        return Err(RecordingError::InvalidConfig(
-            "participant_id cannot be empty".into(),
-        ));
+        return Err(RecordingError::InvalidConfig("participant_id cannot be empty".into()).into());
     }
 
-    state
-        .manager
-        .add_participant(participant_id, participant_name, record_audio, record_video)
+    Ok(state.manager.add_participant(
+        participant_id,
+        participant_name,
+        record_audio,
+        record_video,
+    )?)
 }
 
 #[tauri::command]
@@ -95,11 +252,11 @@ pub async fn add_audio_chunk(
     state: State<'_, RecordingState>,
     participant_id: String,
     chunk: Vec<u8>,
-) -> Result<(), RecordingError> {
+) -> Result<(), CommandError> {
     if chunk.is_empty() {
-        return Err(RecordingError::InvalidChunkData);
+        return Err(RecordingError::InvalidChunkData.into());
     }
-    state.manager.add_audio_chunk(&participant_id, chunk)
+    Ok(state.manager.add_audio_chunk(&participant_id, chunk)?)
 }
 
 #[tauri::command]
@@ -107,30 +264,418 @@ pub async fn add_video_chunk(
     state: State<'_, RecordingState>,
     participant_id: String,
     chunk: Vec<u8>,
-) -> Result<(), RecordingError> {
+) -> Result<(), CommandError> {
     if chunk.is_empty() {
-        return Err(RecordingError::InvalidChunkData);
+        return Err(RecordingError::InvalidChunkData.into());
     }
-    state.manager.add_video_chunk(&participant_id, chunk)
+    Ok(state.manager.add_video_chunk(&participant_id, chunk)?)
 }
 
 #[tauri::command]
 pub async fn get_recording_status(
     state: State<'_, RecordingState>,
-) -> Result<RecordingStatus, RecordingError> {
+) -> Result<RecordingStatus, CommandError> {
     Ok(state.manager.get_status())
 }
 
 #[tauri::command]
 pub async fn get_recording_metadata(
     state: State<'_, RecordingState>,
-) -> Result<Option<RecordingMetadata>, RecordingError> {
+) -> Result<Option<RecordingMetadata>, CommandError> {
     Ok(state.manager.get_metadata())
 }
 
 #[tauri::command]
 pub async fn get_recording_id(
     state: State<'_, RecordingState>,
-) -> Result<Option<String>, RecordingError> {
+) -> Result<Option<String>, CommandError> {
     Ok(state.manager.get_recording_id())
 }
+
+#[tauri::command]
+pub async fn get_loudness_report(recording_dir: PathBuf) -> Result<LoudnessReport, CommandError> {
+    run_blocking(move || {
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        if let Some(report) = metadata.loudness {
+            return Ok(report);
+        }
+
+        // Recordings made before the analysis pass existed are measured on demand
+        let report = loudness::analyze_recording(&metadata)?;
+        metadata.loudness = Some(report.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(report)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_normalized_tracks(
+    recording_dir: PathBuf,
+    target_lufs: Option<f64>,
+    true_peak_ceiling_dbtp: Option<f64>,
+) -> Result<NormalizationExport, CommandError> {
+    let defaults = NormalizationOptions::default();
+    let options = NormalizationOptions {
+        target_lufs: target_lufs.unwrap_or(defaults.target_lufs),
+        true_peak_ceiling_dbtp: true_peak_ceiling_dbtp.unwrap_or(defaults.true_peak_ceiling_dbtp),
+    };
+    if options.true_peak_ceiling_dbtp > 0.0 {
+        return Err(RecordingError::InvalidConfig(
+            "true peak ceiling must be at or below 0 dBTP".into(),
+        )
+        .into());
+    }
+
+    run_blocking(move || {
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        let export = normalize::normalize_recording(&metadata, &options)?;
+        metadata.normalization = Some(export.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(export)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_waveform(
+    recording_dir: PathBuf,
+    participant_id: String,
+    zoom: Option<usize>,
+) -> Result<WaveformData, CommandError> {
+    run_blocking(move || {
+        let metadata = storage::load_metadata(&recording_dir)?;
+        waveform::load_waveform(&metadata, &participant_id, zoom.unwrap_or(0))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_activity_timeline(
+    recording_dir: PathBuf,
+) -> Result<ActivityTimeline, CommandError> {
+    run_blocking(move || {
+        if let Some(timeline) = storage::load_activity(&recording_dir)? {
+            return Ok(timeline);
+        }
+
+        let metadata = storage::load_metadata(&recording_dir)?;
+        let timeline = activity::analyze_recording(&metadata)?;
+        storage::write_activity(&recording_dir, &timeline)?;
+        Ok(timeline)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_bleed_report(recording_dir: PathBuf) -> Result<BleedReport, CommandError> {
+    run_blocking(move || {
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        if let Some(report) = metadata.bleed {
+            return Ok(report);
+        }
+
+        let report = bleed::analyze_recording(&metadata)?;
+        metadata.bleed = Some(report.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(report)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_mixdown(
+    recording_dir: PathBuf,
+    options: Option<MixdownOptions>,
+) -> Result<PathBuf, CommandError> {
+    let options = options.unwrap_or_default();
+    if let Some((participant_id, _)) = options
+        .tracks
+        .iter()
+        .find(|(_, settings)| !(-1.0..=1.0).contains(&settings.pan))
+    {
+        return Err(RecordingError::InvalidConfig(format!(
+            "pan of participant {} must be between -1 and 1",
+            participant_id
+        ))
+        .into());
+    }
+
+    run_blocking(move || {
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        let mix_file = mixdown::mixdown_recording(&metadata, &options)?;
+        metadata.mix_file = Some(mix_file.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(mix_file)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_trimmed_tracks(
+    recording_dir: PathBuf,
+    padding_seconds: Option<f64>,
+) -> Result<TrimExport, CommandError> {
+    let padding_seconds = padding_seconds.unwrap_or(trim::DEFAULT_TRIM_PADDING_SECONDS);
+    if !padding_seconds.is_finite() || padding_seconds < 0.0 {
+        return Err(RecordingError::InvalidConfig(
+            "trim padding must be a positive number of seconds".to_string(),
+        )
+        .into());
+    }
+
+    run_blocking(move || {
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        let timeline = match storage::load_activity(&recording_dir)? {
+            Some(timeline) => timeline,
+            None => {
+                let timeline = activity::analyze_recording(&metadata)?;
+                storage::write_activity(&recording_dir, &timeline)?;
+                timeline
+            }
+        };
+
+        let export = trim::trim_recording(&metadata, &timeline, padding_seconds)?;
+        metadata.trim = Some(export.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(export)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_cleaned_tracks(
+    recording_dir: PathBuf,
+    options: Option<CleanupOptions>,
+) -> Result<CleanupExport, CommandError> {
+    let options = options.unwrap_or_default();
+    for (participant_id, settings) in &options.tracks {
+        let valid = (0.0..=1000.0).contains(&settings.high_pass_hz)
+            && settings.gate_ratio >= 1.0
+            && settings.gate_range_db >= 0.0;
+        if !valid {
+            return Err(RecordingError::InvalidConfig(format!(
+                "invalid cleanup settings for participant {}",
+                participant_id
+            ))
+            .into());
+        }
+    }
+
+    run_blocking(move || {
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        let export = cleanup::cleanup_recording(&metadata, &options)?;
+        metadata.cleanup = Some(export.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(export)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn sync_external_file(
+    recordings_root: PathBuf,
+    recording_id: String,
+    participant_id: String,
+    path: PathBuf,
+) -> Result<ExternalAlignment, CommandError> {
+    run_blocking(move || {
+        let recording_dir = find_recording_dir(&recordings_root, &recording_id)?;
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        let alignment = sync::sync_external_file(&metadata, &participant_id, &path)?;
+        metadata.set_external_alignment(alignment.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(alignment)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn align_external_file(
+    recordings_root: PathBuf,
+    recording_id: String,
+    participant_id: String,
+    path: PathBuf,
+) -> Result<ExternalAlignment, CommandError> {
+    run_blocking(move || {
+        let recording_dir = find_recording_dir(&recordings_root, &recording_id)?;
+        let mut metadata = storage::load_metadata(&recording_dir)?;
+        let alignment = align::align_external_file(&metadata, &participant_id, &path)?;
+        metadata.set_external_alignment(alignment.clone());
+        storage::write_metadata(&recording_dir, &metadata)?;
+        Ok(alignment)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_chapters(recording_dir: PathBuf) -> Result<ChapterFiles, CommandError> {
+    run_blocking(move || {
+        let metadata = storage::load_metadata(&recording_dir)?;
+        chapters::export_chapters(&metadata)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_editor_projects(recording_dir: PathBuf) -> Result<ProjectFiles, CommandError> {
+    run_blocking(move || {
+        let metadata = storage::load_metadata(&recording_dir)?;
+        project::export_projects(&metadata)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_timeline(recording_dir: PathBuf) -> Result<TimelineFiles, CommandError> {
+    run_blocking(move || {
+        let metadata = storage::load_metadata(&recording_dir)?;
+        interchange::export_timelines(&metadata)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn repair_recording(recording_dir: PathBuf) -> Result<Vec<RepairReport>, CommandError> {
+    run_blocking(move || repair::repair_recording(&recording_dir)).await
+}
+
+#[tauri::command]
+pub async fn probe_recording_file(path: PathBuf) -> Result<ProbeReport, CommandError> {
+    run_blocking(move || probe::probe_recording_file(&path)).await
+}
+
+#[tauri::command]
+pub async fn export_ogg_tracks(
+    recording_dir: PathBuf,
+) -> Result<HashMap<String, PathBuf>, CommandError> {
+    run_blocking(move || {
+        let metadata = storage::load_metadata(&recording_dir)?;
+        ogg::export_ogg_tracks(&metadata)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn export_av_files(
+    recording_dir: PathBuf,
+) -> Result<HashMap<String, PathBuf>, CommandError> {
+    run_blocking(move || {
+        let metadata = storage::load_metadata(&recording_dir)?;
+        avmux::mux_recording(&metadata)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_episode_metadata(
+    recording_dir: PathBuf,
+) -> Result<Option<EpisodeMetadata>, CommandError> {
+    run_blocking(move || storage::load_episode(&recording_dir)).await
+}
+
+#[tauri::command]
+pub async fn set_episode_metadata(
+    recording_dir: PathBuf,
+    episode: EpisodeMetadata,
+) -> Result<EpisodeMetadata, CommandError> {
+    run_blocking(move || {
+        let mut episode = episode;
+        // Editing the titles does not forget the last published file
+        if episode.export.is_none() {
+            episode.export = storage::load_episode(&recording_dir)?.and_then(|e| e.export);
+        }
+        storage::write_episode(&recording_dir, &episode)?;
+        Ok(episode)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn publish_episode(
+    recording_dir: PathBuf,
+    options: Option<PublishOptions>,
+) -> Result<PublishExport, CommandError> {
+    let options = options.unwrap_or_default();
+    run_blocking(move || {
+        let metadata = storage::load_metadata(&recording_dir)?;
+        let mut episode = storage::load_episode(&recording_dir)?.ok_or_else(|| {
+            RecordingError::InvalidConfig("episode metadata has not been set".to_string())
+        })?;
+        let export = publish::publish_episode(&metadata, &episode, &options)?;
+        episode.export = Some(export.clone());
+        storage::write_episode(&recording_dir, &episode)?;
+        Ok(export)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_feed_settings(
+    recordings_root: PathBuf,
+) -> Result<Option<FeedSettings>, CommandError> {
+    run_blocking(move || storage::load_feed_settings(&recordings_root)).await
+}
+
+#[tauri::command]
+pub async fn publish_feed(
+    recordings_root: PathBuf,
+    settings: Option<FeedSettings>,
+) -> Result<FeedExport, CommandError> {
+    run_blocking(move || {
+        // New settings are kept for the next update of the feed
+        let settings = match settings {
+            Some(settings) => {
+                storage::write_feed_settings(&recordings_root, &settings)?;
+                settings
+            }
+            None => storage::load_feed_settings(&recordings_root)?.ok_or_else(|| {
+                RecordingError::InvalidConfig("feed settings have not been set".to_string())
+            })?,
+        };
+        feed::publish_feed(&recordings_root, &settings)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn list_recordings(recordings_root: PathBuf) -> Result<Vec<LibraryEntry>, CommandError> {
+    run_blocking(move || library::list_recordings(&recordings_root)).await
+}
+
+#[tauri::command]
+pub async fn get_recording(
+    recordings_root: PathBuf,
+    id: String,
+) -> Result<RecordingMetadata, CommandError> {
+    run_blocking(move || library::get_recording(&recordings_root, &id)).await
+}
+
+#[tauri::command]
+pub async fn search_recordings(
+    recordings_root: PathBuf,
+    query: LibraryQuery,
+) -> Result<Vec<LibraryEntry>, CommandError> {
+    run_blocking(move || library::search_recordings(&recordings_root, &query)).await
+}
+
+#[tauri::command]
+pub async fn delete_recording(
+    state: State<'_, RecordingState>,
+    recordings_root: PathBuf,
+    id: String,
+) -> Result<LibraryEntry, CommandError> {
+    let in_progress = !matches!(
+        state.manager.get_status(),
+        RecordingStatus::Idle | RecordingStatus::Stopped
+    );
+    if in_progress && state.manager.get_recording_id().as_deref() == Some(id.as_str()) {
+        return Err(RecordingError::InvalidConfig(format!(
+            "recording {} is still in progress",
+            id
+        ))
+        .into());
+    }
+
+    run_blocking(move || library::delete_recording(&recordings_root, &id)).await
+}
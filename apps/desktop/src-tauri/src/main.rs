@@ -2,10 +2,10 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod commands;
-mod recording;
 
 use commands::RecordingState;
 use std::path::PathBuf;
+use tauri::Manager;
 
 #[tauri::command]
 fn generate_room_id() -> String {
@@ -28,17 +28,7 @@ fn get_app_name() -> String {
 
 #[tauri::command]
 fn get_recording_directory() -> Result<PathBuf, String> {
-    // Utiliser dirs pour une meilleure compatibilité cross-platform
-    let base_dir = dirs::audio_dir()
-        .or_else(dirs::home_dir)
-        .ok_or_else(|| "Could not determine home or audio directory".to_string())?;
-
-    let recording_dir = base_dir.join("Okarin");
-
-    std::fs::create_dir_all(&recording_dir)
-        .map_err(|e| format!("Failed to create recording directory: {}", e))?;
-
-    Ok(recording_dir)
+    commands::recordings_root().map_err(|e| e.to_string())
 }
 
 fn main() {
@@ -51,6 +41,11 @@ fn main() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_process::init())
         .manage(RecordingState::new())
+        .setup(|app| {
+            app.state::<RecordingState>()
+                .forward_meter_events(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             generate_room_id,
             get_app_version,
@@ -60,13 +55,40 @@ fn main() {
             commands::stop_recording,
             commands::pause_recording,
             commands::resume_recording,
+            commands::add_marker,
             commands::add_participant_track,
             commands::add_audio_chunk,
             commands::add_video_chunk,
             commands::get_recording_status,
             commands::get_recording_metadata,
             commands::get_recording_id,
+            commands::get_loudness_report,
+            commands::export_normalized_tracks,
+            commands::get_waveform,
+            commands::get_activity_timeline,
+            commands::get_bleed_report,
+            commands::export_mixdown,
+            commands::export_trimmed_tracks,
+            commands::export_cleaned_tracks,
+            commands::sync_external_file,
+            commands::align_external_file,
+            commands::export_chapters,
+            commands::export_editor_projects,
+            commands::export_timeline,
+            commands::repair_recording,
+            commands::probe_recording_file,
+            commands::export_ogg_tracks,
+            commands::export_av_files,
+            commands::get_episode_metadata,
+            commands::set_episode_metadata,
+            commands::publish_episode,
+            commands::get_feed_settings,
+            commands::publish_feed,
+            commands::list_recordings,
+            commands::get_recording,
+            commands::search_recordings,
+            commands::delete_recording,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
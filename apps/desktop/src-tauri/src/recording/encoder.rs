@@ -1,3 +0,0 @@
-// Note: Les encodeurs ont été supprimés car MediaRecorder du navigateur
-// produit déjà des chunks WebM encodés (Opus pour audio, VP9/H264 pour vidéo).
-// Le traitement se fait directement dans track.rs sans copie inutile.
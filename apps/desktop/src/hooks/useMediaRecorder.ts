@@ -3,6 +3,11 @@ import { addAudioChunk, addVideoChunk } from '../lib/recording';
 import { getVideoQualityPreset } from '../lib/videoQualityPresets';
 import { useSettingsStore } from '../stores';
 
+// Audio goes out in 100ms chunks: the live level meter runs on them and
+// reports about 10 levels per second. Video keeps 1 second chunks.
+const AUDIO_TIMESLICE_MS = 100;
+const VIDEO_TIMESLICE_MS = 1000;
+
 interface MediaRecorderState {
   audioRecorder: MediaRecorder | null;
   videoRecorder: MediaRecorder | null;
@@ -12,6 +17,8 @@ interface MediaRecorderState {
 interface UseMediaRecorderReturn {
   startRecording: (participantId: string, stream: MediaStream) => Promise<void>;
   stopRecording: (participantId: string) => Promise<void>;
+  pauseRecording: () => void;
+  resumeRecording: () => void;
 }
 
 /**
@@ -99,7 +106,6 @@ export function useMediaRecorder(): UseMediaRecorderReturn {
               const arrayBuffer = await event.data.arrayBuffer();
               const uint8Array = new Uint8Array(arrayBuffer);
               await addAudioChunk(participantId, uint8Array);
-              console.log(`Sent audio chunk for ${participantId}: ${event.data.size} bytes`);
             } catch (error) {
               console.error(`Failed to send audio chunk for ${participantId}:`, error);
             }
@@ -110,8 +116,8 @@ export function useMediaRecorder(): UseMediaRecorderReturn {
           console.error(`Audio recorder error for ${participantId}:`, event);
         };
 
-        // Start recording with timeslice for streaming chunks (1 second chunks)
-        audioRecorder.start(1000);
+        // Start recording with timeslice for streaming chunks
+        audioRecorder.start(AUDIO_TIMESLICE_MS);
         recorderState.audioRecorder = audioRecorder;
 
         console.log(`Audio recorder started for ${participantId}`);
@@ -156,8 +162,8 @@ export function useMediaRecorder(): UseMediaRecorderReturn {
           console.error(`Video recorder error for ${participantId}:`, event);
         };
 
-        // Start recording with timeslice for streaming chunks (1 second chunks)
-        videoRecorder.start(1000);
+        // Start recording with timeslice for streaming chunks
+        videoRecorder.start(VIDEO_TIMESLICE_MS);
         recorderState.videoRecorder = videoRecorder;
 
         console.log(`Video recorder started for ${participantId}`);
@@ -209,8 +215,34 @@ export function useMediaRecorder(): UseMediaRecorderReturn {
     console.log(`Recording stopped for ${participantId}`);
   }, []);
 
+  // Paused time is left out of the recording: the recorders stop producing
+  // data and their timestamps skip the pause, matching the session timeline
+  const pauseRecording = useCallback(() => {
+    for (const { audioRecorder, videoRecorder } of recordersRef.current.values()) {
+      if (audioRecorder?.state === 'recording') {
+        audioRecorder.pause();
+      }
+      if (videoRecorder?.state === 'recording') {
+        videoRecorder.pause();
+      }
+    }
+  }, []);
+
+  const resumeRecording = useCallback(() => {
+    for (const { audioRecorder, videoRecorder } of recordersRef.current.values()) {
+      if (audioRecorder?.state === 'paused') {
+        audioRecorder.resume();
+      }
+      if (videoRecorder?.state === 'paused') {
+        videoRecorder.resume();
+      }
+    }
+  }, []);
+
   return {
     startRecording,
     stopRecording,
+    pauseRecording,
+    resumeRecording,
   };
 }